@@ -1,175 +1,1016 @@
-use crate::scanner::Token;
-
-use super::parser::*;
-use hlvm::{
-    hir::*,
-    lir::{HlvmInstruction, HlvmValue},
-};
-
-fn op_token_to_instruction(op: Token) -> HlvmHirInstruction {
-    match op {
-        Token::OpAdd => HlvmHirInstruction::Add,
-        Token::OpSub => HlvmHirInstruction::Subtract,
-        Token::OpMul => HlvmHirInstruction::Multiply,
-        Token::OpDiv => HlvmHirInstruction::Divide,
-        Token::OpMod => todo!("Modulo not yet implemented"),
-        Token::OpEq => HlvmHirInstruction::Equal,
-        Token::OpBangEq => HlvmHirInstruction::NotEqual,
-        Token::OpLess => HlvmHirInstruction::LessThan,
-        Token::OpLessEq => HlvmHirInstruction::LessThanOrEqual,
-        Token::OpMore => HlvmHirInstruction::GreaterThan,
-        Token::OpMoreEq => HlvmHirInstruction::GreaterThanOrEqual,
-        _ => panic!(),
-    }
-}
-
-fn compile_value(value: NodeValue) -> Vec<HlvmHirInstruction> {
-    let mut instructions = vec![];
-
-    match value {
-        NodeValue::StringValue(string) => {
-            instructions.push(HlvmHirInstruction::Push(HlvmValue::String(string)))
-        }
-        NodeValue::NumberValue(number) => {
-            instructions.push(HlvmHirInstruction::Push(HlvmValue::Number(number)))
-        }
-        NodeValue::BoolValue(bool) => {
-            instructions.push(HlvmHirInstruction::Push(HlvmValue::Bool(bool)))
-        }
-        NodeValue::IdentifierValue(iden) => {
-            instructions.push(HlvmHirInstruction::Get(iden));
-        }
-        NodeValue::FunctionCall(function, mut arguments) => {
-            arguments.reverse();
-            let mut arguemnts_hir = vec![];
-
-            for argument in arguments {
-                arguemnts_hir.append(&mut compile_value(argument));
-            }
-
-            instructions.append(&mut arguemnts_hir);
-            instructions.push(HlvmHirInstruction::Get(function));
-            instructions.push(HlvmHirInstruction::Call)
-        }
-        NodeValue::PrimitiveFunctionCall(index, mut arguments) => {
-            arguments.reverse();
-            let len = arguments.len();
-
-            for argument in arguments {
-                instructions.append(&mut compile_value(argument));
-            }
-
-            instructions.push(HlvmHirInstruction::CallPrimitive(index, len));
-        }
-        NodeValue::Binary(left, right, op) => {
-            instructions.append(&mut compile_value(*left));
-            instructions.append(&mut compile_value(*right));
-            instructions.push(op_token_to_instruction(op));
-        }
-        NodeValue::Unary(value, modifier) => {
-            instructions.append(&mut compile_value(*value));
-            
-            match modifier {
-                Unary::Negate => instructions.push(HlvmHirInstruction::Negate),
-                Unary::Not => instructions.push(HlvmHirInstruction::Not),
-                Unary::Typeof => instructions.push(HlvmHirInstruction::Typeof)
-            }
-        }
-        _ => panic!(),
-    }
-
-    instructions
-}
-
-pub fn compile(ast: Vec<Node>) -> Vec<HlvmHirInstruction> {
-    let mut instructions = vec![];
-
-    for node in ast {
-        match node.inner {
-            NodeValue::VariableDecleration(name, value, ..) => {
-                instructions.append(&mut compile_value(*value));
-                instructions.push(HlvmHirInstruction::SetLocal(name));
-            }
-            NodeValue::VariableAssignment(name, value) => {
-                instructions.append(&mut compile_value(*value));
-                instructions.push(HlvmHirInstruction::SetLocal(name));
-            }
-            NodeValue::If(ontrue, onelseif, onfalse) => {
-                let ontrue_body = compile(ontrue.1);
-                let mut onelseif_hir: Vec<(Vec<HlvmHirInstruction>, Vec<HlvmHirInstruction>)> =
-                    Vec::with_capacity(onelseif.len());
-
-                let onelseif_isempty = onelseif.is_empty();
-
-                for elseif in onelseif {
-                    onelseif_hir.push((compile_value(*elseif.0), compile(elseif.1)));
-                }
-
-                instructions.append(&mut compile_value(*ontrue.0));
-                instructions.push(HlvmHirInstruction::IfStatement {
-                    ontrue: ontrue_body,
-                    onelseif: if onelseif_isempty {
-                        None
-                    } else {
-                        Some(onelseif_hir)
-                    },
-                    onfalse: compile(onfalse.unwrap_or(vec![])),
-                })
-            }
-            NodeValue::WhileStatement(condition, body) => {
-                instructions.push(HlvmHirInstruction::WhileStatement(
-                    compile_value(*condition),
-                    compile(body),
-                ));
-            }
-            NodeValue::Return(value) => {
-                instructions.append(&mut compile_value(*value));
-                instructions.push(HlvmHirInstruction::ReturnValue);
-            }
-            NodeValue::FunctionDecleration(name, body, params, ..) => {
-                let mut parameters = vec![];
-
-                for parameter in params {
-                    parameters.push(parameter.name);
-                }
-
-                let function = HlvmValue::Function(from_hir(compile(body)), parameters, None);
-
-                instructions.push(HlvmHirInstruction::Push(function));
-                instructions.push(HlvmHirInstruction::SetLocal(name));
-            }
-            NodeValue::StructInit(name, arguments) => {
-                for argument in arguments {
-                    instructions.append(&mut compile_value(argument.1.inner));
-                }
-
-                instructions.push(HlvmHirInstruction::Get(name));
-                instructions.push(HlvmHirInstruction::Instantiate);
-            }
-            // NodeValue::TypeDecleration(name, functions, variables) => {
-            //     let mut attributes = variables;
-
-            //     for function in functions {
-            //         if let NodeValue::FunctionDecleration(name, ..) = function {
-            //             
-            //         }
-            //     }
-            // }
-
-            NodeValue::StringValue(..)
-            | NodeValue::NumberValue(..)
-            | NodeValue::BoolValue(..)
-            | NodeValue::IdentifierValue(..)
-            | NodeValue::FunctionCall(..)
-            | NodeValue::PrimitiveFunctionCall(..)
-            | NodeValue::Binary(..)
-            | NodeValue::Unary(..) => {
-                instructions.append(&mut compile_value(node.inner));
-            }
-
-            _ => todo!(),
-        }
-    }
-
-    instructions
-}
+use crate::scanner::Token;
+
+use super::parser::*;
+use crate::{
+    error::{Diagnostic, ErrorCode, Span},
+    scope::source_name,
+    symbols::SymbolTable,
+};
+use hlvm::{
+    cfg,
+    hir::*,
+    lir::{Conversion, FunctionId, HlvmValue},
+};
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// The local a struct is stored in while it's being destructured.
+/// It can't collide with user variables, since it isn't a valid identifier.
+const DESTRUCTURE_TEMPORARY: &str = "<destructure>";
+
+/// The local a function's returned value is stored in while its deferred blocks run.
+const RETURN_TEMPORARY: &str = "<return>";
+
+/// The local that's set once the `index`th `defer` of a function has been reached, so
+/// returning only runs the deferred blocks that were.
+fn defer_flag(index: usize) -> String {
+    format!("<defer {}>", index)
+}
+
+/// State shared by everything compiled into one program: the prelude, the program itself and
+/// the files it imports. Ids only depend on the program, so building it twice gives the same
+/// instructions however many programs were compiled before it.
+#[derive(Default)]
+pub struct Context {
+    /// The id given to the next function that's compiled.
+    next_function_id: FunctionId,
+
+    /// The packages the program depends on, by name, with the file `use "name"` imports.
+    pub packages: BTreeMap<String, PathBuf>,
+
+    /// The line of the first `asm` block in the code being compiled, which has to be
+    /// verified once it's done.
+    asm_line: Option<usize>,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    fn function_id(&mut self) -> FunctionId {
+        let id = self.next_function_id;
+        self.next_function_id += 1;
+        id
+    }
+}
+
+fn op_token_to_instruction(op: Token<'_>) -> HlvmHirInstruction {
+    match op {
+        Token::OpAdd => HlvmHirInstruction::Add,
+        Token::OpSub => HlvmHirInstruction::Subtract,
+        Token::OpMul => HlvmHirInstruction::Multiply,
+        Token::OpDiv => HlvmHirInstruction::Divide,
+        Token::OpMod => HlvmHirInstruction::Modulo,
+        Token::OpPow => HlvmHirInstruction::Power,
+        Token::OpLeftShift => HlvmHirInstruction::ShiftLeft,
+        Token::OpRightShift => HlvmHirInstruction::ShiftRight,
+        Token::OpEq => HlvmHirInstruction::Equal,
+        Token::OpBangEq => HlvmHirInstruction::NotEqual,
+        Token::OpLess => HlvmHirInstruction::LessThan,
+        Token::OpLessEq => HlvmHirInstruction::LessThanOrEqual,
+        Token::OpMore => HlvmHirInstruction::GreaterThan,
+        Token::OpMoreEq => HlvmHirInstruction::GreaterThanOrEqual,
+        Token::KwAnd => HlvmHirInstruction::And,
+        Token::KwOr => HlvmHirInstruction::Or,
+        _ => unreachable!("binary expressions are only built from operators"),
+    }
+}
+
+pub fn compile_value(value: NodeValue) -> Vec<HlvmHirInstruction> {
+    let mut instructions = vec![];
+
+    match value {
+        NodeValue::StringValue(string) => {
+            instructions.push(HlvmHirInstruction::Push(HlvmValue::String(string)))
+        }
+        NodeValue::NumberValue(number) => {
+            instructions.push(HlvmHirInstruction::Push(HlvmValue::Number(number)))
+        }
+        NodeValue::BoolValue(bool) => {
+            instructions.push(HlvmHirInstruction::Push(HlvmValue::Bool(bool)))
+        }
+        NodeValue::NoneValue => instructions.push(HlvmHirInstruction::Push(HlvmValue::None)),
+        NodeValue::IdentifierValue(iden) => {
+            instructions.push(HlvmHirInstruction::Get(iden));
+        }
+        NodeValue::FunctionCall(function, mut arguments) => {
+            let count = arguments.len();
+            arguments.reverse();
+            let mut arguemnts_hir = vec![];
+
+            for argument in arguments {
+                arguemnts_hir.append(&mut compile_value(argument));
+            }
+
+            instructions.append(&mut arguemnts_hir);
+            instructions.push(HlvmHirInstruction::Get(function));
+            instructions.push(HlvmHirInstruction::Call(count))
+        }
+        NodeValue::PrimitiveFunctionCall(index, mut arguments) => {
+            arguments.reverse();
+            let len = arguments.len();
+
+            for argument in arguments {
+                instructions.append(&mut compile_value(argument));
+            }
+
+            instructions.push(HlvmHirInstruction::CallPrimitive(index, len));
+        }
+        NodeValue::Binary(left, right, op) => {
+            instructions.append(&mut compile_value(*left));
+            instructions.append(&mut compile_value(*right));
+            instructions.push(op_token_to_instruction(op));
+        }
+        NodeValue::Conditional(condition, ontrue, onfalse) => {
+            /* Only one branch runs, so exactly one value is left on the stack */
+            instructions.append(&mut compile_value(*condition));
+            instructions.push(HlvmHirInstruction::IfStatement {
+                ontrue: compile_value(*ontrue),
+                onelseif: None,
+                onfalse: compile_value(*onfalse),
+            });
+        }
+        NodeValue::GetAttribute(value, attribute) => {
+            instructions.append(&mut compile_value(*value));
+            instructions.push(HlvmHirInstruction::GetAttribute(attribute));
+        }
+        NodeValue::Call(callee, mut arguments) => {
+            let count = arguments.len();
+            arguments.reverse();
+
+            for argument in arguments {
+                instructions.append(&mut compile_value(argument));
+            }
+
+            instructions.append(&mut compile_value(*callee));
+            instructions.push(HlvmHirInstruction::Call(count))
+        }
+        NodeValue::ArrayValue(elements) => {
+            let count = elements.len();
+
+            for element in elements {
+                instructions.append(&mut compile_value(element));
+            }
+
+            instructions.push(HlvmHirInstruction::BuildArray(count));
+        }
+        NodeValue::Index(value, index) => {
+            instructions.append(&mut compile_value(*value));
+            instructions.append(&mut compile_value(*index));
+            instructions.push(HlvmHirInstruction::Index);
+        }
+        NodeValue::Slice(value, start, end) => {
+            instructions.append(&mut compile_value(*value));
+
+            /* A missing bound is pushed as none */
+            for bound in [start, end] {
+                let bound = bound.map_or(NodeValue::NoneValue, |bound| *bound);
+                instructions.append(&mut compile_value(bound));
+            }
+
+            instructions.push(HlvmHirInstruction::Slice);
+        }
+        NodeValue::Convert(value, datatype) => {
+            instructions.append(&mut compile_value(*value));
+            instructions.push(HlvmHirInstruction::Convert(match datatype {
+                Type::Number => Conversion::Number,
+                Type::String => Conversion::String,
+                Type::Bool => Conversion::Bool,
+                _ => unreachable!("values are only converted to number, string or bool"),
+            }));
+        }
+        NodeValue::StructInit(name, arguments) => {
+            /* The instance stays on the stack while each attribute is set on it */
+            instructions.push(HlvmHirInstruction::Get(name));
+            instructions.push(HlvmHirInstruction::Instantiate);
+
+            for (attribute, argument) in arguments {
+                instructions.append(&mut compile_value(argument.inner));
+                instructions.push(HlvmHirInstruction::SetAttribute(attribute));
+            }
+        }
+        NodeValue::Unary(value, modifier) => {
+            instructions.append(&mut compile_value(*value));
+            
+            match modifier {
+                Unary::Negate => instructions.push(HlvmHirInstruction::Negate),
+                Unary::Not => instructions.push(HlvmHirInstruction::Not),
+                Unary::Typeof => instructions.push(HlvmHirInstruction::Typeof)
+            }
+        }
+        _ => panic!(),
+    }
+
+    instructions
+}
+
+/// The maximum amount of stack slots needed to evaluate `value`.
+fn stack_depth(value: &NodeValue) -> usize {
+    match value {
+        NodeValue::Binary(left, right, _) | NodeValue::Index(left, right) => {
+            stack_depth(left).max(1 + stack_depth(right))
+        }
+        NodeValue::Slice(value, start, end) => {
+            /* A missing bound is pushed as none, which takes a slot */
+            let bound_depth = |bound: &Option<Box<NodeValue>>| {
+                bound.as_deref().map_or(1, stack_depth)
+            };
+            stack_depth(value).max(1 + bound_depth(start)).max(2 + bound_depth(end))
+        }
+        NodeValue::Unary(value, _)
+        | NodeValue::GetAttribute(value, _)
+        | NodeValue::Convert(value, _) => stack_depth(value),
+        NodeValue::Conditional(condition, ontrue, onfalse) => {
+            stack_depth(condition).max(stack_depth(ontrue)).max(stack_depth(onfalse))
+        }
+        NodeValue::FunctionCall(_, arguments) | NodeValue::PrimitiveFunctionCall(_, arguments) => {
+            /* Arguments are pushed in reverse, followed by the function itself */
+            let arguments_depth = arguments
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(pushed, argument)| pushed + stack_depth(argument))
+                .max()
+                .unwrap_or(0);
+
+            arguments_depth.max(arguments.len() + 1)
+        }
+        NodeValue::Call(callee, arguments) => {
+            let arguments_depth = arguments
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(pushed, argument)| pushed + stack_depth(argument))
+                .max()
+                .unwrap_or(0);
+
+            arguments_depth.max(arguments.len() + stack_depth(callee))
+        }
+        NodeValue::ArrayValue(elements) => {
+            /* Each element is evaluated on top of the ones before it */
+            elements
+                .iter()
+                .enumerate()
+                .map(|(pushed, element)| pushed + stack_depth(element))
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        }
+        NodeValue::StructInit(_, arguments) => {
+            /* Every attribute is evaluated on top of the instance */
+            arguments
+                .iter()
+                .map(|(_, argument)| 1 + stack_depth(&argument.inner))
+                .max()
+                .unwrap_or(1)
+        }
+        _ => 1,
+    }
+}
+
+/// Returns the maximum stack depth reached by any statement in `body`, including nested blocks.
+fn collect_frame(body: &[Node]) -> usize {
+    let mut depth = 0;
+
+    for node in body {
+        let node_depth = match &node.inner {
+            NodeValue::VariableDecleration(_, value, ..)
+            | NodeValue::VariableAssignment(_, value) => stack_depth(value),
+            NodeValue::FunctionDecleration(..) => 1,
+            NodeValue::Destructure(pattern, value) => {
+                let names = match pattern {
+                    Pattern::Array(names, _) | Pattern::Struct(names) => names,
+                };
+
+                stack_depth(value).max(names.len() + 1)
+            }
+            NodeValue::If(ontrue, onelseif, onfalse) => {
+                let mut depth = stack_depth(&ontrue.0).max(collect_frame(&ontrue.1));
+
+                for (condition, body) in onelseif {
+                    depth = depth.max(stack_depth(condition)).max(collect_frame(body));
+                }
+
+                depth.max(collect_frame(onfalse.as_deref().unwrap_or(&[])))
+            }
+            NodeValue::WhileStatement(condition, body) => {
+                stack_depth(condition).max(collect_frame(body))
+            }
+            NodeValue::Return(value) => stack_depth(value),
+            NodeValue::AttributeAssignment(object, _, value) => {
+                /* Every object along the way stays on the stack until the value is stored */
+                let mut depth = stack_depth(object);
+                let mut object = &**object;
+
+                while let NodeValue::GetAttribute(inner, _) = object {
+                    depth = depth.max(stack_depth(inner) + 1);
+                    object = inner;
+                }
+
+                depth + stack_depth(value)
+            }
+            NodeValue::Defer(body) => collect_frame(body).max(1),
+            value => stack_depth(value),
+        };
+
+        depth = depth.max(node_depth);
+    }
+
+    depth
+}
+
+/// Computes the amount of locals and the maximum operand stack depth of a function,
+/// so the VM can preallocate both when the function is called.
+fn frame_size(name: &str, body: &[Node], symbols: &SymbolTable) -> (usize, usize) {
+    (symbols.locals_of(name), collect_frame(body))
+}
+
+/// Compiles the body of the function declared as `name` into a function value.
+fn compile_function(
+    name: &str,
+    body: Vec<Node>,
+    params: Vec<Parameter>,
+    symbols: &SymbolTable,
+    context: &mut Context,
+) -> HlvmValue {
+    let parameters = params.into_iter().map(|parameter| parameter.name).collect();
+    let (locals, stack) = frame_size(name, &body, symbols);
+    let body = checking_asm(context, |context| compile_body(body, symbols, context, false, true));
+
+    HlvmValue::Function(
+        from_hir(body),
+        parameters,
+        Some(locals),
+        Some(stack),
+        context.function_id(),
+    )
+}
+
+/// Sets `attribute` of `object` to the value computed by `value`, then stores the updated
+/// object back where it came from, up to the variable it's in.
+fn compile_attribute_assignment(
+    object: NodeValue,
+    attribute: String,
+    mut value: Vec<HlvmHirInstruction>,
+) -> Vec<HlvmHirInstruction> {
+    let mut instructions = compile_value(object.clone());
+    instructions.append(&mut value);
+    instructions.push(HlvmHirInstruction::SetAttribute(attribute));
+
+    match object {
+        NodeValue::IdentifierValue(name) => {
+            instructions.push(HlvmHirInstruction::SetLocal(name));
+            instructions
+        }
+        NodeValue::GetAttribute(inner, name) => {
+            compile_attribute_assignment(*inner, name, instructions)
+        }
+        _ => unreachable!("the parser only allows assigning to attributes of variables"),
+    }
+}
+
+/// Compiles `ast`, whose names have been resolved into `symbols`.
+pub fn compile(
+    ast: Vec<Node>,
+    symbols: &SymbolTable,
+    context: &mut Context,
+) -> Vec<HlvmHirInstruction> {
+    checking_asm(context, |context| compile_body(ast, symbols, context, false, false))
+}
+
+/// Compiles `ast` like `compile`, marking where every statement starts for the source map.
+pub fn compile_mapped(
+    ast: Vec<Node>,
+    symbols: &SymbolTable,
+    context: &mut Context,
+) -> Vec<HlvmHirInstruction> {
+    checking_asm(context, |context| compile_body(ast, symbols, context, true, false))
+}
+
+/// Runs `compile`, then verifies what it compiled if that contains `asm` blocks. Nothing
+/// checks what they do to the stack otherwise, so a broken one is reported at its line
+/// instead of failing once the program runs.
+fn checking_asm(
+    context: &mut Context,
+    compile: impl FnOnce(&mut Context) -> Vec<HlvmHirInstruction>,
+) -> Vec<HlvmHirInstruction> {
+    let enclosing = context.asm_line.take();
+    let instructions = compile(context);
+
+    if let Some(line) = std::mem::replace(&mut context.asm_line, enclosing) {
+        if let Err(err) = cfg::verify(&to_ir(instructions.clone())) {
+            Diagnostic::error(
+                ErrorCode::InvalidAssembly,
+                format!("The asm block leaves the code around it invalid. {}", err),
+            )
+            .with_span(Span::line(line))
+            .emit_and_exit()
+        }
+    }
+
+    instructions
+}
+
+/// Compiles the body of a function or program, running its deferred blocks when it returns
+/// or reaches its end. The deferred blocks are compiled once, into an exit that every
+/// `return` continues with. A `function` returns from there, while a program ends.
+fn compile_body(
+    ast: Vec<Node>,
+    symbols: &SymbolTable,
+    context: &mut Context,
+    mapped: bool,
+    function: bool,
+) -> Vec<HlvmHirInstruction> {
+    let mut defers = vec![];
+    let body = compile_block(ast, symbols, context, mapped, &mut defers);
+
+    if defers.is_empty() {
+        return body;
+    }
+
+    /* Every flag is cleared first, since a `defer` in a branch might never be reached */
+    let mut instructions = vec![];
+
+    for index in 0..defers.len() {
+        instructions.push(HlvmHirInstruction::Push(HlvmValue::Bool(false)));
+        instructions.push(HlvmHirInstruction::SetLocal(defer_flag(index)));
+    }
+
+    let mut exit = compile_deferred(defers, symbols, context, mapped);
+
+    /* Reaching the end of a function returns none, like a bare `return` */
+    if function {
+        instructions.push(HlvmHirInstruction::Push(HlvmValue::None));
+        instructions.push(HlvmHirInstruction::SetLocal(RETURN_TEMPORARY.to_string()));
+        exit.push(HlvmHirInstruction::GetLocal(RETURN_TEMPORARY.to_string()));
+        exit.push(HlvmHirInstruction::ReturnValue);
+    }
+
+    instructions.push(HlvmHirInstruction::Guarded { body, exit });
+    instructions
+}
+
+/// Runs the blocks of the `defer`s that have been reached, the most recent first.
+fn compile_deferred(
+    defers: Vec<Vec<Node>>,
+    symbols: &SymbolTable,
+    context: &mut Context,
+    mapped: bool,
+) -> Vec<HlvmHirInstruction> {
+    let mut instructions = vec![];
+
+    for (index, body) in defers.into_iter().enumerate().rev() {
+        instructions.push(HlvmHirInstruction::GetLocal(defer_flag(index)));
+        instructions.push(HlvmHirInstruction::IfStatement {
+            ontrue: compile_block(body, symbols, context, mapped, &mut vec![]),
+            onelseif: None,
+            onfalse: vec![],
+        });
+    }
+
+    instructions
+}
+
+/// Compiles the statements of a block. `defers` holds the bodies of the `defer`s of the
+/// enclosing function seen so far, which run in its exit.
+fn compile_block(
+    ast: Vec<Node>,
+    symbols: &SymbolTable,
+    context: &mut Context,
+    mapped: bool,
+    defers: &mut Vec<Vec<Node>>,
+) -> Vec<HlvmHirInstruction> {
+    let mut instructions = vec![];
+
+    for node in ast {
+        if mapped {
+            instructions.push(HlvmHirInstruction::Line(node.line + 1));
+        }
+
+        match node.inner {
+            NodeValue::VariableDecleration(name, value, ..) => {
+                instructions.append(&mut compile_value(*value));
+                instructions.push(HlvmHirInstruction::SetLocal(name));
+            }
+            NodeValue::VariableAssignment(name, value) => {
+                instructions.append(&mut compile_value(*value));
+                instructions.push(HlvmHirInstruction::SetLocal(name));
+            }
+            NodeValue::AttributeAssignment(object, attribute, value) => {
+                let value = compile_value(*value);
+                instructions.append(&mut compile_attribute_assignment(*object, attribute, value));
+            }
+            NodeValue::Destructure(Pattern::Array(names, rest), value) => {
+                instructions.append(&mut compile_value(*value));
+                instructions.push(HlvmHirInstruction::Unpack(names.len(), rest.is_some()));
+
+                for name in names.into_iter().chain(rest) {
+                    instructions.push(HlvmHirInstruction::SetLocal(name));
+                }
+            }
+            NodeValue::Destructure(Pattern::Struct(names), value) => {
+                /* The struct is stored in a temporary, because every attribute needs to load it */
+                instructions.append(&mut compile_value(*value));
+                instructions.push(HlvmHirInstruction::SetLocal(DESTRUCTURE_TEMPORARY.to_string()));
+
+                for name in names {
+                    instructions.push(HlvmHirInstruction::GetLocal(DESTRUCTURE_TEMPORARY.to_string()));
+                    instructions.push(HlvmHirInstruction::GetAttribute(
+                        source_name(&name).to_string(),
+                    ));
+                    instructions.push(HlvmHirInstruction::SetLocal(name));
+                }
+            }
+            NodeValue::If(ontrue, onelseif, onfalse) => {
+                let ontrue_body = compile_block(ontrue.1, symbols, context, mapped, defers);
+                let mut onelseif_hir: Vec<(Vec<HlvmHirInstruction>, Vec<HlvmHirInstruction>)> =
+                    Vec::with_capacity(onelseif.len());
+
+                let onelseif_isempty = onelseif.is_empty();
+
+                for elseif in onelseif {
+                    onelseif_hir.push((
+                        compile_value(*elseif.0),
+                        compile_block(elseif.1, symbols, context, mapped, defers),
+                    ));
+                }
+
+                instructions.append(&mut compile_value(*ontrue.0));
+                instructions.push(HlvmHirInstruction::IfStatement {
+                    ontrue: ontrue_body,
+                    onelseif: if onelseif_isempty {
+                        None
+                    } else {
+                        Some(onelseif_hir)
+                    },
+                    onfalse: compile_block(
+                        onfalse.unwrap_or(vec![]),
+                        symbols,
+                        context,
+                        mapped,
+                        defers,
+                    ),
+                })
+            }
+            NodeValue::WhileStatement(condition, body) => {
+                instructions.push(HlvmHirInstruction::WhileStatement(
+                    compile_value(*condition),
+                    compile_block(body, symbols, context, mapped, defers),
+                ));
+            }
+            NodeValue::Return(value) => {
+                instructions.append(&mut compile_value(*value));
+
+                /* The value is put aside while the deferred blocks run in the exit of the
+                   body, which returns it. A `defer` further down can't have been reached yet,
+                   since loops can't defer */
+                if defers.is_empty() {
+                    instructions.push(HlvmHirInstruction::ReturnValue);
+                } else {
+                    instructions.push(HlvmHirInstruction::SetLocal(RETURN_TEMPORARY.to_string()));
+                    instructions.push(HlvmHirInstruction::Leave);
+                }
+            }
+            NodeValue::Defer(body) => {
+                instructions.push(HlvmHirInstruction::Push(HlvmValue::Bool(true)));
+                instructions.push(HlvmHirInstruction::SetLocal(defer_flag(defers.len())));
+                defers.push(body);
+            }
+            NodeValue::Asm(block) => {
+                context.asm_line.get_or_insert(node.line + 1);
+                instructions.push(HlvmHirInstruction::Asm(block));
+            }
+            NodeValue::FunctionDecleration(name, body, params, ..) => {
+                let function = compile_function(&name, body, params, symbols, context);
+                instructions.push(HlvmHirInstruction::Push(function));
+                instructions.push(HlvmHirInstruction::SetLocal(name));
+            }
+            NodeValue::ImportStatement(path, alias) => {
+                let import = crate::import::load(&path, context);
+                instructions.extend(import.instructions);
+
+                /* The module has no code of its own to run, it already ran above */
+                let mut module = Module::new();
+                let main =
+                    HlvmValue::Function(vec![], vec![], Some(0), Some(0), context.function_id());
+                module.insert("<hlvm:main>".to_string(), main);
+                instructions.push(HlvmHirInstruction::LoadModule(alias.clone(), module));
+
+                for (name, global) in import.exports {
+                    instructions.push(HlvmHirInstruction::GetGlobal(alias.clone()));
+                    instructions.push(HlvmHirInstruction::Get(global));
+                    instructions.push(HlvmHirInstruction::SetAttribute(name));
+                    instructions.push(HlvmHirInstruction::SetGlobal(alias.clone()));
+                }
+            }
+            NodeValue::TypeDecleration(name, functions, variables) => {
+                /* Defaults are evaluated once, when the type is declared */
+                instructions.push(HlvmHirInstruction::Push(HlvmValue::StructBlueprint(
+                    Default::default(),
+                )));
+
+                let mut variables: Vec<_> = variables.into_iter().collect();
+                variables.sort_by(|a, b| a.0.cmp(&b.0));
+
+                for (attribute, variable) in variables {
+                    if let NodeValue::VariableDecleration(_, value, ..) = variable {
+                        instructions.append(&mut compile_value(*value));
+                        instructions.push(HlvmHirInstruction::SetAttribute(attribute));
+                    }
+                }
+
+                /* Methods are renamed like nested functions, but stored under their own name */
+                for function in functions {
+                    if let NodeValue::FunctionDecleration(name, body, params, ..) = function {
+                        let function = compile_function(&name, body, params, symbols, context);
+                        instructions.push(HlvmHirInstruction::Push(function));
+                        instructions.push(HlvmHirInstruction::SetAttribute(
+                            source_name(&name).to_string(),
+                        ));
+                    }
+                }
+
+                instructions.push(HlvmHirInstruction::SetGlobal(name));
+            }
+
+            NodeValue::StringValue(..)
+            | NodeValue::NumberValue(..)
+            | NodeValue::BoolValue(..)
+            | NodeValue::NoneValue
+            | NodeValue::IdentifierValue(..)
+            | NodeValue::FunctionCall(..)
+            | NodeValue::PrimitiveFunctionCall(..)
+            | NodeValue::Binary(..)
+            | NodeValue::Conditional(..)
+            | NodeValue::StructInit(..)
+            | NodeValue::GetAttribute(..)
+            | NodeValue::Call(..)
+            | NodeValue::Convert(..)
+            | NodeValue::ArrayValue(..)
+            | NodeValue::Index(..)
+            | NodeValue::Slice(..)
+            | NodeValue::Unary(..) => {
+                /* Nothing uses the value of an expression statement, so it's discarded */
+                instructions.append(&mut compile_value(node.inner));
+                instructions.push(HlvmHirInstruction::Pop);
+            }
+        }
+    }
+
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pipeline::compile_program;
+    use hlvm::{
+        lir::{HlvmInstruction, HlvmValue},
+        vm::{HighLevelVirtualMachine, VmOptions},
+    };
+
+    /// Compiles and runs `source`, then calls its function `name` with `arguments`.
+    fn call(source: &str, name: &str, arguments: Vec<HlvmValue>) -> Result<HlvmValue, String> {
+        let instructions = compile_program(source).expect("The program should typecheck");
+
+        let mut vm = HighLevelVirtualMachine::new(Some(1));
+        vm.execute(&instructions)?;
+        vm.call(name, arguments)
+    }
+
+    #[test]
+    fn passes_arguments_in_order() {
+        let source = "\
+fn sub(a: number, b: number): number {
+    return a - b
+}
+
+fn join(a: string, b: string, c: string): string {
+    return a + b + c
+}
+
+fn test(): string {
+    return join(sub(10, 4) as string, \"-\", sub(1, 3) as string)
+}
+";
+
+        assert_eq!(call(source, "test", vec![]), Ok("6--2".into()));
+        assert_eq!(call(source, "sub", vec![5.0.into(), 2.0.into()]), Ok(3.0.into()));
+    }
+
+    #[test]
+    fn evaluates_arguments_before_the_call() {
+        let source = "\
+fn double(n: number): number {
+    return n * 2
+}
+
+fn add(a: number, b: number): number {
+    return a + b
+}
+
+fn test(): number {
+    return add(double(add(1, 2)), double(double(5)))
+}
+";
+
+        assert_eq!(call(source, "test", vec![]), Ok(26.0.into()));
+    }
+
+    #[test]
+    fn passes_arguments_to_recursive_calls() {
+        let source = "\
+fn power(base: number, exponent: number): number {
+    if exponent == 0 {
+        return 1
+    }
+
+    return base * power(base, exponent - 1)
+}
+";
+
+        assert_eq!(call(source, "power", vec![2.0.into(), 10.0.into()]), Ok(1024.0.into()));
+        assert_eq!(call(source, "power", vec![3.0.into(), 0.0.into()]), Ok(1.0.into()));
+    }
+
+    #[test]
+    fn functions_know_their_arity() {
+        let source = "\
+fn add(a: number, b: number): number {
+    return a + b
+}
+";
+
+        let error = call(source, "add", vec![1.0.into()]).unwrap_err();
+        assert!(error.contains("E0310"), "{}", error);
+    }
+
+    #[test]
+    fn prelude_pow_matches_the_power_operator() {
+        let source = "\
+fn power(base: number, exponent: number): number {
+    return base ** exponent
+}
+";
+
+        let cases = [(2.0, -1.0), (0.0, 0.0), (0.0, -1.0), (2.25, 0.5), (-2.0, 3.0), (3.0, 2.5)];
+
+        for (base, exponent) in cases {
+            let arguments = vec![base.into(), exponent.into()];
+            let expected = Ok(HlvmValue::Number(f64::powf(base, exponent)));
+
+            assert_eq!(call(source, "pow", arguments.clone()), expected);
+            assert_eq!(call(source, "power", arguments), expected);
+        }
+
+        let nan = call(source, "pow", vec![(-8.0).into(), (1.0 / 3.0).into()]);
+        assert!(matches!(nan, Ok(HlvmValue::Number(n)) if n.is_nan()), "{:?}", nan);
+    }
+
+    #[test]
+    fn if_let_guards_choose_the_branch() {
+        let source = "\
+fn classify(text: string): string {
+    if let n = parse_float!(text) where n > 10 {
+        return \"big\"
+    } else if let n = parse_float!(text) where n < 0 {
+        return \"negative\"
+    } else if let n = parse_float!(text) {
+        return \"small\"
+    }
+
+    return \"not a number\"
+}
+";
+
+        let classify = |text: &str| call(source, "classify", vec![text.into()]);
+
+        assert_eq!(classify("12"), Ok("big".into()));
+        assert_eq!(classify("-3"), Ok("negative".into()));
+        assert_eq!(classify("5"), Ok("small".into()));
+        assert_eq!(classify("x"), Ok("not a number".into()));
+    }
+
+    #[test]
+    fn guards_only_see_values_that_arent_none() {
+        /* Comparing none with a number is an error, so this fails if the guard ever runs */
+        let source = "\
+fn test(): string {
+    if let n = parse_float!(\"x\") where n > 0 {
+        return \"matched\"
+    }
+
+    return \"skipped\"
+}
+";
+
+        assert_eq!(call(source, "test", vec![]), Ok("skipped".into()));
+    }
+
+    #[test]
+    fn while_let_guards_end_the_loop() {
+        let source = "\
+fn test(): number {
+    let items: [number] = [3, 5, 20, 1]
+    let mut i: number = 0
+    let mut sum: number = 0
+
+    while let item = items[i] where item < 10 {
+        sum = sum + item
+        i = i + 1
+    }
+
+    return sum * 10 + i
+}
+";
+
+        assert_eq!(call(source, "test", vec![]), Ok(82.0.into()));
+    }
+
+    /// The ids of every function in `instructions`, including nested ones.
+    fn function_ids(instructions: &[HlvmInstruction], ids: &mut Vec<usize>) {
+        for instruction in instructions {
+            if let HlvmInstruction::Push(HlvmValue::Function(body, .., id)) = instruction {
+                ids.push(*id);
+                function_ids(body, ids);
+            }
+        }
+    }
+
+    #[test]
+    fn identical_functions_from_different_declarations_are_unequal() {
+        let source = "\
+fn one(): number {
+    return 1
+}
+
+fn also_one(): number {
+    return 1
+}
+";
+
+        let instructions = compile_program(source).unwrap();
+        let mut vm = HighLevelVirtualMachine::new(Some(1));
+        vm.execute(&instructions).unwrap();
+
+        let globals = &vm.call_stack[0].locals;
+        assert_ne!(globals["one"], globals["also_one"]);
+        assert_eq!(globals["one"], globals["one"].clone());
+    }
+
+    #[test]
+    fn function_ids_are_unique_within_a_program() {
+        let source = "\
+fn a(): number {
+    fn inner(): number {
+        return 1
+    }
+
+    return inner()
+}
+";
+
+        let mut ids = vec![];
+        function_ids(&compile_program(source).unwrap(), &mut ids);
+
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+    }
+
+    #[test]
+    fn deferred_blocks_are_compiled_once() {
+        let source = "\
+fn f(n: number): number {
+    defer {
+        fn cleanup(): number {
+            return 0
+        }
+
+        print!(cleanup())
+    }
+
+    if n == 1 {
+        return 1
+    } else if n == 2 {
+        return 2
+    }
+
+    return 3
+}
+";
+
+        /* `f` and `cleanup` on top of the prelude, however many returns `f` has */
+        let mut prelude = vec![];
+        function_ids(&compile_program("").unwrap(), &mut prelude);
+
+        let instructions = compile_program(source).unwrap();
+        let mut ids = vec![];
+        function_ids(&instructions, &mut ids);
+        assert_eq!(ids.len(), prelude.len() + 2);
+
+        assert_eq!(hlvm::cfg::verify_lir(&instructions), Ok(()));
+        assert_eq!(call(source, "f", vec![2.0.into()]), Ok(2.0.into()));
+        assert_eq!(call(source, "f", vec![5.0.into()]), Ok(3.0.into()));
+    }
+
+    #[test]
+    fn function_ids_dont_depend_on_earlier_compilations() {
+        let source = "\
+fn f(): number {
+    return 1
+}
+";
+
+        let first = compile_program(source).unwrap();
+        compile_program("fn g(): number {\n    return 2\n}\n").unwrap();
+
+        assert_eq!(compile_program(source).unwrap(), first);
+    }
+
+    #[test]
+    fn expression_statements_leave_nothing_behind() {
+        let source = "\
+fn greet(): number {
+    defer {
+        print!(\"bye\")
+    }
+
+    print!(\"hi\")
+    return 1
+}
+
+let mut i: number = 0
+
+while i < 3 {
+    write!(\"\")
+    greet()
+    i = i + 1
+}
+";
+
+        let instructions = compile_program(source).expect("The program should typecheck");
+        assert_eq!(hlvm::cfg::verify_lir(&instructions), Ok(()));
+
+        let mut vm = HighLevelVirtualMachine::new(Some(1));
+        vm.execute(&instructions).unwrap();
+        assert!(vm.stack.is_empty(), "{:?}", vm.stack);
+    }
+
+    #[test]
+    fn long_loops_stay_under_small_memory_limits() {
+        let source = "\
+let mut i: number = 0
+
+while i < 200000 {
+    write!(\"\")
+    i = i + 1
+}
+";
+
+        let instructions = compile_program(source).expect("The program should typecheck");
+        let options = VmOptions::default().max_memory(16 * 1024);
+        let mut vm = HighLevelVirtualMachine::with_options(Some(1), options);
+
+        assert!(vm.execute(&instructions).is_ok());
+        assert!(vm.memory_usage() < 1024, "{}", vm.memory_usage());
+    }
+
+    #[test]
+    fn compiles_very_large_programs() {
+        const STATEMENTS: usize = 20_000;
+
+        /* A long function, a long if/else-if chain and a large array literal */
+        let mut source = String::from("fn sum(): number {\n    let mut total: number = 0\n");
+
+        for i in 0..STATEMENTS {
+            source.push_str(&format!("    total = total + {}\n", i % 10));
+        }
+
+        source.push_str("    return total\n}\n\nfn pick(n: number): number {\n    if n == 0 {\n");
+
+        for i in 1..STATEMENTS / 10 {
+            source.push_str(&format!("        return 0\n    }} else if n == {} {{\n", i));
+        }
+
+        source.push_str("        return n\n    }\n\n    return -1\n}\n\n");
+
+        let elements = (0..STATEMENTS).map(|i| i.to_string()).collect::<Vec<_>>();
+        source.push_str(&format!("let elements: [number] = [{}]\n", elements.join(", ")));
+
+        let instructions = compile_program(&source).unwrap();
+        assert_eq!(hlvm::cfg::check_limits(&instructions), Ok(()));
+
+        let mut vm = HighLevelVirtualMachine::new(Some(1));
+        vm.execute(&instructions).unwrap();
+
+        assert_eq!(vm.call("sum", vec![]), Ok(90_000.0.into()));
+        assert_eq!(vm.call("pick", vec![1999.0.into()]), Ok(1999.0.into()));
+        assert_eq!(vm.call("pick", vec![5000.0.into()]), Ok((-1.0).into()));
+
+        match &vm.call_stack[0].locals["elements"] {
+            HlvmValue::Array(elements) => assert_eq!(elements.len(), STATEMENTS),
+            value => panic!("expected an array, found {:?}", value),
+        }
+    }
+}
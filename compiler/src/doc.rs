@@ -0,0 +1,116 @@
+use crate::parser::{Node, NodeValue, Parameter, Type};
+
+/// A documented public item.
+pub struct DocItem {
+    pub name: String,
+    pub signature: String,
+    pub docs: String,
+}
+
+fn type_name(datatype: &Type) -> String {
+    match datatype {
+        Type::String => "string".to_string(),
+        Type::Number => "number".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Array(inner) => format!("[{}]", type_name(inner)),
+        Type::Struct(name) => name.clone(),
+        Type::Void => "void".to_string(),
+    }
+}
+
+fn function_signature(name: &str, params: &[Parameter], return_type: &Type) -> String {
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| {
+            format!(
+                "{}{}: {}",
+                if param.mutable { "mut " } else { "" },
+                param.name,
+                type_name(&param.datatype)
+            )
+        })
+        .collect();
+
+    match return_type {
+        Type::Void => format!("fn {}({})", name, params.join(", ")),
+        _ => format!("fn {}({}): {}", name, params.join(", "), type_name(return_type)),
+    }
+}
+
+/// Collects all public functions and variables declared at the top level of `ast`.
+pub fn items(ast: &[Node]) -> Vec<DocItem> {
+    let mut items = vec![];
+
+    for node in ast {
+        let (name, signature) = match &node.inner {
+            NodeValue::FunctionDecleration(name, _, params, true, return_type) => {
+                (name, function_signature(name, params, return_type))
+            }
+            NodeValue::VariableDecleration(name, _, true, mutable, datatype) => (
+                name,
+                format!(
+                    "let {}{}: {}",
+                    if *mutable { "mut " } else { "" },
+                    name,
+                    type_name(datatype)
+                ),
+            ),
+            _ => continue,
+        };
+
+        items.push(DocItem {
+            name: name.clone(),
+            signature,
+            docs: node.docs.clone().unwrap_or_default(),
+        });
+    }
+
+    items
+}
+
+/// Renders the documentation of `ast` as Markdown.
+pub fn markdown(title: &str, ast: &[Node]) -> String {
+    let mut output = format!("# {}\n", title);
+
+    for item in items(ast) {
+        output.push_str(&format!("\n## {}\n\n```\n{}\n```\n", item.name, item.signature));
+
+        if !item.docs.is_empty() {
+            output.push_str(&format!("\n{}\n", item.docs));
+        }
+    }
+
+    output
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders the documentation of `ast` as a standalone HTML page.
+pub fn html(title: &str, ast: &[Node]) -> String {
+    let title = escape_html(title);
+    let mut output = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    );
+
+    for item in items(ast) {
+        output.push_str(&format!(
+            "<h2 id=\"{}\">{}</h2>\n<pre><code>{}</code></pre>\n",
+            escape_html(&item.name),
+            escape_html(&item.name),
+            escape_html(&item.signature)
+        ));
+
+        for paragraph in item.docs.split("\n\n").filter(|p| !p.trim().is_empty()) {
+            output.push_str(&format!("<p>{}</p>\n", escape_html(paragraph)));
+        }
+    }
+
+    output.push_str("</body>\n</html>\n");
+    output
+}
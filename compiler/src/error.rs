@@ -1,27 +1,366 @@
 use colored::*;
+pub use hlvm::error::{not_found, suggest, ErrorCode};
+use std::io::IsTerminal;
 use std::process::exit;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// When diagnostics should be colored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Always,
+    Never,
+    /// Color only if stdout is a terminal.
+    Auto,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            "auto" => Ok(ColorChoice::Auto),
+            _ => Err(format!("Unknown color choice '{}', expected 'always', 'never' or 'auto'", s)),
+        }
+    }
+}
+
+/// Sets whether diagnostics printed from now on are colored.
+pub fn set_color_choice(choice: ColorChoice) {
+    match choice {
+        ColorChoice::Always => control::set_override(true),
+        ColorChoice::Never => control::set_override(false),
+        ColorChoice::Auto if !std::io::stdout().is_terminal() => control::set_override(false),
+        /* `colored` still honours NO_COLOR and CLICOLOR in this case */
+        ColorChoice::Auto => control::unset_override(),
+    }
+}
+
+/// How diagnostics are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// The layout meant to be read in a terminal, pointing at the offending source.
+    Human,
+    /// One JSON object per line, for tools.
+    Json,
+    /// One LSP `Diagnostic` object per line, for editor integrations.
+    Lsp,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            "lsp" => Ok(MessageFormat::Lsp),
+            _ => Err(format!("Unknown message format '{}', expected 'human', 'json' or 'lsp'", s)),
+        }
+    }
+}
+
+static MESSAGE_FORMAT: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the format diagnostics printed from now on use.
+pub fn set_message_format(format: MessageFormat) {
+    MESSAGE_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+fn message_format() -> MessageFormat {
+    match MESSAGE_FORMAT.load(Ordering::Relaxed) {
+        1 => MessageFormat::Json,
+        2 => MessageFormat::Lsp,
+        _ => MessageFormat::Human,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Where in the source a diagnostic points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// 1-based.
+    pub line: usize,
+    /// 0-based, in bytes.
+    pub column: usize,
+    /// How many bytes are pointed at, 0 if the whole line is meant.
+    pub length: usize,
+    /// The text of the line, if the source is at hand.
+    pub text: Option<String>,
+}
+
+impl Span {
+    /// A span covering line `line` (1-based), without its text.
+    pub fn line(line: usize) -> Span {
+        Span {
+            line,
+            column: 0,
+            length: 0,
+            text: None,
+        }
+    }
+}
+
+/// An error or warning reported by the scanner, parser, resolver or typechecker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: Option<ErrorCode>,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    /// Tips printed below the message.
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            code: Some(code),
+            severity: Severity::Error,
+            message: message.into(),
+            span: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            code: None,
+            severity: Severity::Warning,
+            message: message.into(),
+            span: None,
+            notes: vec![],
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// The `Error[E0101]` or `Warning` label in front of the message.
+    fn label(&self) -> String {
+        match (self.severity, self.code) {
+            (Severity::Error, Some(code)) => format!("Error[{}]", code),
+            (Severity::Error, None) => "Error".to_string(),
+            (Severity::Warning, Some(code)) => format!("Warning[{}]", code),
+            (Severity::Warning, None) => "Warning".to_string(),
+        }
+    }
+
+    /// Renders the diagnostic for a terminal, colored unless coloring is turned off.
+    pub fn render(&self) -> String {
+        if control::SHOULD_COLORIZE.should_colorize() {
+            self.layout(true)
+        } else {
+            self.render_plain()
+        }
+    }
+
+    /// Renders the diagnostic like `render`, but never with color codes. Output that isn't
+    /// read in a terminal uses it, and JSON and LSP output carry it along as `rendered`.
+    pub fn render_plain(&self) -> String {
+        self.layout(false)
+    }
+
+    fn layout(&self, color: bool) -> String {
+        let mut output = String::new();
+        let (label, tip) = match (color, self.severity) {
+            (false, _) => (self.label(), "  Tip".to_string()),
+            (true, Severity::Error) => (self.label().red().to_string(), "  Tip".blue().to_string()),
+            (true, Severity::Warning) => {
+                (self.label().bright_yellow().to_string(), "  Tip".blue().to_string())
+            }
+        };
+
+        match &self.span {
+            Some(Span { line, column, length, text: Some(text) }) => {
+                output.push_str(&render_context(
+                    &" ".repeat(*column),
+                    &"^".repeat(*length),
+                    *line,
+                    text,
+                ));
+                output.push_str(&format!("{}: {}\n", label, self.message));
+            }
+            Some(span) => output.push_str(&format!("{}: line {}: {}\n", label, span.line, self.message)),
+            None => output.push_str(&format!("{}: {}\n", label, self.message)),
+        }
+
+        for note in &self.notes {
+            output.push_str(&format!("{}: {}\n", tip, note));
+        }
+
+        output
+    }
+
+    /// Renders the diagnostic as a single line of JSON.
+    pub fn to_json(&self) -> String {
+        let mut fields = vec![
+            format!("\"severity\":\"{}\"", self.severity_name()),
+            format!("\"message\":{}", json_string(&self.message)),
+        ];
+
+        if let Some(code) = self.code {
+            fields.push(format!("\"code\":\"{}\"", code));
+        }
+
+        if let Some(span) = &self.span {
+            fields.push(format!(
+                "\"line\":{},\"column\":{},\"length\":{}",
+                span.line, span.column, span.length
+            ));
+        }
+
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        fields.push(format!("\"notes\":[{}]", notes.join(",")));
+        fields.push(format!("\"rendered\":{}", json_string(&self.render_plain())));
+
+        format!("{{{}}}", fields.join(","))
+    }
+
+    /// Renders the diagnostic as an LSP `Diagnostic`, with 0-based positions.
+    /// Notes are appended to the message, since LSP related information needs a file URI.
+    /// The plain rendering goes in `data`, which editors pass back untouched.
+    pub fn to_lsp(&self) -> String {
+        /* A span without a length covers its whole line, up to the start of the next one */
+        let (start, end) = match &self.span {
+            Some(span) if span.length == 0 => ((span.line - 1, 0), (span.line, 0)),
+            Some(span) => (
+                (span.line - 1, span.column),
+                (span.line - 1, span.column + span.length),
+            ),
+            None => ((0, 0), (0, 0)),
+        };
+
+        let mut message = self.message.clone();
+
+        for note in &self.notes {
+            message.push_str(&format!("\n\nTip: {}", note));
+        }
+
+        let severity = match self.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        };
+
+        let code = match self.code {
+            Some(code) => format!("\"code\":\"{}\",", code),
+            None => String::new(),
+        };
+
+        format!(
+            "{{\"range\":{{\"start\":{{\"line\":{},\"character\":{}}},\"end\":{{\"line\":{},\
+             \"character\":{}}}}},\"severity\":{},{}\"source\":\"lace\",\"message\":{},\
+             \"data\":{{\"rendered\":{}}}}}",
+            start.0,
+            start.1,
+            end.0,
+            end.1,
+            severity,
+            code,
+            json_string(&message),
+            json_string(&self.render_plain())
+        )
+    }
+
+    fn severity_name(&self) -> &'static str {
+        match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+
+    /// Prints the diagnostic in the current message format.
+    pub fn emit(&self) {
+        match message_format() {
+            MessageFormat::Human => print!("{}", self.render()),
+            MessageFormat::Json => println!("{}", self.to_json()),
+            MessageFormat::Lsp => println!("{}", self.to_lsp()),
+        }
+    }
+
+    /// Prints the diagnostic, then exits since compilation can't continue.
+    pub fn emit_and_exit(&self) -> ! {
+        self.emit();
+        exit(1)
+    }
+}
+
+/// Quotes `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut output = String::from("\"");
+
+    for c in text.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if c.is_control() => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+
+    output.push('"');
+    output
+}
+
+/// Renders the source line an error points at, along with the pointer below it.
+fn render_context(spacing: &str, pointer: &str, line_idx: usize, line_text: &str) -> String {
+    let empty = " ".repeat(line_idx.to_string().len());
+
+    format!(
+        "{} |\n{} | {}\n{} | {}{}\n",
+        empty, line_idx, line_text, empty, spacing, pointer
+    )
+}
 
 pub struct ErrorHandler;
 
 impl ErrorHandler {
+    fn diagnostic(
+        code: ErrorCode,
+        spacing: String,
+        pointer: String,
+        line_idx: usize,
+        line_text: &str,
+        error: &str,
+    ) -> Diagnostic {
+        Diagnostic::error(code, error).with_span(Span {
+            line: line_idx,
+            column: spacing.len(),
+            length: pointer.len(),
+            text: Some(line_text.to_string()),
+        })
+    }
+
     pub fn error(
-        empty: String,
+        code: ErrorCode,
         spacing: String,
         pointer: String,
         line_idx: usize,
         line_text: &str,
         error: &str,
     ) -> ! {
-        println!("{} |", empty);
-        println!("{} | {}", line_idx, line_text);
-        println!("{} | {}{}", empty, spacing, pointer);
-        println!("{}: {}", "Error".red(), error);
-
-        exit(0);
+        Self::diagnostic(code, spacing, pointer, line_idx, line_text, error).emit_and_exit()
     }
 
     pub fn error_tip(
-        empty: String,
+        code: ErrorCode,
         spacing: String,
         pointer: String,
         line_idx: usize,
@@ -29,12 +368,43 @@ impl ErrorHandler {
         error: &str,
         tip: &str,
     ) -> ! {
-        println!("{} |", empty);
-        println!("{} | {}", line_idx, line_text);
-        println!("{} | {}{}", empty, spacing, pointer);
-        println!("{}: {}", "Error".red(), error);
-        println!("{}: {}", "  Tip".blue(), tip);
+        Self::diagnostic(code, spacing, pointer, line_idx, line_text, error)
+            .with_note(tip)
+            .emit_and_exit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic() -> Diagnostic {
+        Diagnostic::error(ErrorCode::UndefinedVariable, "`x` isn't defined")
+            .with_span(Span::line(3))
+            .with_note("Declare it with `let`")
+    }
+
+    #[test]
+    fn plain_rendering_has_no_color_codes() {
+        colored::control::set_override(true);
+        let plain = diagnostic().render_plain();
+        colored::control::unset_override();
+
+        assert!(!plain.contains('\u{1b}'));
+        assert_eq!(
+            plain,
+            format!(
+                "Error[{}]: line 3: `x` isn't defined\n  Tip: Declare it with `let`\n",
+                ErrorCode::UndefinedVariable
+            )
+        );
+    }
+
+    #[test]
+    fn json_and_lsp_output_carry_the_plain_rendering() {
+        let rendered = json_string(&diagnostic().render_plain());
 
-        exit(0);
+        assert!(diagnostic().to_json().contains(&format!("\"rendered\":{}", rendered)));
+        assert!(diagnostic().to_lsp().contains(&format!("\"data\":{{\"rendered\":{}}}", rendered)));
     }
 }
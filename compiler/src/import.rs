@@ -0,0 +1,120 @@
+/* --------------------------------------------------------------
+Compiles the files named by `use` statements.
+
+An imported file is compiled on its own and its code is placed where it's imported, so that
+its top-level declarations run once and end up as globals. They're renamed by module, so
+they can't collide with the importer's names, and the `pub` ones are bound to the alias as
+the attributes of a `LoadModule` struct.
+-------------------------------------------------------------- */
+
+use crate::{
+    codegen::Context,
+    error::{Diagnostic, ErrorCode},
+    parser::NodeValue,
+    scope::{source_name, Resolver},
+    typecheck::Typechecker,
+};
+use hlvm::hir::HlvmHirInstruction;
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+thread_local! {
+    /// The files being compiled, innermost last. Imports are resolved relative to the last.
+    static FILES: RefCell<Vec<PathBuf>> = const { RefCell::new(vec![]) };
+}
+
+/// An imported file, compiled.
+pub struct Import {
+    /// The code of the file, run where it's imported.
+    pub instructions: Vec<HlvmHirInstruction>,
+
+    /// `(name, global)` pairs of the file's public top-level declarations, with the names
+    /// they're exported as and the globals they're stored in.
+    pub exports: Vec<(String, String)>,
+}
+
+/// Runs `compile` with `file` as the file being compiled, so imports are resolved relative to it.
+pub fn within<T>(file: &Path, compile: impl FnOnce() -> T) -> T {
+    FILES.with(|files| files.borrow_mut().push(file.to_path_buf()));
+    let result = compile();
+    FILES.with(|files| files.borrow_mut().pop());
+    result
+}
+
+/// Compiles the file that `path` names from the file being compiled, as part of the program
+/// `context` belongs to. A `path` naming one of the program's packages imports that package.
+/// Exits with an error if it can't be read, or if it's already being compiled.
+pub fn load(path: &str, context: &mut Context) -> Import {
+    let importer = FILES.with(|files| files.borrow().last().cloned());
+    let path = match (context.packages.get(path), importer.as_deref().and_then(Path::parent)) {
+        (Some(package), _) => package.clone(),
+        (None, Some(directory)) => directory.join(path),
+        (None, None) => PathBuf::from(path),
+    };
+
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        Diagnostic::error(
+            ErrorCode::ImportNotFound,
+            format!("Can't import '{}': {}", path.display(), err),
+        )
+        .emit_and_exit()
+    });
+
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+    let cycle = FILES.with(|files| {
+        let files = files.borrow();
+        let start = files
+            .iter()
+            .position(|file| file.canonicalize().ok().as_ref() == Some(&canonical))?;
+
+        Some(
+            files[start..]
+                .iter()
+                .chain([&path])
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> "),
+        )
+    });
+
+    if let Some(cycle) = cycle {
+        Diagnostic::error(ErrorCode::CircularImport, format!("Circular import: {}", cycle))
+            .emit_and_exit()
+    }
+
+    within(&path, || {
+        let ast = crate::pipeline::lace_pipeline_init(&source);
+
+        let mut resolver = Resolver::new();
+        resolver.module = Some(canonical.display().to_string());
+        let ast = resolver.resolve(ast);
+        let symbols = resolver.symbols;
+
+        if let Err(diagnostics) = Typechecker::new(&symbols).check(&ast) {
+            for diagnostic in &diagnostics {
+                diagnostic.emit();
+            }
+
+            std::process::exit(1);
+        }
+
+        /* Private declarations still run, so public functions can use them */
+        let exports = ast
+            .iter()
+            .filter_map(|node| match &node.inner {
+                NodeValue::FunctionDecleration(global, _, _, true, _)
+                | NodeValue::VariableDecleration(global, _, true, ..) => {
+                    Some((source_name(global).to_string(), global.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        Import {
+            instructions: crate::codegen::compile(ast, &symbols, context),
+            exports,
+        }
+    })
+}
@@ -1,7 +1,13 @@
 pub mod codegen;
+pub mod doc;
 pub mod error;
+pub mod import;
 pub mod optimizer;
 pub mod parser;
+pub mod prelude;
 pub mod scanner;
+pub mod scope;
+pub mod symbols;
 pub mod typecheck;
 pub mod pipeline;
+pub mod visitor;
@@ -1 +1,92 @@
-
+/* --------------------------------------------------------------
+Optimizations that run on the resolved AST, before code generation.
+-------------------------------------------------------------- */
+
+use crate::{
+    parser::{Node, NodeValue},
+    scope::source_name,
+    visitor::{walk_value, Visitor},
+};
+use hlvm::lir::HlvmInstruction;
+use std::collections::HashSet;
+
+/// Collects the names a program refers to.
+#[derive(Default)]
+struct References {
+    names: HashSet<String>,
+
+    /// Whether the program imports files, whose references aren't visible in its AST.
+    imports: bool,
+}
+
+impl Visitor for References {
+    fn visit_value(&mut self, value: &NodeValue) {
+        match value {
+            NodeValue::IdentifierValue(name)
+            | NodeValue::FunctionCall(name, _)
+            | NodeValue::StructInit(name, _) => {
+                self.names.insert(name.clone());
+            }
+            /* Names in assembly aren't resolved, so they're written as in the source */
+            NodeValue::Asm(instructions) => {
+                for instruction in instructions {
+                    if let HlvmInstruction::Get(name)
+                    | HlvmInstruction::GetLocal(name)
+                    | HlvmInstruction::GetGlobal(name) = instruction
+                    {
+                        self.names.insert(name.clone());
+                    }
+                }
+            }
+            NodeValue::ImportStatement(..) => self.imports = true,
+            _ => {}
+        }
+
+        walk_value(self, value)
+    }
+}
+
+/// Removes the functions declared in `library` that `program` never uses, either directly or
+/// through other declarations of `library`. Nothing is removed if `program` imports files,
+/// since what they use can't be seen.
+pub fn remove_unused_functions(library: Vec<Node>, program: &[Node]) -> Vec<Node> {
+    let mut references = References::default();
+    references.visit_block(program);
+
+    if references.imports {
+        return library;
+    }
+
+    let mut kept = vec![false; library.len()];
+
+    /* Keeping a declaration can make the functions it uses needed, so repeat until it doesn't */
+    loop {
+        let mut changed = false;
+
+        for (node, kept) in library.iter().zip(&mut kept) {
+            let needed = match &node.inner {
+                NodeValue::FunctionDecleration(name, ..) => {
+                    references.names.contains(name)
+                        || references.names.contains(source_name(name))
+                }
+                _ => true,
+            };
+
+            if needed && !*kept {
+                *kept = true;
+                changed = true;
+                references.visit_node(node);
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    library
+        .into_iter()
+        .zip(kept)
+        .filter_map(|(node, kept)| kept.then_some(node))
+        .collect()
+}
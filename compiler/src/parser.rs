@@ -1,9 +1,14 @@
 use crate::error::*;
-use crate::scanner::Token;
-use colored::*;
-use logos::Lexer;
-use std::collections::HashMap;
+use crate::scanner::{string_value, unescape, Token};
+use hlvm::{
+    lir::{Conversion, HlvmInstruction, HlvmValue},
+    module::constants,
+    vm::{primitive_index, PRIMITIVES},
+};
+use logos::{Lexer, Logos};
+use std::collections::{HashMap, HashSet};
 use std::mem::discriminant;
+use std::ops::Range;
 
 /// Represents a unary operation
 #[derive(Debug, Clone)]
@@ -23,9 +28,20 @@ pub enum Type {
     Number,
     Bool,
     Array(Box<Type>),
+    /// An instance of a type declared with `struct` or `type`, like `Point`.
+    Struct(String),
     Void,
 }
 
+/// The left hand side of a destructuring `let`.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `[a, b, rest...]` - The names of the elements, and the name of the rest, if any.
+    Array(Vec<String>, Option<String>),
+    /// `{x, y}` - The names of the attributes.
+    Struct(Vec<String>),
+}
+
 #[derive(Debug, Clone)]
 pub struct Parameter {
     pub name: String,
@@ -47,19 +63,37 @@ pub enum NodeValue {
     StructInit(String, Vec<(String, Node)>),
 
     Unary(Box<NodeValue>, Unary),
-    Binary(Box<NodeValue>, Box<NodeValue>, Token),
+    Binary(Box<NodeValue>, Box<NodeValue>, Token<'static>),
+    // Condition, value if true, value if false
+    Conditional(Box<NodeValue>, Box<NodeValue>, Box<NodeValue>),
 
     GetAttribute(Box<NodeValue>, String),
+    /// Calls the result of an expression, like `point.describe()`.
+    Call(Box<NodeValue>, Vec<NodeValue>),
+    /// Converts a value to another type, like `"12" as number`.
+    Convert(Box<NodeValue>, Type),
+    /// An element of a string or array, like `s[1]`.
+    Index(Box<NodeValue>, Box<NodeValue>),
+    /// Part of a string or array, like `s[1..4]`. Missing bounds are the start and end.
+    Slice(Box<NodeValue>, Option<Box<NodeValue>>, Option<Box<NodeValue>>),
 
     FunctionDecleration(String, Vec<Node>, Vec<Parameter>, Public, Type),
     VariableDecleration(String, Box<NodeValue>, Public, Mutable, Type),
+    Destructure(Pattern, Box<NodeValue>),
     // Name, Functions, Attributes
     TypeDecleration(String, Vec<NodeValue>, HashMap<String, NodeValue>),
     VariableAssignment(String, Box<NodeValue>),
+    /// Sets an attribute of a variable, like `point.x = 1`. The object is the variable or
+    /// one of its attributes, like `line.start` in `line.start.x = 1`.
+    AttributeAssignment(Box<NodeValue>, String, Box<NodeValue>),
     WhileStatement(Box<NodeValue>, Vec<Node>),
     ImportStatement(String, String),
     If(ConditionalBlock, Vec<ConditionalBlock>, Option<Vec<Node>>),
     Return(Box<NodeValue>),
+    /// A block that runs when the enclosing function returns, like `defer { close(file) }`.
+    Defer(Vec<Node>),
+    /// Raw HLVM instructions. Names used in them aren't resolved into scopes.
+    Asm(Vec<HlvmInstruction>),
 }
 
 /// Contains a NodeValue along with additional metadata, like which line the node was on.
@@ -67,37 +101,112 @@ pub enum NodeValue {
 pub struct Node {
     pub inner: NodeValue,
     pub line: usize,
+    /// The `///` doc comment written above the node, if any.
+    pub docs: Option<String>,
 }
 
 impl Node {
     pub fn new(value: NodeValue, line: usize) -> Node {
-        Node { inner: value, line }
+        Node {
+            inner: value,
+            line,
+            docs: None,
+        }
+    }
+}
+
+/// The value an attribute of `datatype` starts out as when a struct doesn't give it one.
+fn zero_value(datatype: &Type) -> NodeValue {
+    match datatype {
+        Type::String => NodeValue::StringValue(String::new()),
+        Type::Number => NodeValue::NumberValue(0.0),
+        Type::Bool => NodeValue::BoolValue(false),
+        Type::Array(_) => NodeValue::ArrayValue(vec![]),
+        Type::Struct(_) | Type::Void => NodeValue::NoneValue,
     }
 }
 
+/// The type the primitive function at `index` returns, if it's always the same.
+pub(crate) fn primitive_type(index: usize) -> Option<Type> {
+    match PRIMITIVES.get(index)?.returns? {
+        "number" => Some(Type::Number),
+        "string" => Some(Type::String),
+        "bool" => Some(Type::Bool),
+        _ => None,
+    }
+}
+
+/// The default value of `Parser::max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// The operators each precedence level of binary expressions recognizes, see
+/// `Parser::binary_expression`. `**` is parsed on its own, see `Parser::power_expression`.
+const LOGICAL_OPERATORS: &[Token<'static>] = &[Token::KwAnd, Token::KwOr];
+const COMPARISON_OPERATORS: &[Token<'static>] = &[
+    Token::OpEq,
+    Token::OpBangEq,
+    Token::OpLess,
+    Token::OpMore,
+    Token::OpMoreEq,
+    Token::OpLessEq,
+];
+const BITWISE_OR_OPERATORS: &[Token<'static>] = &[Token::BitwiseOr];
+const BITWISE_XOR_OPERATORS: &[Token<'static>] = &[Token::BitwiseXor];
+const BITWISE_AND_OPERATORS: &[Token<'static>] = &[Token::BitwiseAnd];
+const SHIFT_OPERATORS: &[Token<'static>] = &[Token::OpLeftShift, Token::OpRightShift];
+const ADDITIVE_OPERATORS: &[Token<'static>] = &[Token::OpAdd, Token::OpSub];
+const MULTIPLICATIVE_OPERATORS: &[Token<'static>] = &[Token::OpMul, Token::OpDiv, Token::OpMod];
+
 pub struct Parser<'a> {
-    source: String,
+    source: &'a str,
 
     // Just used to determine the current line index
     line: usize,
     last: usize,
 
+    // How deeply the expression or block currently being parsed is nested
+    depth: usize,
+
+    /// The maximum nesting depth of expressions and blocks before an error is raised,
+    /// which keeps pathological inputs from overflowing the stack.
+    pub max_depth: usize,
+
+    /// Whether a `defer` block is being parsed, which can't return or defer.
+    deferring: bool,
+
+    /// Whether the body of a loop is being parsed, which can't defer, since a deferred block
+    /// only runs once however many times it's reached.
+    looping: bool,
+
+    /// The types declared so far, which can be used in type annotations.
+    types: HashSet<String>,
+
     pub ast: Vec<Node>,
-    pub tokens: Lexer<'a, Token>,
+    pub tokens: Lexer<'a, Token<'a>>,
 
-    pub current: Token,
+    pub current: Token<'a>,
 }
 
 impl<'p> Parser<'p> {
     /// Creates a new Parser.
-    /// Requires the lexer iterator to contain atleast one token, and will panic otherwise.
-    pub fn new(mut tokens: Lexer<Token>, source: String) -> Parser {
-        let first = tokens.next().unwrap();
+    /// If the source has no tokens at all, like an empty or comment-only file,
+    /// the parser starts at `Token::End` and produces an empty program.
+    pub fn new(mut tokens: Lexer<'p, Token<'p>>, source: &'p str) -> Parser<'p> {
+        let (first, last) = match tokens.next() {
+            Some(token) => (token, tokens.span().start),
+            None => (Token::End, 0),
+        };
 
         Parser {
             source,
-            line: 0,
-            last: 0,
+            line: source[..last].matches('\n').count(),
+            last,
+
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            deferring: false,
+            looping: false,
+            types: HashSet::new(),
 
             tokens,
 
@@ -107,20 +216,16 @@ impl<'p> Parser<'p> {
     }
 
     /// Advance the `tokens` iterator
-    fn advance(&mut self) -> Token {
+    fn advance(&mut self) -> Token<'p> {
         match self.tokens.next() {
             Some(token) => {
                 self.current = token.clone();
 
-                let mut nl = 0;
-
-                for ch in self.source.chars().skip(self.last) {
-                    if ch == '\n' {
-                        nl += 1
-                    }
-                }
+                /* Only the source between the previous token and this one has to be counted */
+                let start = self.tokens.span().start;
+                self.line += self.source[self.last..start].matches('\n').count();
+                self.last = start;
 
-                self.line += nl;
                 token
             }
             None => {
@@ -130,8 +235,8 @@ impl<'p> Parser<'p> {
         }
     }
 
-    fn get_error_data(&mut self) -> (String, String, String, usize, &str) {
-        let span = self.tokens.span();
+    /// The spacing, pointer, line number and line text that point at `span` of the source.
+    fn get_error_data(&self, span: Range<usize>) -> (String, String, usize, &str) {
         let mut line = 0;
         let lines: Vec<&str> = self.source.split('\n').collect();
         let mut last_n = 0;
@@ -146,83 +251,203 @@ impl<'p> Parser<'p> {
             }
         }
 
-        let line_len = line.to_string().len();
-
         (
-            " ".repeat(line_len),
-            " ".repeat(span.start - last_n),
-            "^".repeat(span.end - span.start),
+            " ".repeat(self.source[last_n..span.start].chars().count()),
+            /* The end of the input has no width, but still gets a pointer */
+            "^".repeat(self.source[span.clone()].chars().count().max(1)),
             line + 1,
             lines[line],
         )
     }
 
     /// Raise an error
-    fn error(&mut self, error: &str) -> ! {
-        let (empty, spacing, pointer, line_idx, line_text) = self.get_error_data();
-        ErrorHandler::error(empty, spacing, pointer, line_idx, line_text, error);
+    fn error(&mut self, code: ErrorCode, error: &str) -> ! {
+        self.error_at(self.tokens.span(), code, error)
+    }
+
+    /// Raise an error pointing at `span` of the source, rather than at the current token
+    fn error_at(&self, span: Range<usize>, code: ErrorCode, error: &str) -> ! {
+        let (spacing, pointer, line_idx, line_text) = self.get_error_data(span);
+        ErrorHandler::error(code, spacing, pointer, line_idx, line_text, error);
     }
 
     /// Raise an error, with a tip
-    fn error_tip(&mut self, error: &str, tip: &str) -> ! {
-        let (empty, spacing, pointer, line_idx, line_text) = self.get_error_data();
-        ErrorHandler::error_tip(empty, spacing, pointer, line_idx, line_text, error, tip);
+    fn error_tip(&mut self, code: ErrorCode, error: &str, tip: &str) -> ! {
+        self.error_tip_at(self.tokens.span(), code, error, tip)
+    }
+
+    /// Raise an error with a tip, pointing at `span` of the source
+    fn error_tip_at(&self, span: Range<usize>, code: ErrorCode, error: &str, tip: &str) -> ! {
+        let (spacing, pointer, line_idx, line_text) = self.get_error_data(span);
+        ErrorHandler::error_tip(code, spacing, pointer, line_idx, line_text, error, tip);
     }
 
     /// Print a warning to the console
+    #[allow(dead_code)]
     fn warn(&mut self, warning: &str) {
-        println!("{}: {}", "Warning".bright_yellow(), warning);
+        Diagnostic::warning(warning).with_span(Span::line(self.line + 1)).emit();
+    }
+
+    /// Raises a targeted error if the current token is a keyword used where a name was expected.
+    fn check_keyword_conflict(&mut self) {
+        if let Some(keyword) = self.current.keyword() {
+            self.error_tip(
+                ErrorCode::ReservedKeyword,
+                &format!("'{}' is a reserved keyword and can't be used as a name.", keyword),
+                &format!("Try a different name, like '{}_'.", keyword),
+            );
+        }
+    }
+
+    /// Raises an error if `name` is one of the builtin constants, which can't be redefined.
+    fn check_constant_conflict(&mut self, name: &str) {
+        if constants().contains_key(name) {
+            self.error_tip(
+                ErrorCode::AssignToConstant,
+                &format!("'{}' is a builtin constant and can't be assigned to.", name),
+                &format!("Try a different name, like '{}'.", name.to_lowercase()),
+            );
+        }
+    }
+
+    /// Raises an error for a token that should've been an identifier.
+    fn expected_identifier(&mut self, error: &str) -> ! {
+        self.check_keyword_conflict();
+        self.error(ErrorCode::ExpectedToken, error);
     }
 
     /// Advances the tokens iterator and checks if the current token is the token specified.
-    fn expect(&mut self, token: Token, exact: bool) -> bool {
+    fn expect(&mut self, token: Token<'p>, exact: bool) -> bool {
         let next = self.advance();
         (exact && next == token) || (!exact && discriminant(&next) == discriminant(&token))
     }
 
     /// Raises an error if self.expect(token, exact) is false.
-    fn expect_handle(&mut self, token: Token, exact: bool, error: &str) {
+    fn expect_handle(&mut self, token: Token<'p>, exact: bool, error: &str) {
         if !self.expect(token, exact) {
-            self.error(error);
+            self.error(ErrorCode::ExpectedToken, error);
+        }
+    }
+
+    /// Calls `parse`, raising `error` if doing so exceeds the maximum nesting depth.
+    fn nested(&mut self, parse: fn(&mut Self) -> Node, error: &str) -> Node {
+        self.depth += 1;
+
+        if self.depth > self.max_depth {
+            self.error(ErrorCode::TooDeeplyNested, error);
+        }
+
+        let node = parse(self);
+        self.depth -= 1;
+        node
+    }
+
+    /// Raises an error if the primitive `name` at `index` can't take `count` arguments.
+    fn check_primitive_arity(&mut self, name: &str, index: usize, count: usize) {
+        let (min, max) = PRIMITIVES[index].arity;
+
+        if count >= min && max.is_none_or(|max| count <= max) {
+            return;
+        }
+
+        let expected = match max {
+            Some(max) if max == min => format!("{}", min),
+            Some(max) => format!("{} to {}", min, max),
+            None => format!("at least {}", min),
+        };
+
+        self.error(
+            ErrorCode::WrongArgumentCount,
+            &format!(
+                "'{}' takes {} argument{}, but {} {} given.",
+                name,
+                expected,
+                if expected == "1" { "" } else { "s" },
+                count,
+                if count == 1 { "was" } else { "were" }
+            ),
+        );
+    }
+
+    /// Whether the `{` after a name starts a struct literal like `Point { x: 1 }`, rather
+    /// than a block like the body of `if ready { ... }`.
+    fn struct_literal_follows(&self) -> bool {
+        let mut tokens = self.tokens.clone();
+        matches!(
+            (tokens.next(), tokens.next()),
+            (Some(Token::Identifier(_)), Some(Token::Colon))
+        )
+    }
+
+    /// Parses the `x: 1, y: 2` of a struct literal or `new`, up to and including `closing`.
+    fn struct_arguments(&mut self, closing: Token<'p>, error: &str) -> Vec<(String, Node)> {
+        let mut arguments: Vec<(String, Node)> = vec![];
+
+        while self.current != closing {
+            let attribute = match self.current {
+                Token::Identifier(iden) => iden,
+                _ => self.expected_identifier("Expected attribute name."),
+            };
+
+            if self.advance() != Token::Colon {
+                self.error(ErrorCode::ExpectedToken, "Expected ':'");
+            }
+
+            self.advance();
+            arguments.push((attribute.to_string(), self.expression()));
+
+            match self.current {
+                Token::Comma => {
+                    self.advance();
+                }
+                _ if self.current == closing => {}
+                _ => self.error(ErrorCode::ExpectedToken, error),
+            }
         }
+
+        self.advance();
+        arguments
     }
 
     /// Parse a value, the smallest part of an expression
     fn value(&mut self) -> Node {
+        if !matches!(self.current, Token::True | Token::False | Token::None | Token::KwNew) {
+            self.check_keyword_conflict();
+        }
+
         let current = self.current.clone();
+        let start = self.tokens.span().start;
         self.advance();
 
         match current {
             Token::Number(num) => Node::new(NodeValue::NumberValue(num), self.line),
             Token::True => Node::new(NodeValue::BoolValue(true), self.line),
             Token::False => Node::new(NodeValue::BoolValue(false), self.line),
-            Token::String(ref str) => Node::new(NodeValue::StringValue(str.to_string()), self.line),
+            Token::String(str) => Node::new(NodeValue::StringValue(string_value(str)), self.line),
+            Token::None => Node::new(NodeValue::NoneValue, self.line),
+            Token::FormattedString(str) => {
+                Node::new(self.formatted_string(str, start), self.line)
+            }
             Token::Identifier(iden) => match self.current {
                 Token::LeftParen => {
                     self.advance();
-                    let mut arguments: Vec<NodeValue> = vec![];
-
-                    if !(self.current == Token::RightParen) {
-                        arguments.push(self.expression().inner);
-
-                        while self.current == Token::Comma {
-                            self.advance();
-
-                            if !(self.current == Token::RightParen) {
-                                arguments.push(self.expression().inner);
-                            }
-                        }
-                    }
-
-                    self.advance();
+                    let arguments = self.arguments();
                     Node {
-                        inner: NodeValue::FunctionCall(iden, arguments),
+                        inner: NodeValue::FunctionCall(iden.to_string(), arguments),
                         line: self.line,
+                        docs: None,
                     }
                 }
+                Token::LeftCurly if self.struct_literal_follows() => {
+                    self.advance();
+                    let error = "Expected ',' or '}'.";
+                    let arguments = self.struct_arguments(Token::RightCurly, error);
+                    Node::new(NodeValue::StructInit(iden.to_string(), arguments), self.line)
+                }
                 _ => Node {
-                    inner: NodeValue::IdentifierValue(iden),
+                    inner: NodeValue::IdentifierValue(iden.to_string()),
                     line: self.line,
+                    docs: None,
                 },
             },
             Token::LeftSquare => {
@@ -244,6 +469,7 @@ impl<'p> Parser<'p> {
                 Node {
                     inner: NodeValue::ArrayValue(elements),
                     line: self.line,
+                    docs: None,
                 }
             }
             Token::LeftParen => {
@@ -251,7 +477,7 @@ impl<'p> Parser<'p> {
 
                 match self.current {
                     Token::RightParen => {}
-                    _ => self.error("Expected ')' after expression."),
+                    _ => self.error(ErrorCode::ExpectedToken, "Expected ')' after expression."),
                 }
 
                 self.advance();
@@ -274,52 +500,42 @@ impl<'p> Parser<'p> {
                         }
                     }
 
-                    let idx: usize = match iden.as_str() {
-                        "print!" => 0,
-                        "exit!" => 1,
-                        _ => unimplemented!(),
+                    let idx = match primitive_index(iden) {
+                        Some(idx) => idx,
+                        None => self.error(
+                            ErrorCode::UnknownPrimitive,
+                            &format!("Unknown primitive function '{}'.", iden),
+                        ),
                     };
 
+                    self.check_primitive_arity(iden, idx, arguments.len());
+
                     self.advance();
                     Node {
                         inner: NodeValue::PrimitiveFunctionCall(idx, arguments),
                         line: self.line,
+                        docs: None,
                     }
                 }
-                _ => self.error("Expected '('")
+                _ => self.error(ErrorCode::ExpectedToken, "Expected '('")
             }
-            Token::KwNew => match self.advance() {
+            Token::KwNew => match self.current {
                 Token::Identifier(typename) => {
-                    let mut arguments: Vec<(String, Node)> = vec![];
+                    self.expect_handle(Token::LeftParen, true, "Expected '('");
                     self.advance();
 
-                    while !(self.advance() == Token::RightParen) {
-                        if let Token::Identifier(ref iden) = self.current {
-                            let iden = iden.clone();
-
-                            if let Token::Colon = self.advance() {
-                                let expression = self.expression();
-                                arguments.push((iden, expression));
-                            } else {
-                                self.error("Expected ':'");
-                            }
-                        } else {
-                            self.error("Expected identifier");
-                        }
-                    }
-
-                    self.advance();
+                    let error = "Expected ',' or ')'.";
+                    let arguments = self.struct_arguments(Token::RightParen, error);
                     Node {
-                        inner: NodeValue::StructInit(typename, arguments),
+                        inner: NodeValue::StructInit(typename.to_string(), arguments),
                         line: self.line,
+                        docs: None,
                     }
                 },
-                _ => self.error("Expected identifier.")
+                _ => self.error(ErrorCode::ExpectedToken, "Expected identifier.")
             }
-            a => {
-                println!("{:?}", a);
-                todo!()
-            },
+            Token::Error => self.error(ErrorCode::InvalidCharacter, "Invalid character."),
+            _ => self.error(ErrorCode::UnexpectedToken, "Unexpected token."),
         }
     }
 
@@ -328,32 +544,147 @@ impl<'p> Parser<'p> {
             Token::OpBang => {
                 self.advance();
                 Node::new(
-                    NodeValue::Unary(Box::new(self.value().inner), Unary::Not),
+                    NodeValue::Unary(Box::new(self.power_expression().inner), Unary::Not),
                     self.line,
                 )
             }
             Token::OpSub => {
                 self.advance();
                 Node::new(
-                    NodeValue::Unary(Box::new(self.unary().inner), Unary::Negate),
+                    NodeValue::Unary(
+                        Box::new(self.nested(Self::unary, "Expression too deeply nested.").inner),
+                        Unary::Negate,
+                    ),
                     self.line,
                 )
             }
             Token::KwTypeof => {
                 self.advance();
                 Node::new(
-                    NodeValue::Unary(Box::new(self.unary().inner), Unary::Typeof),
+                    NodeValue::Unary(
+                        Box::new(self.nested(Self::unary, "Expression too deeply nested.").inner),
+                        Unary::Typeof,
+                    ),
                     self.line,
                 )
             }
-            _ => self.value(),
+            _ => self.power_expression(),
+        }
+    }
+
+    /// Parses a value followed by any attribute accesses, like `a.b.c` or `a.b()`.
+    fn postfix(&mut self) -> Node {
+        let start = self.tokens.span().start;
+        let mut node = self.value();
+
+        loop {
+            match self.current {
+                Token::Period => {
+                    let period = self.tokens.span();
+
+                    let attribute = match self.advance() {
+                        Token::Identifier(name) => name.to_string(),
+                        /* `1.` scans as `1` and `.`, see `Token::Number` */
+                        _ if matches!(node.inner, NodeValue::NumberValue(_))
+                            && self.source[start..period.start].parse::<f64>().is_ok() =>
+                        {
+                            self.trailing_dot(start..period.end)
+                        }
+                        _ => self.expected_identifier("Expected attribute name after '.'."),
+                    };
+
+                    self.advance();
+                    node.inner = NodeValue::GetAttribute(Box::new(node.inner), attribute);
+
+                    if self.current == Token::LeftParen {
+                        self.advance();
+                        let arguments = self.arguments();
+                        node.inner = NodeValue::Call(Box::new(node.inner), arguments);
+                    }
+                }
+                Token::LeftSquare => {
+                    self.advance();
+                    node.inner = self.index(node.inner);
+                }
+                _ => return node,
+            }
         }
     }
 
-    fn from_builder(&mut self, builder: &str) -> Node {
+    /// Raises an error for the number literal at `span`, which ends with a '.'.
+    fn trailing_dot(&self, span: Range<usize>) -> ! {
+        let literal = &self.source[span.clone()];
+        let number = &literal[..literal.len() - 1];
+
+        /* A number that already has a fraction or an exponent only needs the '.' removed */
+        let message = if number.contains(['.', 'e', 'E']) {
+            format!("Numbers can't end with '.', write '{}' instead of '{}'.", number, literal)
+        } else {
+            format!(
+                "Numbers can't end with '.', write '{}.0' or '{}' instead of '{}'.",
+                number, number, literal
+            )
+        };
+
+        self.error_at(span, ErrorCode::ExpectedToken, &message)
+    }
+
+    /// Parses `[i]`, `[a..b]`, `[a..]`, `[..b]` or `[..]` following `value`,
+    /// from the token after '[' up to and including ']'. Slices may also be written with
+    /// a ':' instead of '..', like `[a:b]`.
+    fn index(&mut self, value: NodeValue) -> NodeValue {
+        let start = match self.current {
+            Token::Range | Token::Colon => None,
+            _ => Some(Box::new(self.expression().inner)),
+        };
+
+        let node = match (start, &self.current) {
+            (start, Token::Range | Token::Colon) => {
+                let end = match self.advance() {
+                    Token::RightSquare => None,
+                    _ => Some(Box::new(self.expression().inner)),
+                };
+
+                NodeValue::Slice(Box::new(value), start, end)
+            }
+            (Some(index), _) => NodeValue::Index(Box::new(value), index),
+            (None, _) => unreachable!("an index without a start is always a slice"),
+        };
+
+        if self.current != Token::RightSquare {
+            self.error(ErrorCode::ExpectedToken, "Expected ']'.");
+        }
+
+        self.advance();
+        node
+    }
+
+    /// Parses the arguments of a call, from the token after '(' up to and including ')'.
+    fn arguments(&mut self) -> Vec<NodeValue> {
+        let mut arguments = vec![];
+
+        while self.current != Token::RightParen {
+            arguments.push(self.expression().inner);
+
+            match self.current {
+                Token::Comma => {
+                    self.advance();
+                }
+                Token::RightParen => {}
+                _ => self.error(ErrorCode::ExpectedToken, "Expected ',' or ')'."),
+            }
+        }
+
+        self.advance();
+        arguments
+    }
+
+    fn parse_builder(&mut self, builder: &str) -> Node {
         match builder {
             "unary" => self.unary(),
             "additive" => self.additive_expression(),
+            "shift" => self.shift_expression(),
+            "cast" => self.cast_expression(),
             "comparison" => self.comparison_expression(),
             "multiplicative" => self.multiplicative_expression(),
             "bitwise_or" => self.bitwise_expression_1(),
@@ -367,106 +698,357 @@ impl<'p> Parser<'p> {
        `builder` -> the function you want to use to parse the left and right sides
        `operators` -> the operators you recognize on this precedence level
     */
-    fn binary_expression(&mut self, builder: &str, operators: Vec<Token>) -> Node {
-        let mut left = self.from_builder(builder);
+    fn binary_expression(&mut self, builder: &str, operators: &[Token<'static>]) -> Node {
+        let mut left = self.parse_builder(builder);
 
         while operators.contains(&self.current) {
-            let operator = self.current.clone();
+            let operator = self.current.operator().unwrap();
             self.advance();
 
-            let right = self.from_builder(builder);
+            let right = self.parse_builder(builder);
 
             left = Node {
                 inner: NodeValue::Binary(Box::new(left.inner), Box::new(right.inner), operator),
                 line: 0,
+                docs: None,
             };
         }
 
         left
     }
 
+    /// Parses `cond ? a : b`, which is right-associative and binds looser than `and`/`or`.
+    fn conditional_expression(&mut self) -> Node {
+        let condition = self.logical_expression();
+
+        if self.current != Token::Que {
+            return condition;
+        }
+
+        self.advance();
+        let ontrue = self.expression();
+
+        if self.current != Token::Colon {
+            self.error(ErrorCode::ExpectedToken, "Expected ':' in conditional expression.");
+        }
+
+        self.advance();
+        let onfalse = self.expression();
+
+        Node {
+            inner: NodeValue::Conditional(
+                Box::new(condition.inner),
+                Box::new(ontrue.inner),
+                Box::new(onfalse.inner),
+            ),
+            line: 0,
+            docs: None,
+        }
+    }
+
     fn logical_expression(&mut self) -> Node {
-        self.binary_expression("comparison", vec![Token::KwAnd, Token::KwOr])
+        self.binary_expression("comparison", LOGICAL_OPERATORS)
     }
 
     fn comparison_expression(&mut self) -> Node {
-        self.binary_expression(
-            "additive",
-            vec![
-                Token::OpEq,
-                Token::OpBangEq,
-                Token::OpLess,
-                Token::OpMore,
-                Token::OpMoreEq,
-                Token::OpLessEq,
-            ],
-        )
+        self.binary_expression("shift", COMPARISON_OPERATORS)
     }
 
     // The highest level of a bitwise operation, scans only for bitwise OR
     fn bitwise_expression_1(&mut self) -> Node {
-        self.binary_expression("bitwise_xor", vec![Token::BitwiseOr])
+        self.binary_expression("bitwise_xor", BITWISE_OR_OPERATORS)
     }
 
     // The second highest level of a bitwise operation, scans only for bitwise XOR
     fn bitwise_expression_2(&mut self) -> Node {
-        self.binary_expression("bitwise_and", vec![Token::BitwiseXor])
+        self.binary_expression("bitwise_and", BITWISE_XOR_OPERATORS)
     }
 
     // The lowest level of a bitwise operation, scans only for bitwise AND
     fn bitwise_expression_3(&mut self) -> Node {
-        self.binary_expression("comparison", vec![Token::BitwiseAnd])
+        self.binary_expression("comparison", BITWISE_AND_OPERATORS)
+    }
+
+    fn shift_expression(&mut self) -> Node {
+        self.binary_expression("additive", SHIFT_OPERATORS)
     }
 
     fn additive_expression(&mut self) -> Node {
-        self.binary_expression("multiplicative", vec![Token::OpAdd, Token::OpSub])
+        self.binary_expression("multiplicative", ADDITIVE_OPERATORS)
     }
 
     fn multiplicative_expression(&mut self) -> Node {
-        self.binary_expression(
-            "unary",
-            vec![
-                Token::OpMul,
-                Token::OpDiv,
-                Token::OpMod,
-                Token::OpPow,
-                Token::OpLeftShift,
-                Token::OpRightShift,
-            ],
+        self.binary_expression("cast", MULTIPLICATIVE_OPERATORS)
+    }
+
+    /// Parses `a ** b`, which binds tighter than `*` and unary operators, and is
+    /// right-associative, so `2 ** 3 ** 2` is `2 ** 9` and `-2 ** 2` is `-(2 ** 2)`.
+    /// The exponent can be negated, like `2 ** -1`.
+    fn power_expression(&mut self) -> Node {
+        let base = self.postfix();
+
+        if self.current != Token::OpPow {
+            return base;
+        }
+
+        self.advance();
+        let exponent = self.nested(Self::unary, "Expression too deeply nested.");
+
+        Node {
+            inner: NodeValue::Binary(Box::new(base.inner), Box::new(exponent.inner), Token::OpPow),
+            line: 0,
+            docs: None,
+        }
+    }
+
+    /// Turns a formatted string like `` `{name} is {age}` `` into the concatenation of its text
+    /// and its placeholders, each converted to a string. `\\{` and `\\}` are literal braces.
+    /// `start` is where the literal starts in the source. Placeholders are parsed in place, so
+    /// their errors point into the source like any other.
+    fn formatted_string(&mut self, literal: &str, start: usize) -> NodeValue {
+        let mut parts = vec![];
+        let mut text = String::new();
+
+        /* Byte offsets into the source, between the backticks */
+        let end = start + literal.len() - 1;
+        let mut idx = start + 1;
+
+        while let Some(c) = self.source[idx..end].chars().next() {
+            idx += c.len_utf8();
+
+            match c {
+                '\\' => match self.source[idx..end].chars().next() {
+                    Some(brace @ ('{' | '}')) => {
+                        text.push(brace);
+                        idx += 1;
+                    }
+                    Some(c) => {
+                        text.extend(['\\', c]);
+                        idx += c.len_utf8();
+                    }
+                    None => text.push('\\'),
+                },
+                '{' => {
+                    let close = self.placeholder_end(idx, end);
+
+                    let mut tokens = Token::lexer(&self.source[..close]);
+                    tokens.bump(idx);
+
+                    let mut parser = Parser::new(tokens, self.source);
+                    parser.depth = self.depth;
+                    parser.max_depth = self.max_depth;
+
+                    if parser.current == Token::End {
+                        self.error_at(
+                            idx - 1..close + 1,
+                            ErrorCode::ExpectedToken,
+                            "Expected an expression inside '{}'.",
+                        );
+                    }
+
+                    let value = parser.expression().inner;
+                    if parser.current != Token::End {
+                        parser.error(ErrorCode::ExpectedToken, "Expected '}' after expression.");
+                    }
+
+                    parts.push(NodeValue::StringValue(unescape(&std::mem::take(&mut text))));
+                    parts.push(NodeValue::Convert(Box::new(value), Type::String));
+                    idx = close + 1;
+                }
+                '}' => self.error_tip_at(
+                    idx - 1..idx,
+                    ErrorCode::UnexpectedToken,
+                    "Unmatched '}' in formatted string.",
+                    "Write '\\}' for a literal brace.",
+                ),
+                c => text.push(c),
+            }
+        }
+
+        parts.push(NodeValue::StringValue(unescape(&text)));
+        parts
+            .into_iter()
+            .filter(|part| !matches!(part, NodeValue::StringValue(text) if text.is_empty()))
+            .reduce(|left, right| NodeValue::Binary(Box::new(left), Box::new(right), Token::OpAdd))
+            .unwrap_or(NodeValue::StringValue(String::new()))
+    }
+
+    /// Where the placeholder starting at `start` of the source ends, which is the `}` that
+    /// closes it before `end`. The placeholder is scanned, so braces inside its strings and
+    /// blocks don't end it.
+    fn placeholder_end(&self, start: usize, end: usize) -> usize {
+        let mut tokens = Token::lexer(&self.source[..end]);
+        tokens.bump(start);
+        let mut depth = 0;
+
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::LeftCurly => depth += 1,
+                Token::RightCurly if depth == 0 => return tokens.span().start,
+                Token::RightCurly => depth -= 1,
+                _ => {}
+            }
+        }
+
+        self.error_at(
+            start - 1..start,
+            ErrorCode::ExpectedToken,
+            "Expected '}' to close the placeholder.",
         )
     }
 
+    /// Parses `value as type`, which binds tighter than any binary operator.
+    fn cast_expression(&mut self) -> Node {
+        let mut value = self.unary();
+
+        while self.current == Token::KwAs {
+            self.advance();
+            let datatype = self.parse_type();
+
+            if let Type::Array(_) | Type::Struct(_) = datatype {
+                self.error(
+                    ErrorCode::UnknownType,
+                    "Values can only be converted to number, string or bool.",
+                );
+            }
+
+            value = Node {
+                inner: NodeValue::Convert(Box::new(value.inner), datatype),
+                line: 0,
+                docs: None,
+            };
+        }
+
+        value
+    }
+
     #[inline(always)]
     fn expression(&mut self) -> Node {
-        self.logical_expression()
+        self.nested(Self::conditional_expression, "Expression too deeply nested.")
     }
 
     fn parse_type(&mut self) -> Type {
+        /* `[number]` is an array of numbers */
+        if self.current == Token::LeftSquare {
+            self.advance();
+            let element = self.parse_type();
+
+            if self.current != Token::RightSquare {
+                self.error(ErrorCode::ExpectedToken, "Expected ']' after the element type.");
+            }
+
+            self.advance();
+            return Type::Array(Box::new(element));
+        }
+
         if let Token::Identifier(_type) = &self.current {
-            let datatype = match _type.clone().as_str() {
+            let datatype = match *_type {
                 "number" => Type::Number,
                 "bool" => Type::Bool,
                 "string" => Type::String,
-                _ => self.error("Unknown type."),
+                name if self.types.contains(name) => Type::Struct(name.to_string()),
+                _ => self.error(ErrorCode::UnknownType, "Unknown type."),
             };
 
             self.advance();
-            return datatype;
+            datatype
         } else {
-            self.error("Expected Identifier");
+            self.error(ErrorCode::ExpectedToken, "Expected Identifier");
+        }
+    }
+
+    /// Parses the names inside `[...]` or `{...}`, up to and including the closing bracket.
+    fn pattern(&mut self) -> Pattern {
+        let (closing, is_array) = match self.current {
+            Token::LeftSquare => (Token::RightSquare, true),
+            _ => (Token::RightCurly, false),
+        };
+
+        let mut names = vec![];
+        let mut rest = None;
+        self.advance();
+
+        while self.current != closing {
+            let name = match self.current {
+                Token::Identifier(name) => name.to_string(),
+                _ => self.expected_identifier("Expected identifier in pattern."),
+            };
+
+            if is_array && self.advance() == Token::Ellipsis {
+                self.advance();
+                rest = Some(name);
+
+                if self.current != closing {
+                    self.error(
+                        ErrorCode::ExpectedToken,
+                        "Expected ']', the rest of an array must be last.",
+                    );
+                }
+
+                break;
+            } else if !is_array {
+                self.advance();
+            }
+
+            names.push(name);
+
+            match self.current {
+                Token::Comma => {
+                    self.advance();
+                }
+                _ if self.current == closing => {}
+                _ => self.error(ErrorCode::ExpectedToken, "Expected ',' in pattern."),
+            }
+        }
+
+        self.advance();
+
+        if is_array {
+            Pattern::Array(names, rest)
+        } else {
+            Pattern::Struct(names)
+        }
+    }
+
+    /// Parses `let [a, b] = value` or `let {x, y} = value`.
+    fn destructuring_decleration(&mut self) -> Node {
+        let pattern = self.pattern();
+
+        let names = match &pattern {
+            Pattern::Array(names, rest) => names.iter().chain(rest).cloned().collect(),
+            Pattern::Struct(names) => names.clone(),
         };
+
+        for name in names {
+            self.check_constant_conflict(&name);
+        }
+
+        if self.current != Token::Assign {
+            self.error(ErrorCode::ExpectedToken, "Expected '='");
+        }
+
+        self.advance();
+        let value = self.expression();
+
+        Node::new(NodeValue::Destructure(pattern, Box::new(value.inner)), self.line)
     }
 
     fn variable_decleration(&mut self, public: bool) -> Node {
-        let (is_mutable, name) = match self.advance() {
+        if let Token::LeftSquare | Token::LeftCurly = self.advance() {
+            return self.destructuring_decleration();
+        }
+
+        let (is_mutable, name) = match self.current {
             Token::KwMut => match self.advance() {
                 Token::Identifier(iden) => (true, iden),
-                _ => self.error("Expected Identifier after 'mut'"),
+                _ => self.expected_identifier("Expected Identifier after 'mut'"),
             },
             Token::Identifier(name_) => (false, name_),
-            _ => self.error("Expected either 'mut' or Identifier."),
+            _ => self.expected_identifier("Expected either 'mut' or Identifier."),
         };
 
+        self.check_constant_conflict(name);
+
         let datatype = match self.advance() {
             Token::Colon => {
                 self.advance();
@@ -477,45 +1059,46 @@ impl<'p> Parser<'p> {
                         self.advance();
                     }
                     _ => {
-                        self.error("Expected '='");
+                        self.error(ErrorCode::ExpectedToken, "Expected '='");
                     }
                 }
 
                 dt
             }
-            _ => self.error("Expected ':'"),
+            _ => self.error(ErrorCode::ExpectedToken, "Expected ':'"),
         };
 
         let value = self.expression();
 
         Node {
             inner: NodeValue::VariableDecleration(
-                name,
+                name.to_string(),
                 Box::new(value.inner),
                 public,
                 is_mutable,
                 datatype,
             ),
             line: self.line,
+            docs: None,
         }
     }
 
     fn variable_assignment(&mut self, name: String) -> Node {
+        self.check_constant_conflict(&name);
         self.advance();
         let value = self.expression();
 
         Node {
             inner: NodeValue::VariableAssignment(name, Box::new(value.inner)),
             line: self.line,
+            docs: None,
         }
     }
 
     fn function_decleration(&mut self, public: bool) -> Node {
-        self.expect_handle(
-            Token::Identifier("".to_string()),
-            false,
-            "Expected identifier",
-        );
+        if !self.expect(Token::Identifier(""), false) {
+            self.expected_identifier("Expected identifier");
+        }
 
         let name = self.current.clone();
         let mut params: Vec<Parameter> = vec![];
@@ -535,26 +1118,24 @@ impl<'p> Parser<'p> {
                             match self.advance() {
                                 Token::Identifier(str) => {
                                     self.advance();
-                                    str.clone()
+                                    str
                                 }
-                                _ => self.error("Expected identifier"),
+                                _ => self.expected_identifier("Expected identifier"),
                             },
                             true,
                         ),
                         _ => panic!(),
                     };
 
-                    println!("{:?}", self.current);
-
                     if self.current != Token::Colon {
-                        self.error("Expected ':' after parameter name.");
+                        self.error(ErrorCode::ExpectedToken, "Expected ':' after parameter name.");
                     }
 
                     self.advance();
                     let datatype = self.parse_type();
 
                     let param = Parameter {
-                        name,
+                        name: name.to_string(),
                         mutable,
                         datatype,
                     };
@@ -565,7 +1146,7 @@ impl<'p> Parser<'p> {
                 Token::Comma => {
                     self.advance();
                 }
-                _ => self.error("Expected either `mut` or identifier."),
+                _ => self.expected_identifier("Expected either `mut` or identifier."),
             }
         }
 
@@ -585,14 +1166,17 @@ impl<'p> Parser<'p> {
                         Token::LeftCurly => {
                             self.advance();
                         }
-                        _ => self.error("Expected '{'"),
+                        _ => self.error(ErrorCode::ExpectedToken, "Expected '{'"),
                     }
 
                     return_type
                 }
-                _ => self.error("Expected ':' or '{'"),
+                _ => self.error(ErrorCode::ExpectedToken, "Expected ':' or '{'"),
             };
 
+            /* A function declared in a `defer` block or a loop returns on its own */
+            let deferring = std::mem::replace(&mut self.deferring, false);
+            let looping = std::mem::replace(&mut self.looping, false);
             let mut body = Vec::new();
 
             while self.current != Token::RightCurly {
@@ -600,25 +1184,132 @@ impl<'p> Parser<'p> {
             }
 
             self.advance();
+            self.deferring = deferring;
+            self.looping = looping;
 
             Node {
-                inner: NodeValue::FunctionDecleration(name, body, params, public, return_type),
+                inner: NodeValue::FunctionDecleration(
+                    name.to_string(),
+                    body,
+                    params,
+                    public,
+                    return_type,
+                ),
                 line: self.line,
+                docs: None,
             }
         } else {
-            self.error("Expected function name");
+            self.error(ErrorCode::ExpectedToken, "Expected function name");
+        }
+    }
+
+    /// Parses the `let name = value` of an `if let` or `while let`, returning the declaration
+    /// and a condition that is true if the value isn't `none`.
+    /// A `where` guard after the value is added to the condition, and only evaluated once the
+    /// value is known not to be `none`, so `if let x = value where x > 10` never compares none.
+    fn let_condition(&mut self) -> (Node, NodeValue) {
+        let name = match self.advance() {
+            Token::Identifier(name) => name.to_string(),
+            _ => self.expected_identifier("Expected Identifier after 'let'."),
+        };
+
+        self.check_constant_conflict(&name);
+
+        if self.advance() != Token::Assign {
+            self.error(ErrorCode::ExpectedToken, "Expected '='");
+        }
+
+        self.advance();
+        let value = self.expression();
+
+        let mut condition = NodeValue::Binary(
+            Box::new(NodeValue::IdentifierValue(name.clone())),
+            Box::new(NodeValue::NoneValue),
+            Token::OpBangEq,
+        );
+
+        if self.current == Token::KwWhere {
+            self.advance();
+            let guard = self.expression();
+
+            condition = NodeValue::Conditional(
+                Box::new(condition),
+                Box::new(guard.inner),
+                Box::new(NodeValue::BoolValue(false)),
+            );
+        }
+
+        let decleration = NodeValue::VariableDecleration(
+            name,
+            Box::new(value.inner),
+            false,
+            true,
+            Type::Void,
+        );
+
+        (Node::new(decleration, self.line), condition)
+    }
+
+    /// Wraps `body` in an `if true { ... }`, so the variables it declares are scoped to it.
+    fn scoped_statements(&self, body: Vec<Node>) -> Node {
+        Node::new(
+            NodeValue::If((Box::new(NodeValue::BoolValue(true)), body), vec![], None),
+            self.line,
+        )
+    }
+
+    /// Parses the `{ ... }` block of an if statement or one of its branches.
+    fn if_block(&mut self) -> Vec<Node> {
+        if self.current != Token::LeftCurly {
+            self.error(ErrorCode::ExpectedToken, "Expected '{' after if statement.");
+        }
+
+        self.advance();
+        let mut body: Vec<Node> = vec![];
+
+        while self.current != Token::RightCurly {
+            body.push(self.statement());
+        }
+
+        self.advance();
+        body
+    }
+
+    /// Parses a `{ ... }` block on its own, whose variables are only visible inside it.
+    fn block_statement(&mut self) -> Node {
+        self.advance();
+        let mut body: Vec<Node> = vec![];
+
+        while self.current != Token::RightCurly {
+            body.push(self.statement());
         }
+
+        self.advance();
+        self.scoped_statements(body)
     }
 
     fn while_statement(&mut self) -> Node {
         self.advance();
-        let condition = self.expression();
+
+        /* `while let x = value { ... }` becomes
+            `let x = value; while x != none { ...; x = value }` */
+        let binding = if self.current == Token::KwLet {
+            Some(self.let_condition())
+        } else {
+            None
+        };
+
+        let condition = match &binding {
+            Some((_, condition)) => Node::new(condition.clone(), self.line),
+            None => self.expression(),
+        };
 
         if self.current != Token::LeftCurly {
-            self.error("Expected '{' after while statement.");
+            self.error(ErrorCode::ExpectedToken, "Expected '{' after while statement.");
         }
 
         self.advance();
+        let looping = std::mem::replace(&mut self.looping, true);
         let mut body: Vec<Node> = vec![];
 
         while self.current != Token::RightCurly {
@@ -626,83 +1317,96 @@ impl<'p> Parser<'p> {
         }
 
         self.advance();
+        self.looping = looping;
+
+        if let Some((decleration, _)) = binding {
+            if let NodeValue::VariableDecleration(name, value, ..) = &decleration.inner {
+                body.push(Node::new(
+                    NodeValue::VariableAssignment(name.clone(), value.clone()),
+                    self.line,
+                ));
+            }
+
+            let statement =
+                Node::new(NodeValue::WhileStatement(Box::new(condition.inner), body), self.line);
+
+            return self.scoped_statements(vec![decleration, statement]);
+        }
 
         Node {
             inner: NodeValue::WhileStatement(Box::new(condition.inner), body),
             line: self.line,
+            docs: None,
         }
     }
 
     fn import_statement(&mut self) -> Node {
-        self.expect(Token::String(String::new()), false);
+        self.expect(Token::String(""), false);
 
         if let Token::String(path) = self.current.clone() {
             self.expect(Token::KwAs, true);
-            self.expect(Token::Identifier(String::new()), false);
+            self.expect(Token::Identifier(""), false);
 
             if let Token::Identifier(name) = self.current.clone() {
                 self.advance();
                 Node {
-                    inner: NodeValue::ImportStatement(path, name),
+                    inner: NodeValue::ImportStatement(string_value(path), name.to_string()),
                     line: self.line,
+                    docs: None,
                 }
             } else {
-                self.error("Expected path to file.");
+                self.expected_identifier("Expected identifier after 'as'.");
             }
         } else {
-            self.error("Expected path to file.");
+            self.error(ErrorCode::ExpectedToken, "Expected path to file.");
         }
     }
 
     fn if_statement(&mut self) -> Node {
-        self.advance();
-        let condition = self.expression();
+        /* `if let x = value { ... }` becomes `let x = value; if x != none { ... }` */
+        if self.advance() == Token::KwLet {
+            let (decleration, condition) = self.let_condition();
+            let statement = self.if_body(Node::new(condition, self.line));
 
-        if self.current != Token::LeftCurly {
-            self.error("Expected '{' after if statement.");
+            return self.scoped_statements(vec![decleration, statement]);
         }
 
-        self.advance();
-        let mut body: Vec<Node> = vec![];
-
-        while self.current != Token::RightCurly {
-            body.push(self.statement());
-        }
+        let condition = self.expression();
+        self.if_body(condition)
+    }
 
-        self.advance();
+    /// Parses everything of an if statement after its condition.
+    /// `else if`s are parsed in a loop rather than recursively, so a long chain of them
+    /// can't overflow the stack.
+    fn if_body(&mut self, condition: Node) -> Node {
+        let body = self.if_block();
 
         let mut else_body: Vec<Node> = vec![];
         let mut else_if_bodies: Vec<ConditionalBlock> = vec![];
 
-        if self.current == Token::KwElse {
+        while self.current == Token::KwElse {
             self.advance();
 
             match self.current {
                 Token::LeftCurly => {
-                    self.advance();
-
-                    while self.current != Token::RightCurly {
-                        else_body.push(self.statement());
-                    }
-
-                    self.advance();
+                    else_body = self.if_block();
+                    break;
                 }
                 Token::KwIf => {
-                    let statement = self.if_statement();
-
-                    if let NodeValue::If(_if, elseif, _else) = statement.inner {
-                        else_if_bodies.push(_if);
-
-                        for body in elseif {
-                            else_if_bodies.push(body);
-                        }
-
-                        if let Option::Some(code) = _else {
-                            else_body = code;
-                        }
+                    /* The rest of the chain belongs to the `if let`, in the scope of its binding */
+                    if self.advance() == Token::KwLet {
+                        let (decleration, condition) = self.let_condition();
+                        let statement = self.if_body(Node::new(condition, self.line));
+                        let scoped = vec![decleration, statement];
+
+                        else_if_bodies.push((Box::new(NodeValue::BoolValue(true)), scoped));
+                        break;
                     }
+
+                    let condition = self.expression();
+                    else_if_bodies.push((Box::new(condition.inner), self.if_block()));
                 }
-                _ => self.error("Expected '{' or 'if'."),
+                _ => self.error(ErrorCode::ExpectedToken, "Expected '{' or 'if'."),
             }
         }
 
@@ -715,21 +1419,140 @@ impl<'p> Parser<'p> {
         Node {
             inner: NodeValue::If((Box::new(condition.inner), body), else_if_bodies, else_body),
             line: self.line,
+            docs: None,
         }
     }
 
     fn return_statement(&mut self) -> Node {
+        if self.deferring {
+            self.error(ErrorCode::InvalidDefer, "Can't return from a 'defer' block.");
+        }
+
         self.advance();
-        let value = self.expression();
+
+        /* A `return` that ends its block returns none */
+        let value = match self.current {
+            Token::RightCurly | Token::End => NodeValue::NoneValue,
+            _ => self.expression().inner,
+        };
 
         Node {
-            inner: NodeValue::Return(Box::new(value.inner)),
+            inner: NodeValue::Return(Box::new(value)),
             line: self.line,
+            docs: None,
         }
     }
 
+    fn defer_statement(&mut self) -> Node {
+        if self.deferring {
+            self.error(ErrorCode::InvalidDefer, "Can't defer inside a 'defer' block.");
+        }
+
+        if self.looping {
+            self.error_tip(
+                ErrorCode::InvalidDefer,
+                "Can't defer inside a loop.",
+                "Move the 'defer' before the loop, or the loop into a function.",
+            );
+        }
+
+        self.expect_handle(Token::LeftCurly, true, "Expected '{' after 'defer'.");
+        self.advance();
+
+        self.deferring = true;
+        let mut body: Vec<Node> = vec![];
+
+        while self.current != Token::RightCurly {
+            body.push(self.statement());
+        }
+
+        self.advance();
+        self.deferring = false;
+
+        Node::new(NodeValue::Defer(body), self.line)
+    }
+
+    /// Parses `struct Point { x: number, y: number }`, which declares a type whose attributes
+    /// start out as the zero value of their type, like `0` or `""`.
+    fn struct_decleration(&mut self) -> Node {
+        let name = match self.advance() {
+            Token::Identifier(name) => name.to_string(),
+            _ => self.expected_identifier("Expected Identifier"),
+        };
+
+        self.expect_handle(Token::LeftCurly, true, "Expected '{'");
+        self.advance();
+
+        /* Declared before the attributes are parsed, so a struct can contain itself */
+        self.types.insert(name.clone());
+        let mut attributes: HashMap<String, NodeValue> = HashMap::new();
+
+        while self.current != Token::RightCurly {
+            let attribute = match self.current {
+                Token::Identifier(attribute) => attribute.to_string(),
+                _ => self.expected_identifier("Expected attribute name."),
+            };
+
+            if self.advance() != Token::Colon {
+                self.error(ErrorCode::ExpectedToken, "Expected ':'");
+            }
+
+            self.advance();
+            let datatype = self.parse_type();
+
+            match self.current {
+                Token::Comma => {
+                    self.advance();
+                }
+                Token::RightCurly | Token::Identifier(_) => {}
+                _ => self.error(ErrorCode::ExpectedToken, "Expected ',' or '}'."),
+            }
+
+            let value = Box::new(zero_value(&datatype));
+            let decleration =
+                NodeValue::VariableDecleration(attribute.clone(), value, false, true, datatype);
+            attributes.insert(attribute, decleration);
+        }
+
+        self.advance();
+        Node::new(NodeValue::TypeDecleration(name, vec![], attributes), self.line)
+    }
+
+    /// Parses `object.attribute = value`, after `object.attribute`.
+    fn attribute_assignment(&mut self, target: NodeValue) -> Node {
+        let (object, attribute) = match target {
+            NodeValue::GetAttribute(object, attribute) => (object, attribute),
+            _ => unreachable!("only attributes are assigned to"),
+        };
+
+        /* The updated value is stored back into the variable, so there has to be one */
+        let mut root = &*object;
+
+        while let NodeValue::GetAttribute(inner, _) = root {
+            root = inner;
+        }
+
+        if !matches!(root, NodeValue::IdentifierValue(_)) {
+            self.error(
+                ErrorCode::UnexpectedToken,
+                "Only attributes of variables can be assigned to.",
+            );
+        }
+
+        self.advance();
+        let value = self.expression();
+
+        Node::new(
+            NodeValue::AttributeAssignment(object, attribute, Box::new(value.inner)),
+            self.line,
+        )
+    }
+
     fn type_decleration(&mut self) -> Node {
-        self.expect_handle(Token::Identifier(String::new()), false, "Expected Identifier");
+        if !self.expect(Token::Identifier(""), false) {
+            self.expected_identifier("Expected Identifier");
+        }
+
         let name = self.current.clone();
         self.expect_handle(Token::LeftCurly, true, "Expected '{'");
         self.advance();
@@ -744,9 +1567,9 @@ impl<'p> Parser<'p> {
                 Token::KwPub => match self.advance() {
                     Token::KwLet => self.variable_decleration(true),
                     Token::KwFn => self.function_decleration(true),
-                    _ => self.error("Expected 'let' or 'fn' after 'pub'"),
+                    _ => self.error(ErrorCode::ExpectedToken, "Expected 'let' or 'fn' after 'pub'"),
                 },
-                _ => self.error("Unexpected token")
+                _ => self.error(ErrorCode::UnexpectedToken, "Unexpected token")
             };
 
             match node.inner {
@@ -763,29 +1586,226 @@ impl<'p> Parser<'p> {
         self.advance();
 
         if let Token::Identifier(name) = name {
-            return Node {
-                inner: NodeValue::TypeDecleration(name, functions, variables),
+            self.types.insert(name.to_string());
+
+            Node {
+                inner: NodeValue::TypeDecleration(name.to_string(), functions, variables),
                 line: self.line,
-            };
+                docs: None,
+            }
         } else {
             panic!()
         }
     }
 
+    /// Parses an operand of an `asm` instruction that has to be a number.
+    fn asm_number(&mut self) -> usize {
+        match self.advance() {
+            Token::Number(number) if number.fract() == 0.0 && number >= 0.0 => number as usize,
+            _ => self.error(ErrorCode::InvalidAssembly, "Expected a whole number operand."),
+        }
+    }
+
+    /// Parses an operand of an `asm` instruction that has to be a name.
+    fn asm_name(&mut self) -> String {
+        match self.advance() {
+            Token::Identifier(name) => name.to_string(),
+            _ => self.error(ErrorCode::InvalidAssembly, "Expected a name operand."),
+        }
+    }
+
+    /// Parses the value pushed by `push`, which is a literal or the name of a builtin constant.
+    fn asm_value(&mut self) -> HlvmValue {
+        match self.advance() {
+            Token::Number(number) => HlvmValue::Number(number),
+            Token::OpSub => match self.advance() {
+                Token::Number(number) => HlvmValue::Number(-number),
+                _ => self.error(ErrorCode::InvalidAssembly, "Expected a number after '-'."),
+            },
+            Token::String(str) => HlvmValue::String(string_value(str)),
+            Token::True => HlvmValue::Bool(true),
+            Token::False => HlvmValue::Bool(false),
+            Token::None => HlvmValue::None,
+            Token::Identifier(name) => match constants().remove(name) {
+                Some(value) => value,
+                None => self.error(
+                    ErrorCode::InvalidAssembly,
+                    &format!("'{}' isn't a builtin constant.", name),
+                ),
+            },
+            _ => self.error(ErrorCode::InvalidAssembly, "Expected a value to push."),
+        }
+    }
+
+    /// Parses `asm { ... }`, a block of raw instructions like `push 1 get x add`.
+    /// The block is verified here, since the VM trusts the instructions it's given.
+    fn asm_block(&mut self) -> Node {
+        self.expect_handle(Token::LeftCurly, true, "Expected '{' after 'asm'.");
+        let mut block = vec![];
+
+        loop {
+            let mnemonic = match self.advance() {
+                Token::RightCurly => break,
+                Token::Identifier(mnemonic) => mnemonic,
+                token => match token.keyword() {
+                    Some(mnemonic) => mnemonic,
+                    None => self.error(ErrorCode::InvalidAssembly, "Expected an instruction."),
+                },
+            };
+
+            let instruction = match mnemonic {
+                "push" => HlvmInstruction::Push(self.asm_value()),
+                "call" => HlvmInstruction::Call(self.asm_number()),
+                "call_primitive" => {
+                    let index = match self.advance() {
+                        Token::PrimitiveFnIdentifier(name) => match primitive_index(name) {
+                            Some(index) => index,
+                            None => self.error(
+                                ErrorCode::UnknownPrimitive,
+                                &format!("Unknown primitive function '{}'.", name),
+                            ),
+                        },
+                        _ => self.error(
+                            ErrorCode::InvalidAssembly,
+                            "Expected a primitive function, like 'print!'.",
+                        ),
+                    };
+
+                    HlvmInstruction::CallPrimitive(index, self.asm_number())
+                }
+                "return" => HlvmInstruction::Return,
+                "return_value" => HlvmInstruction::ReturnValue,
+                "get" => HlvmInstruction::Get(self.asm_name()),
+                "get_local" => HlvmInstruction::GetLocal(self.asm_name()),
+                "get_global" => HlvmInstruction::GetGlobal(self.asm_name()),
+                "set_local" => HlvmInstruction::SetLocal(self.asm_name()),
+                "set_global" => HlvmInstruction::SetGlobal(self.asm_name()),
+                "get_attribute" => HlvmInstruction::GetAttribute(self.asm_name()),
+                "set_attribute" => HlvmInstruction::SetAttribute(self.asm_name()),
+                "instantiate" => HlvmInstruction::Instantiate,
+                "build_array" => HlvmInstruction::BuildArray(self.asm_number()),
+                "index" => HlvmInstruction::Index,
+                "slice" => HlvmInstruction::Slice,
+                "unpack" => {
+                    let count = self.asm_number();
+
+                    let rest = match self.advance() {
+                        Token::True => true,
+                        Token::False => false,
+                        _ => self.error(ErrorCode::InvalidAssembly, "Expected 'true' or 'false'."),
+                    };
+
+                    HlvmInstruction::Unpack(count, rest)
+                }
+                "add" => HlvmInstruction::Add,
+                "subtract" => HlvmInstruction::Subtract,
+                "multiply" => HlvmInstruction::Multiply,
+                "divide" => HlvmInstruction::Divide,
+                "modulo" => HlvmInstruction::Modulo,
+                "power" => HlvmInstruction::Power,
+                "shift_left" => HlvmInstruction::ShiftLeft,
+                "shift_right" => HlvmInstruction::ShiftRight,
+                "equal" => HlvmInstruction::Equal,
+                "not_equal" => HlvmInstruction::NotEqual,
+                "greater_than" => HlvmInstruction::GreaterThan,
+                "less_than" => HlvmInstruction::LessThan,
+                "greater_than_or_equal" => HlvmInstruction::GreaterThanOrEqual,
+                "less_than_or_equal" => HlvmInstruction::LessThanOrEqual,
+                "and" => HlvmInstruction::And,
+                "or" => HlvmInstruction::Or,
+                "not" => HlvmInstruction::Not,
+                "pop" => HlvmInstruction::Pop,
+                "convert" => {
+                    let conversion = match self.asm_name().as_str() {
+                        "number" => Conversion::Number,
+                        "string" => Conversion::String,
+                        "bool" => Conversion::Bool,
+                        _ => self.error(
+                            ErrorCode::InvalidAssembly,
+                            "Expected 'number', 'string' or 'bool'.",
+                        ),
+                    };
+
+                    HlvmInstruction::Convert(conversion)
+                }
+                "jump" => HlvmInstruction::Jump(self.asm_number()),
+                "jump_if" => HlvmInstruction::JumpIf(self.asm_number()),
+                _ => self.error(
+                    ErrorCode::InvalidAssembly,
+                    &format!("Unknown instruction '{}'.", mnemonic),
+                ),
+            };
+
+            block.push(instruction);
+        }
+
+        /* Jumping to the end of the block is fine, anything past it isn't */
+        for instruction in &block {
+            if let HlvmInstruction::Jump(addr) | HlvmInstruction::JumpIf(addr) = instruction {
+                if *addr > block.len() {
+                    self.error(
+                        ErrorCode::InvalidAssembly,
+                        &format!("Jump to {} is outside of the block.", addr),
+                    );
+                }
+            }
+        }
+
+        self.advance();
+        Node::new(NodeValue::Asm(block), self.line)
+    }
+
+    #[inline(always)]
     fn statement(&mut self) -> Node {
+        /* Statements report the line they start on, not the one they end on */
+        let line = self.line;
+        let mut node = self.nested(Self::parse_statement, "Block too deeply nested.");
+        node.line = line;
+        node
+    }
+
+    /// Parses the statement following one or more `///` doc comments,
+    /// and attaches the comments to it.
+    fn documented_statement(&mut self) -> Node {
+        let mut docs: Vec<&str> = vec![];
+
+        while let Token::DocComment(line) = self.current {
+            let line = line.trim_start_matches('/');
+            docs.push(line.strip_prefix(' ').unwrap_or(line).trim_end());
+            self.advance();
+        }
+
+        if self.current == Token::End {
+            self.error(
+                ErrorCode::DanglingDocComment,
+                "Expected a statement after doc comment.",
+            );
+        }
+
+        let mut node = self.statement();
+        node.docs = Some(docs.join("\n"));
+        node
+    }
+
+    fn parse_statement(&mut self) -> Node {
         match self.current {
+            Token::DocComment(_) => self.documented_statement(),
             Token::KwLet => self.variable_decleration(false),
             Token::KwFn => self.function_decleration(false),
             Token::KwPub => match self.advance() {
                 Token::KwLet => self.variable_decleration(true),
                 Token::KwFn => self.function_decleration(true),
-                _ => self.error("Expected 'let' or 'fn' after 'pub'"),
+                _ => self.error(ErrorCode::ExpectedToken, "Expected 'let' or 'fn' after 'pub'"),
             },
             Token::KwWhile => self.while_statement(),
             Token::KwUse => self.import_statement(),
             Token::KwIf => self.if_statement(),
             Token::KwReturn => self.return_statement(),
+            Token::KwDefer => self.defer_statement(),
             Token::KwType => self.type_decleration(),
+            Token::KwStruct => self.struct_decleration(),
+            Token::KwAsm => self.asm_block(),
+            Token::LeftCurly => self.block_statement(),
             Token::Identifier(_) => {
                 /*  Lines that start with identifiers can either be assignments or expressions.
                     Therefore, we parse an expression, and if expression is a sole identifier and
@@ -793,14 +1813,14 @@ impl<'p> Parser<'p> {
                 */
                 let node = self.expression();
 
-                if let NodeValue::IdentifierValue(iden) = &node.inner {
-                    if self.current == Token::Assign {
+                match &node.inner {
+                    NodeValue::IdentifierValue(iden) if self.current == Token::Assign => {
                         self.variable_assignment(iden.clone())
-                    } else {
-                        node
                     }
-                } else {
-                    node
+                    NodeValue::GetAttribute(..) if self.current == Token::Assign => {
+                        self.attribute_assignment(node.inner)
+                    }
+                    _ => node,
                 }
             }
             Token::Number(_)
@@ -814,7 +1834,8 @@ impl<'p> Parser<'p> {
             | Token::KwTypeof
             | Token::OpBang
             | Token::OpSub => self.expression(),
-            ref unimplemented => todo!("{:?}", unimplemented),
+            Token::Error => self.error(ErrorCode::InvalidCharacter, "Invalid character."),
+            _ => self.error(ErrorCode::UnexpectedToken, "Unexpected token."),
         }
     }
 
@@ -824,4 +1845,112 @@ impl<'p> Parser<'p> {
             self.ast.push(statement);
         }
     }
+
+    /// Parses the whole source as a single expression.
+    pub fn parse_expression(&mut self) -> NodeValue {
+        let expression = self.expression();
+
+        if self.current != Token::End {
+            self.error(ErrorCode::UnexpectedToken, "Expected the end of the expression.");
+        }
+
+        expression.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hlvm::vm::HighLevelVirtualMachine;
+
+    /// Parses the expression `source` and writes it back with every binary expression in
+    /// parentheses, which shows how tightly each operator binds.
+    fn grouped(source: &str) -> String {
+        fn group(value: &NodeValue) -> String {
+            match value {
+                NodeValue::Binary(left, right, operator) => {
+                    let operator = match operator {
+                        Token::OpAdd => "+",
+                        Token::OpSub => "-",
+                        Token::OpMul => "*",
+                        Token::OpDiv => "/",
+                        Token::OpMod => "%",
+                        Token::OpPow => "**",
+                        Token::OpLeftShift => "<<",
+                        Token::OpRightShift => ">>",
+                        Token::OpLess => "<",
+                        Token::OpEq => "==",
+                        operator => panic!("unexpected operator {:?}", operator),
+                    };
+
+                    format!("({} {} {})", group(left), operator, group(right))
+                }
+                NodeValue::Unary(value, Unary::Negate) => format!("-{}", group(value)),
+                NodeValue::NumberValue(number) => number.to_string(),
+                NodeValue::IdentifierValue(name) => name.clone(),
+                value => panic!("unexpected value {:?}", value),
+            }
+        }
+
+        group(&Parser::new(Token::lexer(source), source).parse_expression())
+    }
+
+    fn evaluate(source: &str) -> HlvmValue {
+        let instructions = crate::pipeline::compile_expression_str(source);
+        HighLevelVirtualMachine::new(Some(1)).execute(&instructions).unwrap()
+    }
+
+    #[test]
+    fn modulo_binds_like_multiplication() {
+        assert_eq!(grouped("1 + 2 % 3"), "(1 + (2 % 3))");
+        assert_eq!(grouped("10 % 4 * 3"), "((10 % 4) * 3)");
+        assert_eq!(grouped("10 * 4 % 3"), "((10 * 4) % 3)");
+        assert_eq!(evaluate("1 + 7 % 4"), HlvmValue::Number(4.0));
+    }
+
+    #[test]
+    fn power_binds_tightest_and_to_the_right() {
+        assert_eq!(grouped("2 * 3 ** 2"), "(2 * (3 ** 2))");
+        assert_eq!(grouped("2 ** 3 ** 2"), "(2 ** (3 ** 2))");
+        assert_eq!(grouped("a % b ** c"), "(a % (b ** c))");
+        assert_eq!(evaluate("2 ** 3 ** 2"), HlvmValue::Number(512.0));
+    }
+
+    #[test]
+    fn power_takes_negative_and_fractional_operands() {
+        assert_eq!(grouped("2 ** -1"), "(2 ** -1)");
+        assert_eq!(evaluate("2 ** -1"), HlvmValue::Number(0.5));
+        assert_eq!(evaluate("0 ** 0"), HlvmValue::Number(1.0));
+        assert_eq!(evaluate("2.25 ** 0.5"), HlvmValue::Number(1.5));
+        assert_eq!(evaluate("0.5 ** -2"), HlvmValue::Number(4.0));
+
+        /* `**` binds tighter than unary minus, as in most languages that have it */
+        assert_eq!(grouped("-2 ** 2"), "-(2 ** 2)");
+        assert_eq!(evaluate("-2 ** 2"), HlvmValue::Number(-4.0));
+        assert_eq!(evaluate("(-2) ** 2"), HlvmValue::Number(4.0));
+        assert_eq!(evaluate("-2 ** -2 ** 2"), HlvmValue::Number(-0.0625));
+    }
+
+    #[test]
+    fn shifts_bind_between_addition_and_comparison() {
+        assert_eq!(grouped("1 << 2 + 3"), "(1 << (2 + 3))");
+        assert_eq!(grouped("8 >> 1 < 5"), "((8 >> 1) < 5)");
+        assert_eq!(grouped("1 << 4 >> 2"), "((1 << 4) >> 2)");
+        assert_eq!(grouped("a == 1 << 2"), "(a == (1 << 2))");
+        assert_eq!(evaluate("1 << 2 + 3"), HlvmValue::Number(32.0));
+        assert_eq!(evaluate("256 >> 2 ** 2"), HlvmValue::Number(16.0));
+    }
+
+    #[test]
+    fn every_primitive_is_found_by_name_and_typed() {
+        for (index, primitive) in PRIMITIVES.iter().enumerate() {
+            assert_eq!(primitive_index(primitive.name), Some(index), "{}", primitive.name);
+            assert_eq!(
+                primitive.returns.is_some(),
+                primitive_type(index).is_some(),
+                "{}",
+                primitive.name
+            );
+        }
+    }
 }
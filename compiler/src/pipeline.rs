@@ -1,11 +1,43 @@
-use crate::parser::Node;
+use crate::{error::Diagnostic, parser::Node};
+use hlvm::{
+    hir::{from_hir, HlvmHirInstruction},
+    lir::HlvmInstruction,
+};
 use logos::Logos;
 
 pub fn lace_pipeline_init(source: &str) -> Vec<Node> {
     let scanner = crate::scanner::Token::lexer(source);
-    let mut parser = crate::parser::Parser::new(scanner, source.to_string());
+    let mut parser = crate::parser::Parser::new(scanner, source);
     parser.parse();
-
-    println!("{:?}", parser.ast);
     parser.ast
 }
+
+/// Compiles a whole program along with the prelude, the way `shoelace build` does without
+/// any options. Imports are relative to the current directory.
+/// Syntax errors exit the process like they do for `lace_pipeline_init`, type errors are
+/// returned so the caller can emit them.
+pub fn compile_program(source: &str) -> Result<Vec<HlvmInstruction>, Vec<Diagnostic>> {
+    let ast = crate::prelude::link(lace_pipeline_init(source));
+
+    let mut resolver = crate::scope::Resolver::new();
+    let ast = resolver.resolve(ast);
+    let symbols = resolver.symbols;
+
+    crate::typecheck::Typechecker::new(&symbols).check(&ast)?;
+
+    let mut context = crate::codegen::Context::new();
+    Ok(from_hir(crate::codegen::compile(ast, &symbols, &mut context)))
+}
+
+/// Compiles a single expression, like `1 + foo(2)`, into instructions that return its value.
+/// Names are looked up when the instructions run, so they can be executed against an existing
+/// VM to evaluate the expression in its state.
+pub fn compile_expression_str(source: &str) -> Vec<HlvmInstruction> {
+    let scanner = crate::scanner::Token::lexer(source);
+    let mut parser = crate::parser::Parser::new(scanner, source);
+
+    let mut instructions = crate::codegen::compile_value(parser.parse_expression());
+    instructions.push(HlvmHirInstruction::ReturnValue);
+
+    from_hir(instructions)
+}
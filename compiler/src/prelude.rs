@@ -0,0 +1,11 @@
+use crate::parser::Node;
+
+/// Source code of the prelude, embedded into the compiler.
+pub const PRELUDE: &str = include_str!("prelude.lc");
+
+/// Links the prelude into `program` by placing its declarations before the program's own.
+pub fn link(mut program: Vec<Node>) -> Vec<Node> {
+    let mut ast = crate::pipeline::lace_pipeline_init(PRELUDE);
+    ast.append(&mut program);
+    ast
+}
@@ -1,7 +1,7 @@
 use logos::Logos;
 
 #[derive(Logos, Debug, PartialEq, Clone)]
-pub enum Token {
+pub enum Token<'s> {
     // Brackets
     #[token("{")]
     LeftCurly,
@@ -29,10 +29,14 @@ pub enum Token {
     KwPub,
     #[token("type")]
     KwType,
+    #[token("struct")]
+    KwStruct,
     #[token("typeof")]
     KwTypeof,
     #[token("return")]
     KwReturn,
+    #[token("defer")]
+    KwDefer,
     #[token("fn")]
     KwFn,
     #[token("async")]
@@ -43,6 +47,8 @@ pub enum Token {
     KwOr,
     #[token("while")]
     KwWhile,
+    #[token("where")]
+    KwWhere,
     #[token("use")]
     KwUse,
     #[token("if")]
@@ -51,6 +57,8 @@ pub enum Token {
     KwElse,
     #[token("new")]
     KwNew,
+    #[token("asm")]
+    KwAsm,
 
     // Builtin Values
     #[token("true")]
@@ -107,21 +115,43 @@ pub enum Token {
     Que,
     #[token(".")]
     Period,
+    /// Separates the bounds of a slice, like `s[1..4]`.
+    #[token("..")]
+    Range,
+    /// Follows the rest name of an array pattern, like `let [first, rest...] = array`.
+    #[token("...")]
+    Ellipsis,
+
+    #[regex(r"///[^\n]*", |lex| lex.slice())]
+    DocComment(&'s str),
 
     // Literals
     // #[regex(r#"0b([0-9]+)"#, |lex|lex .slice().parse())]
     // Byte(i8),
-    #[regex(r#"([0-9]*\.[0-9]+)|([0-9]*)"#, |lex| lex.slice().parse())]
+    /// Digits with an optional fraction and exponent, like `1`, `1.5` and `1.5e-3`,
+    /// or a fraction without an integer part, like `.5` and `.5e3`.
+    /// A fraction needs digits, so that `1..4` is a range rather than `1.` and `.4`.
+    /// `1.` on its own is a number followed by `.`, which the parser reports as a number
+    /// ending with '.' unless an attribute name follows.
+    #[regex(r"[0-9]+(\.[0-9]+)?([eE][+-]?[0-9]+)?", |lex| lex.slice().parse())]
+    #[regex(r"\.[0-9]+([eE][+-]?[0-9]+)?", |lex| lex.slice().parse())]
     Number(f64),
-    #[regex("\"([^\"]*)\"", |lex| lex.slice().to_string())]
-    #[regex("'([^\"]*)'", |lex| lex.slice().to_string())]
-    String(String),
-    #[regex("`([^\"]*)`", |lex| lex.slice().to_string())]
-    FormattedString(String),
-    #[regex("[a-zA-Z_]+!", |lex| lex.slice().to_string())]
-    PrimitiveFnIdentifier(String),
-    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
-    Identifier(String),
+    /// Quoted with `"` or `'`. Either may contain the other quote, and a backslash escapes
+    /// the delimiter or another backslash.
+    /// Also raw strings like `r"C:\path"`, which have no escapes, and `"""`-quoted strings,
+    /// which may contain `"` and `""` without escaping them.
+    /// The slice keeps its delimiters, see `string_value`.
+    #[regex(r#""([^"\\]|\\.|\\\n)*""#, |lex| lex.slice())]
+    #[regex(r#"'([^'\\]|\\.|\\\n)*'"#, |lex| lex.slice())]
+    #[regex(r#"r"[^"]*""#, |lex| lex.slice())]
+    #[regex(r#""""([^"\\]|\\.|\\\n|"[^"]|""[^"])*""""#, |lex| lex.slice())]
+    String(&'s str),
+    #[regex(r#"`([^`\\]|\\.)*`"#, |lex| lex.slice())]
+    FormattedString(&'s str),
+    #[regex("[a-zA-Z_]+!", |lex| lex.slice())]
+    PrimitiveFnIdentifier(&'s str),
+    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice())]
+    Identifier(&'s str),
 
     #[error]
     #[regex(r"[\s\t\n\f]+", logos::skip)]
@@ -130,3 +160,193 @@ pub enum Token {
 
     End,
 }
+
+/// The text of a string literal, without its delimiters and with its escapes replaced.
+pub fn string_value(literal: &str) -> String {
+    if let Some(raw) = literal.strip_prefix('r') {
+        return raw[1..raw.len() - 1].to_string();
+    }
+
+    match literal.strip_prefix(r#"""""#) {
+        /* A line break right after the opening quotes isn't part of the text */
+        Some(text) => {
+            let text = &text[..text.len() - 3];
+            unescape(text.strip_prefix('\n').unwrap_or(text))
+        }
+        None => unescape(&literal[1..literal.len() - 1]),
+    }
+}
+
+/// Replaces the escapes of `text`. A backslash at the end of a line continues the string on
+/// the next line, leaving out the line break and the indentation that follows it.
+/// Unknown escapes are kept as they're written.
+pub fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some(c @ ('\\' | '"' | '\'' | '`')) => result.push(c),
+            Some('\n') => {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            }
+            Some(c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            None => result.push('\\'),
+        }
+    }
+
+    result
+}
+
+impl Token<'_> {
+    /// Returns the keyword's source text if the token is a reserved keyword.
+    pub fn keyword(&self) -> Option<&'static str> {
+        Some(match self {
+            Token::KwAs => "as",
+            Token::KwLet => "let",
+            Token::KwMut => "mut",
+            Token::KwPub => "pub",
+            Token::KwType => "type",
+            Token::KwStruct => "struct",
+            Token::KwTypeof => "typeof",
+            Token::KwReturn => "return",
+            Token::KwDefer => "defer",
+            Token::KwFn => "fn",
+            Token::KwAsync => "async",
+            Token::KwAnd => "and",
+            Token::KwOr => "or",
+            Token::KwWhile => "while",
+            Token::KwWhere => "where",
+            Token::KwUse => "use",
+            Token::KwIf => "if",
+            Token::KwElse => "else",
+            Token::KwNew => "new",
+            Token::KwAsm => "asm",
+            Token::True => "true",
+            Token::False => "false",
+            Token::None => "none",
+            _ => return None,
+        })
+    }
+
+    /// Returns the token as a `Token<'static>` if it is an operator.
+    /// Operators don't borrow from the source, so they can outlive the scanner and be stored in the AST.
+    pub fn operator(&self) -> Option<Token<'static>> {
+        Some(match self {
+            Token::OpBangEq => Token::OpBangEq,
+            Token::OpEq => Token::OpEq,
+            Token::OpMoreEq => Token::OpMoreEq,
+            Token::OpLessEq => Token::OpLessEq,
+            Token::OpRightShift => Token::OpRightShift,
+            Token::OpLeftShift => Token::OpLeftShift,
+            Token::OpMore => Token::OpMore,
+            Token::OpLess => Token::OpLess,
+            Token::OpAdd => Token::OpAdd,
+            Token::OpSub => Token::OpSub,
+            Token::OpMul => Token::OpMul,
+            Token::OpDiv => Token::OpDiv,
+            Token::OpMod => Token::OpMod,
+            Token::OpPow => Token::OpPow,
+            Token::BitwiseXor => Token::BitwiseXor,
+            Token::BitwiseOr => Token::BitwiseOr,
+            Token::BitwiseAnd => Token::BitwiseAnd,
+            Token::KwAnd => Token::KwAnd,
+            Token::KwOr => Token::KwOr,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token<'_>> {
+        Token::lexer(source).collect()
+    }
+
+    /// Scans `source`, which must be a single string literal, and returns its value.
+    fn scan_string(source: &str) -> String {
+        let tokens: Vec<Token> = Token::lexer(source).collect();
+
+        match tokens.as_slice() {
+            [Token::String(literal)] => string_value(literal),
+            tokens => panic!("expected a single string in {:?}, found {:?}", source, tokens),
+        }
+    }
+
+    #[test]
+    fn strings_contain_the_other_quote() {
+        assert_eq!(scan_string(r#""he said 'hi'""#), "he said 'hi'");
+        assert_eq!(scan_string(r#"'say "hi"'"#), r#"say "hi""#);
+    }
+
+    #[test]
+    fn escaped_delimiters_dont_end_strings() {
+        assert_eq!(scan_string(r"'it\'s'"), "it's");
+        assert_eq!(scan_string(r#""a \"quote\"""#), r#"a "quote""#);
+        assert_eq!(scan_string(r#""ends with \\""#), r"ends with \");
+    }
+
+    #[test]
+    fn escapes_are_replaced() {
+        assert_eq!(scan_string(r#""\n\t\r\0""#), "\n\t\r\0");
+        assert_eq!(scan_string(r#""\\ \' \` \"""#), "\\ ' ` \"");
+        assert_eq!(scan_string("\"one \\\n    two\""), "one two");
+    }
+
+    #[test]
+    fn unknown_escapes_are_kept() {
+        assert_eq!(scan_string(r#""\q\d""#), r"\q\d");
+        assert_eq!(unescape(r"trailing \"), r"trailing \");
+    }
+
+    #[test]
+    fn raw_strings_have_no_escapes() {
+        assert_eq!(scan_string(r#"r"C:\new\table""#), r"C:\new\table");
+        assert_eq!(scan_string(r#"r"it\'s""#), r"it\'s");
+    }
+
+    #[test]
+    fn triple_quoted_strings_contain_quotes() {
+        assert_eq!(scan_string(r#""""say "hi" and ""bye"" now""""#), r#"say "hi" and ""bye"" now"#);
+        assert_eq!(scan_string("\"\"\"\nfirst\nsecond\"\"\""), "first\nsecond");
+        assert_eq!(scan_string(r#""""tab\there""""#), "tab\there");
+    }
+
+    #[test]
+    fn unterminated_strings_are_errors() {
+        let tokens: Vec<Token> = Token::lexer(r#""never closed"#).collect();
+
+        assert!(tokens.contains(&Token::Error), "{:?}", tokens);
+    }
+
+    #[test]
+    fn numbers_have_one_grammar() {
+        assert_eq!(tokens("12"), [Token::Number(12.0)]);
+        assert_eq!(tokens("1.5"), [Token::Number(1.5)]);
+        assert_eq!(tokens(".5"), [Token::Number(0.5)]);
+        assert_eq!(tokens("1e9"), [Token::Number(1e9)]);
+        assert_eq!(tokens("1.5E-3"), [Token::Number(1.5e-3)]);
+        assert_eq!(tokens(".5e+2"), [Token::Number(50.0)]);
+    }
+
+    #[test]
+    fn dots_after_numbers_arent_fractions() {
+        assert_eq!(tokens("1..4"), [Token::Number(1.0), Token::Range, Token::Number(4.0)]);
+        assert_eq!(tokens("1."), [Token::Number(1.0), Token::Period]);
+        assert_eq!(tokens("1.x"), [Token::Number(1.0), Token::Period, Token::Identifier("x")]);
+    }
+}
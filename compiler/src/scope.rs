@@ -0,0 +1,476 @@
+use crate::{
+    error::{Diagnostic, Span},
+    parser::{Node, NodeValue, Parameter, Pattern, Type},
+    symbols::{Symbol, SymbolKind, SymbolTable},
+};
+use std::collections::HashMap;
+
+/// Separates a name from the suffix given to it by the resolver, like `x@3`.
+/// `@` can't appear in identifiers, so resolved names never collide with user names.
+const SUFFIX_SEPARATOR: char = '@';
+
+/// The name as written in the source, without the suffix added by `Resolver`.
+pub fn source_name(name: &str) -> &str {
+    name.split(SUFFIX_SEPARATOR).next().unwrap_or(name)
+}
+
+struct Scope {
+    /// Maps names as written in the source to the names used in the generated code.
+    names: HashMap<String, String>,
+
+    /// Whether this is an `if`/`while` body rather than the top of a function or program.
+    block: bool,
+}
+
+/// Gives every variable a lexical scope.
+///
+/// The VM keeps all locals of a call in a single map, so a `let` inside a block is renamed
+/// to a unique name. This stops it from leaking into the enclosing scope, and stops
+/// declarations that shadow an outer variable from overwriting it.
+pub struct Resolver {
+    scopes: Vec<Scope>,
+    counter: usize,
+
+    /// The resolved names of the functions being resolved, innermost last.
+    functions: Vec<String>,
+
+    /// Every declaration seen so far, under its resolved name.
+    pub symbols: SymbolTable,
+
+    /// Print a warning whenever a declaration shadows or redeclares a variable.
+    pub warn_shadowing: bool,
+
+    /// Set when resolving an imported file. Its globals are suffixed with it, so they can't
+    /// collide with the globals of the files importing it.
+    pub module: Option<String>,
+
+    /// The renamed top-level functions of a module, which can be called before they're
+    /// declared. Names in the main program aren't renamed, so they don't need this.
+    hoisted: HashMap<String, String>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![],
+            counter: 0,
+            functions: vec![],
+            symbols: SymbolTable::new(),
+            warn_shadowing: false,
+            module: None,
+            hoisted: HashMap::new(),
+        }
+    }
+
+    pub fn resolve(&mut self, program: Vec<Node>) -> Vec<Node> {
+        if let Some(module) = &self.module {
+            self.hoisted = program
+                .iter()
+                .filter_map(|node| match &node.inner {
+                    NodeValue::FunctionDecleration(name, ..)
+                    | NodeValue::TypeDecleration(name, ..) => {
+                        Some((name.clone(), format!("{}{}{}", name, SUFFIX_SEPARATOR, module)))
+                    }
+                    _ => None,
+                })
+                .collect();
+        }
+
+        self.scoped(false, |resolver| resolver.resolve_block(program))
+    }
+
+    fn scoped<T>(&mut self, block: bool, resolve: impl FnOnce(&mut Self) -> T) -> T {
+        self.scopes.push(Scope {
+            names: HashMap::new(),
+            block,
+        });
+
+        let result = resolve(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn lookup(&self, name: &str) -> Option<&String> {
+        self.scopes.iter().rev().find_map(|scope| scope.names.get(name))
+    }
+
+    /// Declares `name` in the innermost scope and returns the name it should be compiled to.
+    fn declare(
+        &mut self,
+        name: String,
+        line: usize,
+        kind: SymbolKind,
+        datatype: Type,
+        mutable: bool,
+    ) -> String {
+        let scope = self.scopes.last().unwrap();
+
+        /* The locals of a function are keyed by its name, so a nested one always gets its own */
+        let nested_function = kind == SymbolKind::Function && self.scopes.len() > 1;
+
+        if let Some(resolved) = scope.names.get(&name) {
+            if self.warn_shadowing {
+                warn(line, &format!("'{}' is redeclared in the same scope.", name));
+            }
+
+            if !nested_function {
+                return resolved.clone();
+            }
+        }
+
+        let shadows = self.lookup(&name).is_some();
+
+        if shadows && self.warn_shadowing {
+            warn(line, &format!("'{}' shadows a variable of an outer scope.", name));
+        }
+
+        /* Globals are only renamed by module, they have to stay reachable from every function */
+        let resolved = if self.scopes.len() > 1 && (scope.block || shadows || nested_function) {
+            self.counter += 1;
+            format!("{}{}{}", name, SUFFIX_SEPARATOR, self.counter)
+        } else if let (1, Some(module)) = (self.scopes.len(), &self.module) {
+            format!("{}{}{}", name, SUFFIX_SEPARATOR, module)
+        } else {
+            name.clone()
+        };
+
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .names
+            .insert(name, resolved.clone());
+
+        self.symbols.insert(
+            resolved.clone(),
+            Symbol {
+                kind,
+                datatype,
+                mutable,
+                parameters: None,
+                function: self.functions.last().cloned(),
+            },
+        );
+
+        resolved
+    }
+
+    fn resolve_name(&self, name: String) -> String {
+        self.lookup(&name).or_else(|| self.hoisted.get(&name)).cloned().unwrap_or(name)
+    }
+
+    /// `datatype` with the names of the structs in it resolved.
+    fn resolve_type(&self, datatype: Type) -> Type {
+        match datatype {
+            Type::Struct(name) => Type::Struct(self.resolve_name(name)),
+            Type::Array(element) => Type::Array(Box::new(self.resolve_type(*element))),
+            datatype => datatype,
+        }
+    }
+
+    /// The resolved types of `parameters`, in order.
+    fn parameter_types(&self, parameters: &[Parameter]) -> Vec<Type> {
+        parameters
+            .iter()
+            .map(|parameter| self.resolve_type(parameter.datatype.clone()))
+            .collect()
+    }
+
+    /// Resolves the parameters and body of the function that was declared as `name`, in a
+    /// scope of its own.
+    fn resolve_function(
+        &mut self,
+        name: String,
+        body: Vec<Node>,
+        parameters: Vec<Parameter>,
+        public: bool,
+        return_type: Type,
+        line: usize,
+    ) -> NodeValue {
+        self.functions.push(name.clone());
+
+        let function = self.scoped(false, |resolver| {
+            let parameters = parameters
+                .into_iter()
+                .map(|mut parameter| {
+                    parameter.datatype = resolver.resolve_type(parameter.datatype);
+                    parameter.name = resolver.declare(
+                        parameter.name,
+                        line,
+                        SymbolKind::Parameter,
+                        parameter.datatype.clone(),
+                        parameter.mutable,
+                    );
+                    parameter
+                })
+                .collect();
+
+            NodeValue::FunctionDecleration(
+                name,
+                resolver.resolve_block(body),
+                parameters,
+                public,
+                return_type,
+            )
+        });
+
+        self.functions.pop();
+        function
+    }
+
+    /// Resolves method `name` of a type. It's stored as an attribute rather than declared in
+    /// a scope, but it's renamed all the same so its locals are its own.
+    fn resolve_method(
+        &mut self,
+        name: String,
+        body: Vec<Node>,
+        parameters: Vec<Parameter>,
+        public: bool,
+        return_type: Type,
+        line: usize,
+    ) -> NodeValue {
+        self.counter += 1;
+        let resolved = format!("{}{}{}", name, SUFFIX_SEPARATOR, self.counter);
+        let return_type = self.resolve_type(return_type);
+
+        self.symbols.insert(
+            resolved.clone(),
+            Symbol {
+                kind: SymbolKind::Function,
+                datatype: return_type.clone(),
+                mutable: false,
+                parameters: Some(self.parameter_types(&parameters)),
+                function: self.functions.last().cloned(),
+            },
+        );
+
+        self.resolve_function(resolved, body, parameters, public, return_type, line)
+    }
+
+    fn resolve_block(&mut self, body: Vec<Node>) -> Vec<Node> {
+        body.into_iter()
+            .map(|node| Node {
+                inner: self.resolve_value(node.inner, node.line),
+                ..node
+            })
+            .collect()
+    }
+
+    fn resolve_boxed(&mut self, value: NodeValue, line: usize) -> Box<NodeValue> {
+        Box::new(self.resolve_value(value, line))
+    }
+
+    fn resolve_values(&mut self, values: Vec<NodeValue>, line: usize) -> Vec<NodeValue> {
+        values
+            .into_iter()
+            .map(|value| self.resolve_value(value, line))
+            .collect()
+    }
+
+    fn resolve_value(&mut self, value: NodeValue, line: usize) -> NodeValue {
+        match value {
+            NodeValue::IdentifierValue(name) => NodeValue::IdentifierValue(self.resolve_name(name)),
+            NodeValue::FunctionCall(name, arguments) => NodeValue::FunctionCall(
+                self.resolve_name(name),
+                self.resolve_values(arguments, line),
+            ),
+            NodeValue::PrimitiveFunctionCall(index, arguments) => {
+                NodeValue::PrimitiveFunctionCall(index, self.resolve_values(arguments, line))
+            }
+            NodeValue::ArrayValue(values) => {
+                NodeValue::ArrayValue(self.resolve_values(values, line))
+            }
+            NodeValue::StructInit(name, arguments) => NodeValue::StructInit(
+                self.resolve_name(name),
+                arguments
+                    .into_iter()
+                    .map(|(attribute, node)| {
+                        (attribute, Node {
+                            inner: self.resolve_value(node.inner, node.line),
+                            ..node
+                        })
+                    })
+                    .collect(),
+            ),
+            NodeValue::Unary(value, unary) => {
+                NodeValue::Unary(self.resolve_boxed(*value, line), unary)
+            }
+            NodeValue::Binary(left, right, op) => NodeValue::Binary(
+                self.resolve_boxed(*left, line),
+                self.resolve_boxed(*right, line),
+                op,
+            ),
+            NodeValue::Conditional(condition, ontrue, onfalse) => NodeValue::Conditional(
+                self.resolve_boxed(*condition, line),
+                self.resolve_boxed(*ontrue, line),
+                self.resolve_boxed(*onfalse, line),
+            ),
+            NodeValue::GetAttribute(value, attribute) => {
+                NodeValue::GetAttribute(self.resolve_boxed(*value, line), attribute)
+            }
+            NodeValue::Index(value, index) => NodeValue::Index(
+                self.resolve_boxed(*value, line),
+                self.resolve_boxed(*index, line),
+            ),
+            NodeValue::Slice(value, start, end) => NodeValue::Slice(
+                self.resolve_boxed(*value, line),
+                start.map(|start| self.resolve_boxed(*start, line)),
+                end.map(|end| self.resolve_boxed(*end, line)),
+            ),
+            NodeValue::Convert(value, datatype) => {
+                NodeValue::Convert(self.resolve_boxed(*value, line), datatype)
+            }
+            NodeValue::Call(callee, arguments) => NodeValue::Call(
+                self.resolve_boxed(*callee, line),
+                self.resolve_values(arguments, line),
+            ),
+            NodeValue::FunctionDecleration(name, body, parameters, public, return_type) => {
+                let return_type = self.resolve_type(return_type);
+
+                /* Declared before the body is resolved, so the function can call itself */
+                let name =
+                    self.declare(name, line, SymbolKind::Function, return_type.clone(), false);
+
+                let owner = self.functions.last().cloned();
+                let types = self.parameter_types(&parameters);
+                if let Some(symbol) = self.symbols.get_mut(owner.as_deref(), &name) {
+                    symbol.parameters = Some(types);
+                }
+
+                self.resolve_function(name, body, parameters, public, return_type, line)
+            }
+            NodeValue::VariableDecleration(name, value, public, mutable, datatype) => {
+                /* The value can still refer to a variable this declaration shadows */
+                let value = self.resolve_boxed(*value, line);
+                let datatype = self.resolve_type(datatype);
+                let name =
+                    self.declare(name, line, SymbolKind::Variable, datatype.clone(), mutable);
+
+                NodeValue::VariableDecleration(name, value, public, mutable, datatype)
+            }
+            NodeValue::Destructure(pattern, value) => {
+                let value = self.resolve_boxed(*value, line);
+
+                /* The types of destructured elements aren't known until runtime */
+                let mut declare =
+                    |name| self.declare(name, line, SymbolKind::Variable, Type::Void, false);
+
+                let pattern = match pattern {
+                    Pattern::Array(names, rest) => Pattern::Array(
+                        names
+                            .into_iter()
+                            .map(&mut declare)
+                            .collect(),
+                        rest.map(&mut declare),
+                    ),
+                    Pattern::Struct(names) => Pattern::Struct(
+                        names
+                            .into_iter()
+                            .map(&mut declare)
+                            .collect(),
+                    ),
+                };
+
+                NodeValue::Destructure(pattern, value)
+            }
+            NodeValue::VariableAssignment(name, value) => {
+                let value = self.resolve_boxed(*value, line);
+                NodeValue::VariableAssignment(self.resolve_name(name), value)
+            }
+            NodeValue::AttributeAssignment(object, attribute, value) => {
+                let value = self.resolve_boxed(*value, line);
+                NodeValue::AttributeAssignment(self.resolve_boxed(*object, line), attribute, value)
+            }
+            NodeValue::WhileStatement(condition, body) => NodeValue::WhileStatement(
+                self.resolve_boxed(*condition, line),
+                self.scoped(true, |resolver| resolver.resolve_block(body)),
+            ),
+            NodeValue::If(ontrue, onelseif, onfalse) => {
+                let ontrue = (
+                    self.resolve_boxed(*ontrue.0, line),
+                    self.scoped(true, |resolver| resolver.resolve_block(ontrue.1)),
+                );
+
+                let onelseif = onelseif
+                    .into_iter()
+                    .map(|(condition, body)| {
+                        (
+                            self.resolve_boxed(*condition, line),
+                            self.scoped(true, |resolver| resolver.resolve_block(body)),
+                        )
+                    })
+                    .collect();
+
+                let onfalse =
+                    onfalse.map(|body| self.scoped(true, |resolver| resolver.resolve_block(body)));
+
+                NodeValue::If(ontrue, onelseif, onfalse)
+            }
+            NodeValue::ImportStatement(path, alias) => {
+                /* The types of a module's attributes aren't known until runtime */
+                let alias = self.declare(alias, line, SymbolKind::Variable, Type::Void, false);
+                NodeValue::ImportStatement(path, alias)
+            }
+            NodeValue::Return(value) => NodeValue::Return(self.resolve_boxed(*value, line)),
+            NodeValue::Defer(body) => {
+                NodeValue::Defer(self.scoped(true, |resolver| resolver.resolve_block(body)))
+            }
+            NodeValue::TypeDecleration(name, functions, variables) => {
+                /* Types are globals like functions, so an imported file's can't replace them */
+                let name = self.declare(name, line, SymbolKind::Variable, Type::Void, false);
+
+                let variables: HashMap<String, NodeValue> = variables
+                    .into_iter()
+                    .map(|(attribute, variable)| match variable {
+                        NodeValue::VariableDecleration(name, value, public, mutable, datatype) => {
+                            let value = self.resolve_boxed(*value, line);
+                            let datatype = self.resolve_type(datatype);
+                            let variable = NodeValue::VariableDecleration(
+                                name, value, public, mutable, datatype,
+                            );
+                            (attribute, variable)
+                        }
+                        variable => (attribute, variable),
+                    })
+                    .collect();
+
+                /* Methods have no type that describes them, so they're typed at runtime */
+                let mut methods = HashMap::new();
+                let functions = functions
+                    .into_iter()
+                    .map(|function| match function {
+                        NodeValue::FunctionDecleration(method, body, params, public, datatype) => {
+                            methods.insert(method.clone(), Type::Void);
+                            self.resolve_method(method, body, params, public, datatype, line)
+                        }
+                        function => function,
+                    })
+                    .collect();
+
+                let attributes = variables
+                    .iter()
+                    .filter_map(|(attribute, variable)| match variable {
+                        NodeValue::VariableDecleration(.., datatype) => {
+                            Some((attribute.clone(), datatype.clone()))
+                        }
+                        _ => None,
+                    })
+                    .chain(methods)
+                    .collect();
+
+                self.symbols.insert_struct(name.clone(), attributes);
+                NodeValue::TypeDecleration(name, functions, variables)
+            }
+            value => value,
+        }
+    }
+}
+
+fn warn(line: usize, warning: &str) {
+    Diagnostic::warning(warning).with_span(Span::line(line + 1)).emit();
+}
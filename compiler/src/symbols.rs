@@ -0,0 +1,121 @@
+use crate::parser::Type;
+use hlvm::{lir::HlvmValue, module::constants};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+    Constant,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+
+    /// The declared type. For functions, this is the return type.
+    pub datatype: Type,
+    pub mutable: bool,
+
+    /// The types of a function's parameters, in order, `None` for other kinds of symbols.
+    pub parameters: Option<Vec<Type>>,
+
+    /// The function the symbol is local to, or `None` for globals.
+    pub function: Option<String>,
+}
+
+/// Every symbol declared in a program, keyed by the function it's local to and its
+/// name after scope resolution. Built by `Resolver`, and used by the typechecker and codegen.
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    symbols: HashMap<(Option<String>, String), Symbol>,
+
+    /// The declared types of the attributes of every struct, keyed by the struct's name.
+    structs: HashMap<String, HashMap<String, Type>>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    /// Creates a table containing only the builtin constants.
+    pub fn new() -> Self {
+        let mut table = Self {
+            symbols: HashMap::new(),
+            structs: HashMap::new(),
+        };
+
+        for (name, value) in constants() {
+            let datatype = match value {
+                HlvmValue::String(_) => Type::String,
+                HlvmValue::Bool(_) => Type::Bool,
+                _ => Type::Number,
+            };
+
+            table.insert(
+                name,
+                Symbol {
+                    kind: SymbolKind::Constant,
+                    datatype,
+                    mutable: false,
+                    parameters: None,
+                    function: None,
+                },
+            );
+        }
+
+        table
+    }
+
+    pub fn insert(&mut self, name: String, symbol: Symbol) {
+        self.symbols.insert((symbol.function.clone(), name), symbol);
+    }
+
+    /// Looks up `name` in the locals of `function`, or in the globals if `function` is `None`.
+    pub fn get(&self, function: Option<&str>, name: &str) -> Option<&Symbol> {
+        self.symbols
+            .get(&(function.map(String::from), name.to_string()))
+    }
+
+    pub fn get_mut(&mut self, function: Option<&str>, name: &str) -> Option<&mut Symbol> {
+        self.symbols
+            .get_mut(&(function.map(String::from), name.to_string()))
+    }
+
+    pub fn insert_struct(&mut self, name: String, attributes: HashMap<String, Type>) {
+        self.structs.insert(name, attributes);
+    }
+
+    /// The name of struct `name` as it's stored, and the declared types of its attributes.
+    pub fn attributes(&self, name: &str) -> Option<(&str, &HashMap<String, Type>)> {
+        self.structs
+            .get_key_value(name)
+            .map(|(name, attributes)| (name.as_str(), attributes))
+    }
+
+    /// The names of the symbols in `function` with one of the given kinds.
+    pub fn names<'a>(
+        &'a self,
+        function: Option<&'a str>,
+        kinds: &'a [SymbolKind],
+    ) -> impl Iterator<Item = &'a str> {
+        self.symbols
+            .iter()
+            .filter(move |((owner, _), symbol)| {
+                owner.as_deref() == function && kinds.contains(&symbol.kind)
+            })
+            .map(|((_, name), _)| name.as_str())
+    }
+
+    /// The amount of locals `function` declares, including its parameters.
+    pub fn locals_of(&self, function: &str) -> usize {
+        self.symbols
+            .keys()
+            .filter(|(owner, _)| owner.as_deref() == Some(function))
+            .count()
+    }
+}
@@ -1,22 +1,61 @@
 use crate::{
-    parser::{Node, NodeValue, Type},
+    error::{not_found, Diagnostic, ErrorCode, Span},
+    parser::{primitive_type, Node, NodeValue, Type, Unary},
     scanner::Token,
+    scope::source_name,
+    symbols::{Symbol, SymbolKind, SymbolTable},
+    visitor::{walk_block, walk_node, Visitor},
 };
 use std::collections::HashMap;
 
-pub struct Typechecker {
-    functions: HashMap<String, Type>,
-    variables: HashMap<String, Type>,
+/// Checks a program, including the bodies of its blocks and functions, whose names have been
+/// resolved into `symbols`.
+pub struct Typechecker<'s> {
+    symbols: &'s SymbolTable,
+
+    /// The resolved names of the functions being checked, innermost last. Names are looked up
+    /// in the locals of the last one before the globals.
+    functions: Vec<String>,
+
+    /// The line of the statement being checked, 0-based.
+    line: usize,
+
+    /// Every error found so far. Checking continues after an error, so that all of them
+    /// can be reported at once.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+const VARIABLE_KINDS: &[SymbolKind] =
+    &[SymbolKind::Variable, SymbolKind::Parameter, SymbolKind::Constant];
+
+/// The type that values of both `a` and `b` fit, if there is one.
+/// An empty array has the element type `void`, so it fits an array of any type, and `none`
+/// fits a struct, since that's what its attributes start out as.
+fn common_type(a: &Type, b: &Type) -> Option<Type> {
+    match (a, b) {
+        _ if a == b => Some(a.clone()),
+        (Type::Struct(_), Type::Void) => Some(a.clone()),
+        (Type::Void, Type::Struct(_)) => Some(b.clone()),
+        (Type::Array(a), Type::Array(b)) => Some(Type::Array(Box::new(match (&**a, &**b) {
+            (Type::Void, b) => b.clone(),
+            (a, Type::Void) => a.clone(),
+            (a, b) => common_type(a, b)?,
+        }))),
+        _ => None,
+    }
+}
+
+/// Whether a value of type `found` can be stored where `expected` is declared.
+/// `void` is declared by values whose type is inferred, so anything fits it.
+fn fits(expected: &Type, found: &Type) -> bool {
+    *expected == Type::Void || common_type(expected, found).as_ref() == Some(expected)
 }
 
 fn binary_return_type(op: &str, left: Type, right: Type) -> Result<Type, ()> {
     match (op, left, right) {
-        ("==", Type::Number, Type::Number) => Ok(Type::Bool),
-        ("==", Type::String, Type::String) => Ok(Type::Bool),
-        ("==", Type::Bool, Type::Bool) => Ok(Type::Bool),
-        ("!=", Type::Number, Type::Number) => Ok(Type::Bool),
-        ("!=", Type::String, Type::String) => Ok(Type::Bool),
-        ("!=", Type::Bool, Type::Bool) => Ok(Type::Bool),
+        ("==" | "!=", left, right) if left == right => Ok(Type::Bool),
+        ("and" | "or", Type::Bool, Type::Bool) => Ok(Type::Bool),
+        ("<" | ">" | "<=" | ">=", Type::String, Type::String) => Ok(Type::Bool),
         (_, Type::Number, Type::Number) => Ok(Type::Number),
         (_, Type::Number, Type::Bool) => Ok(Type::Number),
         (_, Type::Bool, Type::Number) => Ok(Type::Number),
@@ -26,111 +65,816 @@ fn binary_return_type(op: &str, left: Type, right: Type) -> Result<Type, ()> {
     }
 }
 
-fn token_to_op(t: Token) -> &'static str {
+fn token_to_op(t: Token<'_>) -> &'static str {
     match t {
         Token::OpAdd => "+",
         Token::OpSub => "-",
         Token::OpMul => "*",
         Token::OpDiv => "/",
         Token::OpMod => "%",
+        Token::OpPow => "**",
+        Token::OpLeftShift => "<<",
+        Token::OpRightShift => ">>",
         Token::OpEq => "==",
         Token::OpBangEq => "!=",
         Token::OpLess => "<",
         Token::OpLessEq => "<=",
         Token::OpMore => ">",
         Token::OpMoreEq => ">=",
-        _ => panic!(),
+        Token::KwAnd => "and",
+        Token::KwOr => "or",
+        _ => unreachable!("binary expressions are only built from operators"),
     }
 }
 
-impl Typechecker {
-    pub fn new() -> Self {
+impl<'s> Typechecker<'s> {
+    pub fn new(symbols: &'s SymbolTable) -> Self {
         Self {
-            functions: HashMap::new(),
-            variables: HashMap::new(),
+            symbols,
+            functions: vec![],
+            line: 0,
+            diagnostics: vec![],
         }
     }
 
-    fn get_value_type(&self, value: NodeValue) -> Type {
+    /// Whether the type of `value` depends on values that are only known at runtime,
+    /// like the attributes of a module or the names bound by destructuring.
+    fn runtime_typed(&self, value: &NodeValue) -> bool {
         match value {
-            NodeValue::NumberValue(_) => Type::Number,
-            NodeValue::BoolValue(_) => Type::Bool,
-            NodeValue::StringValue(_) => Type::String,
-            NodeValue::IdentifierValue(iden) => {
-                let var = self.variables.get(&iden);
+            /* Methods are declared as `void`, since there's no type that describes them */
+            NodeValue::GetAttribute(value, attribute) => match self.struct_attributes(value) {
+                Some((_, attributes)) => attributes.get(attribute) == Some(&Type::Void),
+                None => true,
+            },
+            NodeValue::Call(..) => true,
+            /* Functions can be passed around, but have no type that describes them */
+            NodeValue::IdentifierValue(name) => matches!(
+                self.lookup(name),
+                Some(symbol) if symbol.datatype == Type::Void || symbol.kind == SymbolKind::Function
+            ),
+            NodeValue::PrimitiveFunctionCall(index, _) => primitive_type(*index).is_none(),
+            NodeValue::ArrayValue(elements) => {
+                elements.iter().any(|element| self.runtime_typed(element))
+            }
+            NodeValue::Index(value, index) => {
+                self.runtime_typed(value) || self.runtime_typed(index)
+            }
+            NodeValue::Slice(value, start, end) => {
+                self.runtime_typed(value)
+                    || [start, end].into_iter().flatten().any(|bound| self.runtime_typed(bound))
+            }
+            NodeValue::Unary(value, _) => self.runtime_typed(value),
+            /* Whatever the value is, the result has the type it's converted to */
+            NodeValue::Convert(..) => false,
+            NodeValue::Binary(left, right, _) => {
+                self.runtime_typed(left) || self.runtime_typed(right)
+            }
+            NodeValue::Conditional(condition, ontrue, onfalse) => {
+                self.runtime_typed(condition)
+                    || self.runtime_typed(ontrue)
+                    || self.runtime_typed(onfalse)
+            }
+            _ => false,
+        }
+    }
+
+    /// Looks up `name` in the locals of the function being checked, then in the globals.
+    fn lookup(&self, name: &str) -> Option<&'s Symbol> {
+        let symbols = self.symbols;
 
-                match var {
-                    Some(t) => t.clone(),
-                    None => panic!("Variable {} not found", iden),
+        self.functions
+            .last()
+            .and_then(|function| symbols.get(Some(function), name))
+            .or_else(|| symbols.get(None, name))
+    }
+
+    /// The name and declared attributes of the struct `value` is an instance of, if that's
+    /// known without running the program.
+    fn struct_attributes(&self, value: &NodeValue) -> Option<(&'s str, &'s HashMap<String, Type>)> {
+        let name = match value {
+            NodeValue::IdentifierValue(name) => match self.lookup(name)? {
+                Symbol {
+                    datatype: Type::Struct(name),
+                    kind,
+                    ..
+                } if VARIABLE_KINDS.contains(kind) => name,
+                _ => return None,
+            },
+            NodeValue::GetAttribute(value, attribute) => {
+                match self.struct_attributes(value)?.1.get(attribute)? {
+                    Type::Struct(name) => name,
+                    _ => return None,
                 }
             }
-            NodeValue::FunctionCall(name, _) => {
-                let fun = self.functions.get(&name);
+            NodeValue::StructInit(name, _) => name,
+            _ => return None,
+        };
 
-                match fun {
-                    Some(t) => t.clone(),
-                    None => panic!("Function {} not found", name),
-                }
+        self.symbols.attributes(name)
+    }
+
+    /// The declared type of `attribute` of the struct `name`, after recording an error if the
+    /// struct doesn't declare it.
+    fn attribute_type(
+        &mut self,
+        name: &str,
+        attributes: &HashMap<String, Type>,
+        attribute: &str,
+    ) -> Result<Type, ()> {
+        match attributes.get(attribute) {
+            Some(datatype) => Ok(datatype.clone()),
+            None => {
+                let names = attributes.keys().map(String::as_str);
+                let message =
+                    not_found(&format!("'{}' attribute", source_name(name)), attribute, names);
+                self.error(ErrorCode::UnknownAttribute, message);
+                Err(())
             }
-            _ => panic!(),
         }
     }
 
-    fn eval_binary_expression(&self, value: NodeValue) -> Result<Type, ()> {
+    /// Records an error at the statement being checked.
+    fn error(&mut self, code: ErrorCode, message: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::error(code, message).with_span(Span::line(self.line + 1)));
+    }
+
+    /// Returns `Err` if the type can't be known, after recording why.
+    fn get_value_type(&mut self, value: NodeValue) -> Result<Type, ()> {
         match value {
-            NodeValue::Binary(left, right, op) => binary_return_type(
-                token_to_op(op),
-                self.eval_binary_expression(*left)?,
-                self.eval_binary_expression(*right)?,
-            ),
-            _ => Ok(self.get_value_type(value)),
+            NodeValue::NumberValue(_) => Ok(Type::Number),
+            NodeValue::BoolValue(_) => Ok(Type::Bool),
+            NodeValue::StringValue(_) => Ok(Type::String),
+            NodeValue::NoneValue => Ok(Type::Void),
+            NodeValue::IdentifierValue(iden) => match self.lookup(&iden) {
+                Some(symbol) if VARIABLE_KINDS.contains(&symbol.kind) => Ok(symbol.datatype.clone()),
+                _ => {
+                    let names = self.symbols.names(None, VARIABLE_KINDS);
+                    let message = not_found("Variable", &iden, names);
+                    self.error(ErrorCode::UndefinedVariable, message);
+                    Err(())
+                }
+            },
+            NodeValue::FunctionCall(name, arguments) => match self.lookup(&name) {
+                Some(symbol) if symbol.kind == SymbolKind::Function => {
+                    let found: Vec<Option<Type>> = arguments
+                        .iter()
+                        .map(|argument| {
+                            let message = "Error in argument: Invalid types";
+                            self.expression_type(argument.clone(), message)
+                        })
+                        .collect();
+
+                    let datatype = symbol.datatype.clone();
+                    let parameters = symbol.parameters.clone().unwrap_or_default();
+
+                    if parameters.len() != arguments.len() {
+                        let arity = parameters.len();
+                        self.error(
+                            ErrorCode::WrongArgumentCount,
+                            format!(
+                                "'{}' takes {} argument{}, but {} {} given",
+                                name,
+                                arity,
+                                if arity == 1 { "" } else { "s" },
+                                arguments.len(),
+                                if arguments.len() == 1 { "was" } else { "were" }
+                            ),
+                        );
+                        return Err(());
+                    }
+
+                    for (idx, (expected, found)) in parameters.iter().zip(found).enumerate() {
+                        if let Some(found) = found.filter(|found| !fits(expected, found)) {
+                            self.error(
+                                ErrorCode::MismatchedTypes,
+                                format!(
+                                    "Argument {} of '{}' should be {:?}, got {:?}",
+                                    idx + 1,
+                                    name,
+                                    expected,
+                                    found
+                                ),
+                            );
+                        }
+                    }
+
+                    Ok(datatype)
+                }
+                _ => {
+                    let names = self.symbols.names(None, &[SymbolKind::Function]);
+                    let message = not_found("Function", &name, names);
+                    self.error(ErrorCode::UndefinedFunction, message);
+                    Err(())
+                }
+            },
+            NodeValue::PrimitiveFunctionCall(index, _) => primitive_type(index).ok_or(()),
+            NodeValue::StructInit(name, arguments) => {
+                if let Some((_, attributes)) = self.symbols.attributes(&name) {
+                    for (attribute, value) in arguments {
+                        let expected = match self.attribute_type(&name, attributes, &attribute) {
+                            Ok(expected) => expected,
+                            Err(()) => continue,
+                        };
+
+                        let message = "Error in struct literal: Invalid types";
+                        if let Some(found) = self
+                            .expression_type(value.inner, message)
+                            .filter(|found| !fits(&expected, found))
+                        {
+                            self.error(
+                                ErrorCode::MismatchedTypes,
+                                format!(
+                                    "Attribute '{}' of '{}' should be {:?}, got {:?}",
+                                    attribute,
+                                    source_name(&name),
+                                    expected,
+                                    found
+                                ),
+                            );
+                        }
+                    }
+                }
+
+                Ok(Type::Struct(name))
+            }
+            NodeValue::Unary(value, Unary::Negate) => match self.eval_binary_expression(*value)? {
+                Type::Number | Type::Bool => Ok(Type::Number),
+                datatype => {
+                    let message = format!("Only numbers can be negated, got {:?}", datatype);
+                    self.error(ErrorCode::InvalidOperands, message);
+                    Err(())
+                }
+            },
+            NodeValue::Unary(value, Unary::Not) => {
+                self.eval_binary_expression(*value)?;
+                Ok(Type::Bool)
+            }
+            NodeValue::Unary(value, Unary::Typeof) => {
+                self.eval_binary_expression(*value)?;
+                Ok(Type::String)
+            }
+            NodeValue::Convert(_, datatype) => Ok(datatype),
+            NodeValue::Index(value, index) => {
+                let datatype = self.eval_binary_expression(*value)?;
+                self.expect_index(*index)?;
+
+                match datatype {
+                    Type::String => Ok(Type::String),
+                    Type::Array(element) => Ok(*element),
+                    datatype => self.not_indexable(datatype),
+                }
+            }
+            NodeValue::Slice(value, start, end) => {
+                let datatype = self.eval_binary_expression(*value)?;
+
+                for bound in [start, end].into_iter().flatten() {
+                    self.expect_index(*bound)?;
+                }
+
+                match datatype {
+                    Type::String | Type::Array(_) => Ok(datatype),
+                    datatype => self.not_indexable(datatype),
+                }
+            }
+            NodeValue::ArrayValue(elements) => {
+                let mut element_type = Type::Void;
+
+                for element in elements {
+                    let datatype = self.eval_binary_expression(element)?;
+
+                    element_type = match common_type(&element_type, &datatype) {
+                        Some(common) => common,
+                        None if element_type == Type::Void => datatype,
+                        None => {
+                            self.error(
+                                ErrorCode::MismatchedTypes,
+                                format!(
+                                    "Array elements must have the same type, found {:?} and {:?}",
+                                    element_type, datatype
+                                ),
+                            );
+                            return Err(());
+                        }
+                    };
+                }
+
+                Ok(Type::Array(Box::new(element_type)))
+            }
+            NodeValue::GetAttribute(value, attribute) => match self.struct_attributes(&value) {
+                Some((name, attributes)) => self.attribute_type(name, attributes, &attribute),
+                /* Never reached, since `runtime_typed` keeps these from being checked */
+                None => Err(()),
+            },
+            NodeValue::Call(..) => Err(()),
+            _ => {
+                self.error(ErrorCode::UnexpectedToken, "Expected a value, found a statement");
+                Err(())
+            }
         }
     }
 
-    fn initialise(&mut self, program: &[Node]) {
-        for node in program.iter() {
-            match node.inner.clone() {
-                NodeValue::FunctionDecleration(name, _, _, _, return_type) => {
-                    self.functions.insert(name.clone(), return_type);
+    /// Checks that `index` is a number, since strings and arrays are indexed by position.
+    fn expect_index(&mut self, index: NodeValue) -> Result<(), ()> {
+        match self.eval_binary_expression(index)? {
+            Type::Number => Ok(()),
+            datatype => {
+                self.error(
+                    ErrorCode::MismatchedTypes,
+                    format!("Indexes must be numbers, got {:?}", datatype),
+                );
+                Err(())
+            }
+        }
+    }
+
+    fn not_indexable(&mut self, datatype: Type) -> Result<Type, ()> {
+        let message = format!("Only strings and arrays can be indexed, got {:?}", datatype);
+        self.error(ErrorCode::InvalidOperands, message);
+        Err(())
+    }
+
+    fn eval_binary_expression(&mut self, value: NodeValue) -> Result<Type, ()> {
+        match value {
+            NodeValue::Binary(left, right, op) => {
+                let op = token_to_op(op);
+                let left = self.eval_binary_expression(*left)?;
+                let right = self.eval_binary_expression(*right)?;
+
+                binary_return_type(op, left.clone(), right.clone()).map_err(|_| {
+                    let message = format!("Can't use '{}' on {:?} and {:?}", op, left, right);
+                    self.error(ErrorCode::InvalidOperands, message);
+                })
+            }
+            NodeValue::Conditional(condition, ontrue, onfalse) => {
+                self.eval_binary_expression(*condition)?;
+
+                /* Both branches have to agree, since either one may be the result. A branch
+                   that's `none` marks a missing value, like in `found ? index : none`, so the
+                   result may be `none` and is only stored where `none` fits */
+                let ontrue = self.eval_binary_expression(*ontrue)?;
+                let onfalse = self.eval_binary_expression(*onfalse)?;
+
+                if ontrue == Type::Void || onfalse == Type::Void {
+                    return Ok(common_type(&ontrue, &onfalse).unwrap_or(Type::Void));
                 }
-                _ => {}
+
+                common_type(&ontrue, &onfalse).ok_or_else(|| {
+                    let message = format!(
+                        "Both branches of a conditional must have the same type, got {:?} and {:?}",
+                        ontrue, onfalse
+                    );
+                    self.error(ErrorCode::MismatchedTypes, message);
+                })
             }
+            _ => self.get_value_type(value),
         }
     }
 
-    pub fn check(&mut self, program: Vec<Node>) {
-        self.initialise(&program);
+    /// The name and declared return type of the function being checked, if there is one.
+    fn return_type(&self) -> Option<(String, Type)> {
+        let (function, outer) = self.functions.split_last()?;
+        let symbol = self.symbols.get(outer.last().map(String::as_str), function)?;
 
-        for node in program.iter() {
-            match node.inner.clone() {
-                NodeValue::VariableDecleration(name, value, _, _, annotation) => {
-                    if let Ok(return_type) = self.eval_binary_expression(*value) {
-                        if annotation == return_type {
-                            self.variables.insert(name.clone(), return_type);
-                        } else {
-                            panic!("Expected type {:?}, got {:?}", annotation, return_type);
-                        }
-                    } else {
-                        panic!("Error in variable decleration: Invalid Types.")
+        Some((source_name(function).to_string(), symbol.datatype.clone()))
+    }
+
+    /// Checks every statement, returning all the errors that were found.
+    pub fn check(&mut self, program: &[Node]) -> Result<(), Vec<Diagnostic>> {
+        walk_block(self, program);
+
+        if self.diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.diagnostics))
+        }
+    }
+
+    /// Evaluates the type of `value`, reporting `message` if its operands don't fit together.
+    /// Nothing more is reported if the cause was already recorded, like an undefined name.
+    /// Returns `None` without checking anything if the type is only known at runtime.
+    fn expression_type(&mut self, value: NodeValue, message: &str) -> Option<Type> {
+        if self.runtime_typed(&value) {
+            return None;
+        }
+
+        let known = self.diagnostics.len();
+        let result = self.eval_binary_expression(value);
+
+        if result.is_err() && self.diagnostics.len() == known {
+            self.error(ErrorCode::InvalidOperands, message);
+        }
+
+        result.ok()
+    }
+}
+
+impl Visitor for Typechecker<'_> {
+    fn visit_node(&mut self, node: &Node) {
+        self.line = node.line;
+        walk_node(self, node)
+    }
+
+    fn visit_value(&mut self, value: &NodeValue) {
+        match value {
+            NodeValue::VariableDecleration(_, value, _, _, annotation) => {
+                let message = "Error in variable decleration: Invalid Types.";
+
+                /* `if let` and `while let` declare their variable without a type */
+                if let Some(return_type) = self.expression_type((**value).clone(), message) {
+                    if !fits(annotation, &return_type) {
+                        self.error(
+                            ErrorCode::MismatchedTypes,
+                            format!("Expected type {:?}, got {:?}", annotation, return_type),
+                        );
+                    }
+                }
+            }
+            NodeValue::VariableAssignment(name, value) => {
+                let message = "Error in assignment: Invalid types";
+                let datatype = self.lookup(name).map(|symbol| symbol.datatype.clone());
+
+                if let (Some(return_type), Some(datatype)) =
+                    (self.expression_type((**value).clone(), message), datatype)
+                {
+                    if !fits(&datatype, &return_type) {
+                        self.error(
+                            ErrorCode::MismatchedTypes,
+                            format!("Expected type {:?}, got {:?}", datatype, return_type),
+                        );
                     }
                 }
-                NodeValue::If(_if, _elseif, _else) => {
-                    let if_type = self.eval_binary_expression((*_if.0).clone());
+            }
+            NodeValue::Return(value) => {
+                let message = "Error in return statement: Invalid types";
 
-                    if let Err(_) = if_type {
-                        panic!("Error in if statement (IF): Invalid types")
+                if let (Some(found), Some((function, expected))) =
+                    (self.expression_type((**value).clone(), message), self.return_type())
+                {
+                    /* A bare `return` returns none, like reaching the end of the function */
+                    if found != Type::Void && !fits(&expected, &found) {
+                        self.error(
+                            ErrorCode::MismatchedTypes,
+                            format!("'{}' returns {:?}, got {:?}", function, expected, found),
+                        );
                     }
+                }
+            }
+            NodeValue::AttributeAssignment(object, attribute, value) => {
+                let message = "Error in assignment: Invalid types";
 
-                    for (condition, _) in _elseif {
-                        let if_type = self.eval_binary_expression((*condition).clone());
+                /* Checks the attributes the object is read through, like `a.b` of `a.b.c = 1` */
+                let expected = match self.expression_type((**object).clone(), message) {
+                    Some(_) => match self.struct_attributes(object) {
+                        Some((name, attributes)) => self.attribute_type(name, attributes, attribute),
+                        None => Err(()),
+                    },
+                    None => Err(()),
+                };
 
-                        if let Err(_) = if_type {
-                            panic!("Error in if statement (ELSEIF): Invalid types")
-                        }
+                if let (Ok(expected), Some(found)) =
+                    (expected, self.expression_type((**value).clone(), message))
+                {
+                    if !fits(&expected, &found) {
+                        self.error(
+                            ErrorCode::MismatchedTypes,
+                            format!("Expected type {:?}, got {:?}", expected, found),
+                        );
                     }
                 }
-                _ => {}
             }
+            call @ NodeValue::FunctionCall(..) => {
+                self.expression_type(call.clone(), "Error in function call: Invalid types");
+            }
+            NodeValue::PrimitiveFunctionCall(_, arguments) => {
+                for argument in arguments {
+                    self.expression_type(argument.clone(), "Error in argument: Invalid types");
+                }
+            }
+            NodeValue::If(_if, _elseif, _else) => {
+                self.expression_type((*_if.0).clone(), "Error in if statement (IF): Invalid types");
+                self.visit_block(&_if.1);
+
+                for (condition, body) in _elseif {
+                    self.expression_type(
+                        (**condition).clone(),
+                        "Error in if statement (ELSEIF): Invalid types",
+                    );
+                    self.visit_block(body);
+                }
+
+                if let Some(body) = _else {
+                    self.visit_block(body);
+                }
+            }
+            NodeValue::WhileStatement(condition, body) => {
+                let message = "Error in while statement: Invalid types";
+                self.expression_type((**condition).clone(), message);
+                self.visit_block(body);
+            }
+            NodeValue::FunctionDecleration(name, body, ..) => {
+                self.functions.push(name.clone());
+                self.visit_block(body);
+                self.functions.pop();
+            }
+            NodeValue::TypeDecleration(_, functions, variables) => {
+                for variable in variables.values() {
+                    self.visit_value(variable);
+                }
+
+                for function in functions {
+                    self.visit_value(function);
+                }
+            }
+            NodeValue::Defer(body) => self.visit_block(body),
+            _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pipeline::lace_pipeline_init, scope::Resolver};
+
+    /// Typechecks `source`, returning the lines and codes of the errors found.
+    fn check(source: &str) -> Vec<(usize, ErrorCode)> {
+        let mut resolver = Resolver::new();
+        let ast = resolver.resolve(lace_pipeline_init(source));
+
+        match Typechecker::new(&resolver.symbols).check(&ast) {
+            Ok(()) => vec![],
+            Err(diagnostics) => diagnostics
+                .into_iter()
+                .map(|diagnostic| (diagnostic.span.unwrap().line, diagnostic.code.unwrap()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reports_every_error_of_a_program() {
+        let source = "\
+let a: number = \"one\"
+let b: string = 1 + \"two\"
+let c: bool = missing
+let d: number = true ? 1 : \"three\"
+";
+
+        assert_eq!(
+            check(source),
+            vec![
+                (1, ErrorCode::MismatchedTypes),
+                (2, ErrorCode::InvalidOperands),
+                (3, ErrorCode::UndefinedVariable),
+                (4, ErrorCode::MismatchedTypes),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_blocks_and_function_bodies() {
+        let source = "\
+fn f(n: number): number {
+    let s: string = n
+    if n > 1 {
+        let b: bool = 3
+    }
+    while n < 0 {
+        n = \"negative\"
+    }
+    return n + \"x\"
+}
+";
+
+        assert_eq!(
+            check(source),
+            vec![
+                (2, ErrorCode::MismatchedTypes),
+                (4, ErrorCode::MismatchedTypes),
+                (7, ErrorCode::MismatchedTypes),
+                (9, ErrorCode::InvalidOperands),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_returns_against_the_return_type() {
+        let source = "\
+fn f(x: number): number {
+    let y: string = \"a\"
+    if x > 0 {
+        return x
+    }
+    return y
+}
+fn outer(): string {
+    fn inner(): bool {
+        return 1
+    }
+    return \"ok\"
+}
+fn untyped() {
+    return 1
+}
+fn early(x: number): number {
+    if x > 1 {
+        return
+    }
+    return x
+}
+";
+
+        assert_eq!(
+            check(source),
+            vec![(6, ErrorCode::MismatchedTypes), (10, ErrorCode::MismatchedTypes)]
+        );
+    }
+
+    #[test]
+    fn checks_arguments_against_the_parameters() {
+        let source = "\
+fn f(x: number, s: string): number {
+    return x
+}
+let a: number = f(1, \"s\")
+let b: number = f(\"s\", \"s\")
+let c: number = f(1, 2)
+f(true, [1])
+let d: number = f(1)
+";
+
+        assert_eq!(
+            check(source),
+            vec![
+                (5, ErrorCode::MismatchedTypes),
+                (6, ErrorCode::MismatchedTypes),
+                (7, ErrorCode::MismatchedTypes),
+                (7, ErrorCode::MismatchedTypes),
+                (8, ErrorCode::WrongArgumentCount),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_values_that_used_to_panic() {
+        assert_eq!(check("let x: number = -5\nlet y: bool = !x"), vec![]);
+        assert_eq!(check("let x: number = none"), vec![(1, ErrorCode::MismatchedTypes)]);
+        assert_eq!(check("let x: bool = true and false or true"), vec![]);
+        assert_eq!(check("let x: number = -\"a\""), vec![(1, ErrorCode::InvalidOperands)]);
+    }
+
+    #[test]
+    fn leaves_runtime_typed_values_alone() {
+        let source = "\
+struct Point { x: number, start: Point }
+let p: Point = Point { x: 1 }
+let x: number = p.x
+let y: number = p.start.x
+let [a, b] = [1, 2]
+let c: string = a
+fn id(n: number): number {
+    return n
+}
+print!(id == id)
+";
+
+        assert_eq!(check(source), vec![]);
+    }
+
+    #[test]
+    fn conditionals_that_may_be_none_only_fit_where_none_does() {
+        let source = "\
+struct Point { x: number }
+let c: bool = true
+let a: number = c ? 1 : none
+let b: string = c ? none : \"s\"
+let p: Point = c ? Point { x: 1 } : none
+let m: number = (c ? 1 : none) + 1
+";
+
+        assert_eq!(
+            check(source),
+            vec![
+                (3, ErrorCode::MismatchedTypes),
+                (4, ErrorCode::MismatchedTypes),
+                (6, ErrorCode::InvalidOperands),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_struct_literals_against_the_declaration() {
+        let source = "\
+struct Point { x: number, y: number }
+let a: Point = Point { x: 1, y: 2 }
+let b: Point = Point { x: \"s\", y: 1 }
+let c: Point = Point { x: 1, z: 2 }
+let d: Point = Point { x: 1, y: true ? 1 : \"s\" }
+";
+
+        assert_eq!(
+            check(source),
+            vec![
+                (3, ErrorCode::MismatchedTypes),
+                (4, ErrorCode::UnknownAttribute),
+                (5, ErrorCode::MismatchedTypes),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_attribute_assignments_against_the_declaration() {
+        let source = "\
+struct Point { x: number, y: number }
+struct Line { start: Point, label: string }
+let p: Point = Point { x: 1, y: 2 }
+p.x = 3
+p.w = 3
+p.x = \"str\"
+let l: Line = Line { start: p }
+l.start.y = 4
+l.start.y = \"4\"
+l.end.y = 4
+";
+
+        assert_eq!(
+            check(source),
+            vec![
+                (5, ErrorCode::UnknownAttribute),
+                (6, ErrorCode::MismatchedTypes),
+                (9, ErrorCode::MismatchedTypes),
+                (10, ErrorCode::UnknownAttribute),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_functions_with_the_same_name_keep_their_own_locals() {
+        let source = "\
+fn a(): number {
+    fn inner(): number {
+        let x: number = 1
+        return x + 1
+    }
+
+    return inner()
+}
+
+fn b(): string {
+    fn inner(): string {
+        let x: string = \"1\"
+        return x + \"1\"
+    }
+
+    return inner()
+}
+";
+
+        assert_eq!(check(source), vec![]);
+    }
+
+    #[test]
+    fn checks_the_bodies_of_methods() {
+        let source = "\
+type Counter {
+    let count: number = 0
+
+    fn next(step: number): number {
+        let doubled: number = step * 2
+        return doubled + undefined
+    }
+
+    fn label(): number {
+        return \"counter\"
+    }
+}
+";
+
+        assert_eq!(
+            check(source),
+            vec![(6, ErrorCode::UndefinedVariable), (10, ErrorCode::MismatchedTypes)]
+        );
+    }
+
+    #[test]
+    fn gives_attributes_their_declared_type() {
+        let source = "\
+struct Point { x: number, label: string }
+let p: Point = Point { x: 1 }
+let x: number = p.x
+let s: string = p.x
+let n: string = p.label * 2
+let w: number = p.w
+let l: number = Point { x: 2 }.label
+";
+
+        assert_eq!(
+            check(source),
+            vec![
+                (4, ErrorCode::MismatchedTypes),
+                (6, ErrorCode::UnknownAttribute),
+                (7, ErrorCode::MismatchedTypes),
+            ]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use crate::parser::{Node, NodeValue};
+
+/// Traverses a lace AST.
+///
+/// Every method walks the children of what it's given by default, so implementors only
+/// need to override the methods for the nodes they care about. Overriding methods can call
+/// the matching `walk_*` function to continue into the children.
+pub trait Visitor {
+    fn visit_block(&mut self, block: &[Node]) {
+        walk_block(self, block)
+    }
+
+    fn visit_node(&mut self, node: &Node) {
+        walk_node(self, node)
+    }
+
+    fn visit_value(&mut self, value: &NodeValue) {
+        walk_value(self, value)
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &[Node]) {
+    for node in block {
+        visitor.visit_node(node);
+    }
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    visitor.visit_value(&node.inner);
+}
+
+pub fn walk_value<V: Visitor + ?Sized>(visitor: &mut V, value: &NodeValue) {
+    match value {
+        NodeValue::StringValue(_)
+        | NodeValue::IdentifierValue(_)
+        | NodeValue::NumberValue(_)
+        | NodeValue::BoolValue(_)
+        | NodeValue::NoneValue
+        | NodeValue::Asm(_)
+        | NodeValue::ImportStatement(..) => {}
+
+        NodeValue::ArrayValue(values)
+        | NodeValue::FunctionCall(_, values)
+        | NodeValue::PrimitiveFunctionCall(_, values) => {
+            for value in values {
+                visitor.visit_value(value);
+            }
+        }
+        NodeValue::Call(callee, arguments) => {
+            visitor.visit_value(callee);
+
+            for argument in arguments {
+                visitor.visit_value(argument);
+            }
+        }
+        NodeValue::StructInit(_, arguments) => {
+            for (_, argument) in arguments {
+                visitor.visit_node(argument);
+            }
+        }
+        NodeValue::Unary(value, _)
+        | NodeValue::GetAttribute(value, _)
+        | NodeValue::Convert(value, _)
+        | NodeValue::VariableDecleration(_, value, ..)
+        | NodeValue::Destructure(_, value)
+        | NodeValue::VariableAssignment(_, value)
+        | NodeValue::Return(value) => visitor.visit_value(value),
+        NodeValue::Binary(left, right, _)
+        | NodeValue::Index(left, right)
+        | NodeValue::AttributeAssignment(left, _, right) => {
+            visitor.visit_value(left);
+            visitor.visit_value(right);
+        }
+        NodeValue::Slice(value, start, end) => {
+            visitor.visit_value(value);
+
+            for bound in [start, end].into_iter().flatten() {
+                visitor.visit_value(bound);
+            }
+        }
+        NodeValue::Conditional(condition, ontrue, onfalse) => {
+            visitor.visit_value(condition);
+            visitor.visit_value(ontrue);
+            visitor.visit_value(onfalse);
+        }
+        NodeValue::FunctionDecleration(_, body, ..) | NodeValue::Defer(body) => {
+            visitor.visit_block(body)
+        }
+        NodeValue::TypeDecleration(_, functions, variables) => {
+            for function in functions {
+                visitor.visit_value(function);
+            }
+
+            for variable in variables.values() {
+                visitor.visit_value(variable);
+            }
+        }
+        NodeValue::WhileStatement(condition, body) => {
+            visitor.visit_value(condition);
+            visitor.visit_block(body);
+        }
+        NodeValue::If(ontrue, onelseif, onfalse) => {
+            visitor.visit_value(&ontrue.0);
+            visitor.visit_block(&ontrue.1);
+
+            for (condition, body) in onelseif {
+                visitor.visit_value(condition);
+                visitor.visit_block(body);
+            }
+
+            if let Some(body) = onfalse {
+                visitor.visit_block(body);
+            }
+        }
+    }
+}
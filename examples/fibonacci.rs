@@ -0,0 +1,7 @@
+//! `cargo run --example fibonacci` builds and runs fibonacci.lc.
+
+mod run;
+
+fn main() {
+    run::lace(include_str!("fibonacci.lc"))
+}
@@ -0,0 +1,7 @@
+//! `cargo run --example fizzbuzz` builds and runs fizzbuzz.lc.
+
+mod run;
+
+fn main() {
+    run::lace(include_str!("fizzbuzz.lc"))
+}
@@ -0,0 +1,37 @@
+//! Shared by the example targets, each of which builds and runs one of the `.lc` programs
+//! next to it. They're declared in shoelace's Cargo.toml, so from the shoelace directory
+//! `cargo run --example fizzbuzz` runs `fizzbuzz.lc`.
+
+use hlvm::{lir::HlvmValue, vm::HighLevelVirtualMachine};
+
+/// Compiles and runs `source`, then exits with its exit code.
+pub fn lace(source: &str) -> ! {
+    let instructions = match compiler::pipeline::compile_program(source) {
+        Ok(instructions) => instructions,
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                diagnostic.emit();
+            }
+
+            std::process::exit(1)
+        }
+    };
+
+    let mut vm = HighLevelVirtualMachine::new(Some(1));
+
+    let result = vm.execute(&instructions).and_then(|_| {
+        match vm.call_stack[0].locals.get("main") {
+            Some(HlvmValue::Function(..)) => vm.call("main", vec![]),
+            _ => Ok(HlvmValue::None),
+        }
+    });
+
+    match result {
+        Ok(HlvmValue::Number(code)) => std::process::exit(code as i32),
+        Ok(_) => std::process::exit(0),
+        Err(err) => {
+            println!("Error: {}", err);
+            std::process::exit(1)
+        }
+    }
+}
@@ -0,0 +1,7 @@
+//! `cargo run --example strings` builds and runs strings.lc.
+
+mod run;
+
+fn main() {
+    run::lace(include_str!("strings.lc"))
+}
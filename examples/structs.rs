@@ -0,0 +1,7 @@
+//! `cargo run --example structs` builds and runs structs.lc.
+
+mod run;
+
+fn main() {
+    run::lace(include_str!("structs.lc"))
+}
@@ -0,0 +1,85 @@
+use crate::lir::HlvmInstruction;
+
+/// A position in the instructions that jumps can target before it is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label(usize);
+
+/// Builds a list of instructions, resolving jumps to labels once all of them are placed.
+/// Labels can be jumped to before they're bound, which avoids patching jumps by hand.
+#[derive(Debug, Default)]
+pub struct InstructionBuilder {
+    instructions: Vec<HlvmInstruction>,
+
+    /// The address every label is bound to, `None` until `bind` is called.
+    labels: Vec<Option<usize>>,
+
+    /// The jumps that have to be patched with the address of a label.
+    fixups: Vec<(usize, Label)>,
+}
+
+impl InstructionBuilder {
+    pub fn new() -> InstructionBuilder {
+        InstructionBuilder::default()
+    }
+
+    /// The address of the next instruction.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    pub fn push(&mut self, instruction: HlvmInstruction) {
+        self.instructions.push(instruction);
+    }
+
+    pub fn extend(&mut self, instructions: impl IntoIterator<Item = HlvmInstruction>) {
+        self.instructions.extend(instructions);
+    }
+
+    /// Creates a label that isn't bound to an address yet.
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Binds `label` to the address of the next instruction.
+    pub fn bind(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.len());
+    }
+
+    /// Creates a label bound to the address of the next instruction.
+    pub fn here(&mut self) -> Label {
+        let label = self.label();
+        self.bind(label);
+        label
+    }
+
+    pub fn jump(&mut self, label: Label) {
+        self.fixups.push((self.len(), label));
+        self.push(HlvmInstruction::Jump(0));
+    }
+
+    pub fn jump_if(&mut self, label: Label) {
+        self.fixups.push((self.len(), label));
+        self.push(HlvmInstruction::JumpIf(0));
+    }
+
+    /// Resolves every jump to its label and returns the instructions.
+    /// Panics if a label that is jumped to was never bound.
+    pub fn finish(mut self) -> Vec<HlvmInstruction> {
+        for (offset, label) in self.fixups {
+            let address = self.labels[label.0].expect("Jump to a label that was never bound");
+
+            self.instructions[offset] = match self.instructions[offset] {
+                HlvmInstruction::Jump(_) => HlvmInstruction::Jump(address),
+                HlvmInstruction::JumpIf(_) => HlvmInstruction::JumpIf(address),
+                _ => unreachable!(),
+            };
+        }
+
+        self.instructions
+    }
+}
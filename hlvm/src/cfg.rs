@@ -0,0 +1,334 @@
+/* --------------------------------------------------------------
+Control-flow graphs over the IR, and a verification pass that catches lowering bugs
+(jumps into the middle of nowhere, unbalanced stacks) before a program is ever run.
+-------------------------------------------------------------- */
+
+use crate::{
+    ir::{BlockId, Ir, IrBuilder, Terminator},
+    lir::{HlvmInstruction, HlvmValue, MAX_OPERAND},
+    stream,
+    vm::PRIMITIVES,
+};
+
+/// The edges between the blocks of an `Ir`, indexed by block.
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    pub successors: Vec<Vec<BlockId>>,
+    pub predecessors: Vec<Vec<BlockId>>,
+}
+
+impl Cfg {
+    pub fn new(ir: &Ir) -> Cfg {
+        let mut cfg = Cfg {
+            successors: vec![vec![]; ir.blocks.len()],
+            predecessors: vec![vec![]; ir.blocks.len()],
+        };
+
+        for (id, block) in ir.blocks.iter().enumerate() {
+            let targets = match block.terminator {
+                Terminator::Jump(target) => vec![target],
+                Terminator::Branch { then, otherwise } => vec![then, otherwise],
+                Terminator::Return | Terminator::ReturnValue | Terminator::Exit => vec![],
+            };
+
+            for target in targets {
+                cfg.successors[id].push(target);
+
+                if let Some(predecessors) = cfg.predecessors.get_mut(target) {
+                    predecessors.push(id);
+                }
+            }
+        }
+
+        cfg
+    }
+
+    /// Which blocks can be reached from the entry of `ir`.
+    pub fn reachable(&self, ir: &Ir) -> Vec<bool> {
+        let mut reachable = vec![false; self.successors.len()];
+        let mut pending: Vec<BlockId> = ir.layout.first().copied().into_iter().collect();
+
+        while let Some(block) = pending.pop() {
+            if block < reachable.len() && !reachable[block] {
+                reachable[block] = true;
+                pending.extend(&self.successors[block]);
+            }
+        }
+
+        reachable
+    }
+}
+
+impl Ir {
+    /// Splits already emitted instructions back into basic blocks.
+    pub fn from_lir(instructions: Vec<HlvmInstruction>) -> Ir {
+        let mut builder = IrBuilder::new();
+        builder.raw(instructions);
+        builder.finish()
+    }
+}
+
+/// How many values `instruction` pops and pushes,
+/// or `None` if that depends on the values themselves.
+fn stack_effect(instruction: &HlvmInstruction) -> Option<(usize, usize)> {
+    use HlvmInstruction::*;
+
+    match instruction {
+        Push(_) | Get(_) | GetLocal(_) | GetGlobal(_) => Some((0, 1)),
+        SetLocal(_) | SetGlobal(_) | SetAttribute(_) | Pop => Some((1, 0)),
+        GetAttribute(_) | Not | Negate | Typeof | Convert(_) => Some((1, 1)),
+        Add | Subtract | Multiply | Divide | Modulo | Power | ShiftLeft | ShiftRight | Equal
+        | NotEqual | GreaterThan | LessThan | GreaterThanOrEqual | LessThanOrEqual | And | Or
+        | BinaryAnd | BinaryOr | Index => Some((2, 1)),
+        Slice => Some((3, 1)),
+        CallPrimitive(_, args) => Some((*args, 1)),
+        Call(args) => Some((args + 1, 1)),
+        BuildArray(count) => Some((*count, 1)),
+        Unpack(count, rest) => Some((1, count + *rest as usize)),
+        Jump(_) | Return => Some((0, 0)),
+        JumpIf(_) | ReturnValue => Some((1, 0)),
+
+        /* Blueprints pop as many values as they have attributes */
+        Instantiate => None,
+    }
+}
+
+/// Checks that every block of `ir` only changes control flow through its terminator,
+/// that every jump targets a placed block, and that every block that's reached from
+/// more than one place is entered with the same stack depth.
+pub fn verify(ir: &Ir) -> Result<(), String> {
+    let cfg = Cfg::new(ir);
+
+    for (id, block) in ir.blocks.iter().enumerate() {
+        if let Some(instruction) = block.instructions.iter().find(|instruction| {
+            matches!(
+                instruction,
+                HlvmInstruction::Jump(_)
+                    | HlvmInstruction::JumpIf(_)
+                    | HlvmInstruction::Return
+                    | HlvmInstruction::ReturnValue
+            )
+        }) {
+            return Err(format!(
+                "Block {} contains {:?}, which should be its terminator",
+                id, instruction
+            ));
+        }
+
+        if let Some(target) = cfg.successors[id]
+            .iter()
+            .find(|target| !ir.layout.contains(target))
+        {
+            return Err(format!(
+                "Block {} jumps to block {}, which is never placed",
+                id, target
+            ));
+        }
+    }
+
+    /* The depth every block is entered with, `None` once it can't be known */
+    let mut depths: Vec<Option<Option<usize>>> = vec![None; ir.blocks.len()];
+    let mut pending = vec![];
+
+    if let Some(&entry) = ir.layout.first() {
+        depths[entry] = Some(Some(0));
+        pending.push(entry);
+    }
+
+    while let Some(id) = pending.pop() {
+        let block = &ir.blocks[id];
+        let mut depth = depths[id].unwrap();
+
+        for (idx, instruction) in block.instructions.iter().enumerate() {
+            depth = match (depth, stack_effect(instruction)) {
+                (Some(depth), Some((pops, _))) if pops > depth => {
+                    return Err(format!(
+                        "Instruction {} of block {} ({:?}) pops {} values, but the stack only \
+                         holds {}",
+                        idx, id, instruction, pops, depth
+                    ))
+                }
+                (Some(depth), Some((pops, pushes))) => Some(depth - pops + pushes),
+                _ => None,
+            };
+        }
+
+        let pops = match block.terminator {
+            Terminator::Branch { .. } | Terminator::ReturnValue => 1,
+            _ => 0,
+        };
+
+        if let Some(current) = depth {
+            if pops > current {
+                return Err(format!(
+                    "The terminator of block {} ({:?}) pops a value off an empty stack",
+                    id, block.terminator
+                ));
+            }
+        }
+
+        let depth = depth.map(|depth| depth - pops);
+
+        for &target in &cfg.successors[id] {
+            match depths[target] {
+                None => {
+                    depths[target] = Some(depth);
+                    pending.push(target);
+                }
+                Some(Some(expected)) if matches!(depth, Some(depth) if depth != expected) => {
+                    return Err(format!(
+                        "Block {} is entered with a stack depth of {}, but block {} enters it \
+                         with {}",
+                        target,
+                        expected,
+                        id,
+                        depth.unwrap()
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Verifies emitted instructions, along with the bodies of the functions they push.
+pub fn verify_lir(instructions: &[HlvmInstruction]) -> Result<(), String> {
+    for (idx, instruction) in instructions.iter().enumerate() {
+        match instruction {
+            HlvmInstruction::Jump(addr) | HlvmInstruction::JumpIf(addr)
+                if *addr > instructions.len() =>
+            {
+                return Err(format!("Instruction {} jumps past the end, to {}", idx, addr));
+            }
+            HlvmInstruction::CallPrimitive(index, _) if *index >= PRIMITIVES.len() => {
+                return Err(format!(
+                    "Instruction {} calls primitive function {}, but there are only {}",
+                    idx,
+                    index,
+                    PRIMITIVES.len()
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    verify(&Ir::from_lir(instructions.to_vec()))?;
+
+    for (idx, instruction) in instructions.iter().enumerate() {
+        if let HlvmInstruction::Push(HlvmValue::Function(body, ..)) = instruction {
+            verify_lir(body)
+                .map_err(|err| format!("In the function pushed by instruction {}: {}", idx, err))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that no operand of `instructions`, or of the functions they push, is larger than
+/// `MAX_OPERAND`, and that none of them has more instructions than a jump can target.
+pub fn check_limits(instructions: &[HlvmInstruction]) -> Result<(), String> {
+    if instructions.len() > MAX_OPERAND {
+        return Err(format!(
+            "{} instructions are more than the limit of {} per function",
+            instructions.len(),
+            MAX_OPERAND
+        ));
+    }
+
+    for decoded in stream::instructions(instructions) {
+        if let HlvmInstruction::Push(HlvmValue::Function(body, ..)) = decoded.instruction {
+            if body.len() > MAX_OPERAND {
+                return Err(format!(
+                    "The function pushed by {} has {} instructions, which is more than the \
+                     limit of {} per function",
+                    decoded.location(),
+                    body.len(),
+                    MAX_OPERAND
+                ));
+            }
+        }
+
+        if let Some(operand) = decoded.operands.iter().find(|operand| **operand > MAX_OPERAND) {
+            return Err(format!(
+                "The operand {} of {} is more than the limit of {}",
+                operand,
+                decoded.location(),
+                MAX_OPERAND
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operands_up_to_the_limit_are_allowed() {
+        let instructions = vec![
+            HlvmInstruction::BuildArray(MAX_OPERAND),
+            HlvmInstruction::Jump(MAX_OPERAND),
+        ];
+
+        assert_eq!(check_limits(&instructions), Ok(()));
+    }
+
+    /* Operands past the limit don't fit a 32-bit usize to begin with */
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn operands_past_the_limit_are_rejected() {
+        let error = check_limits(&[HlvmInstruction::Jump(MAX_OPERAND + 1)]).unwrap_err();
+        assert!(error.contains(&(MAX_OPERAND + 1).to_string()), "{}", error);
+
+        /* Including the operands of instructions in the functions a program pushes */
+        let function = HlvmValue::Function(
+            vec![HlvmInstruction::Call(MAX_OPERAND + 1)],
+            vec![],
+            None,
+            None,
+            0,
+        );
+
+        assert!(check_limits(&[HlvmInstruction::Push(function)]).is_err());
+    }
+
+    /// `while i < 3 { print!(i) i = i + 1 }`, with `discard` after the call to `print!`.
+    fn counting_loop(discard: bool) -> Vec<HlvmInstruction> {
+        use HlvmInstruction::*;
+
+        let mut body = vec![Get("i".into()), CallPrimitive(0, 1)];
+        body.extend(discard.then_some(Pop));
+        body.extend([Get("i".into()), Push(1.0.into()), Add, SetLocal("i".into())]);
+
+        let mut instructions = vec![Push(0.0.into()), SetLocal("i".into())];
+        let condition = instructions.len();
+        let end = condition + 5 + body.len() + 1;
+
+        instructions.extend([Get("i".into()), Push(3.0.into()), LessThan, Not, JumpIf(end)]);
+        instructions.extend(body);
+        instructions.push(Jump(condition));
+        instructions
+    }
+
+    #[test]
+    fn loops_that_discard_call_results_are_valid() {
+        assert_eq!(verify_lir(&counting_loop(true)), Ok(()));
+    }
+
+    #[test]
+    fn loops_that_leave_values_behind_are_rejected() {
+        let error = verify_lir(&counting_loop(false)).unwrap_err();
+        assert!(error.contains("stack depth"), "{}", error);
+    }
+
+    #[test]
+    fn calls_to_primitives_that_dont_exist_are_rejected() {
+        let instructions = [HlvmInstruction::CallPrimitive(PRIMITIVES.len(), 0)];
+        let error = verify_lir(&instructions).unwrap_err();
+        assert!(error.contains("primitive function"), "{}", error);
+    }
+}
@@ -0,0 +1,163 @@
+/* --------------------------------------------------------------
+Conversions between Rust types and HlvmValue, for programs embedding the vm.
+Converting into a value always succeeds, converting out of one fails if the types don't match.
+-------------------------------------------------------------- */
+
+use crate::{error::ErrorCode, lir::HlvmValue};
+use hashbrown::HashMap;
+use std::rc::Rc;
+
+fn mismatch(expected: &str, value: &HlvmValue) -> String {
+    ErrorCode::InvalidConversion.message(format!("Expected {}, got {:?}", expected, value))
+}
+
+impl HlvmValue {
+    /// Returns `None` if the value is `none`, so optional values can be converted with
+    /// `value.into_option().map(f64::try_from).transpose()`.
+    pub fn into_option(self) -> Option<HlvmValue> {
+        match self {
+            HlvmValue::None => None,
+            value => Some(value),
+        }
+    }
+}
+
+impl From<f64> for HlvmValue {
+    fn from(value: f64) -> Self {
+        HlvmValue::Number(value)
+    }
+}
+
+impl From<bool> for HlvmValue {
+    fn from(value: bool) -> Self {
+        HlvmValue::Bool(value)
+    }
+}
+
+impl From<String> for HlvmValue {
+    fn from(value: String) -> Self {
+        HlvmValue::String(value)
+    }
+}
+
+impl From<&str> for HlvmValue {
+    fn from(value: &str) -> Self {
+        HlvmValue::String(value.to_string())
+    }
+}
+
+impl<T: Into<HlvmValue>> From<Vec<T>> for HlvmValue {
+    fn from(value: Vec<T>) -> Self {
+        HlvmValue::array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<HlvmValue>> From<Option<T>> for HlvmValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(HlvmValue::None, Into::into)
+    }
+}
+
+/// Maps become struct instances, with every key as an attribute.
+impl<T: Into<HlvmValue>> From<HashMap<String, T>> for HlvmValue {
+    fn from(value: HashMap<String, T>) -> Self {
+        HlvmValue::instance(value.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl<T: Into<HlvmValue>> From<std::collections::HashMap<String, T>> for HlvmValue {
+    fn from(value: std::collections::HashMap<String, T>) -> Self {
+        HlvmValue::instance(value.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl TryFrom<HlvmValue> for f64 {
+    type Error = String;
+
+    fn try_from(value: HlvmValue) -> Result<Self, Self::Error> {
+        match value {
+            HlvmValue::Number(number) => Ok(number),
+            value => Err(mismatch("a number", &value)),
+        }
+    }
+}
+
+impl TryFrom<HlvmValue> for bool {
+    type Error = String;
+
+    fn try_from(value: HlvmValue) -> Result<Self, Self::Error> {
+        match value {
+            HlvmValue::Bool(bool) => Ok(bool),
+            value => Err(mismatch("a bool", &value)),
+        }
+    }
+}
+
+impl TryFrom<HlvmValue> for String {
+    type Error = String;
+
+    fn try_from(value: HlvmValue) -> Result<Self, Self::Error> {
+        match value {
+            HlvmValue::String(string) => Ok(string),
+            value => Err(mismatch("a string", &value)),
+        }
+    }
+}
+
+impl<T: TryFrom<HlvmValue, Error = String>> TryFrom<HlvmValue> for Vec<T> {
+    type Error = String;
+
+    fn try_from(value: HlvmValue) -> Result<Self, Self::Error> {
+        match value {
+            HlvmValue::Array(values) => {
+                Rc::unwrap_or_clone(values).into_iter().map(T::try_from).collect()
+            }
+            value => Err(mismatch("an array", &value)),
+        }
+    }
+}
+
+impl<T: TryFrom<HlvmValue, Error = String>> TryFrom<HlvmValue> for HashMap<String, T> {
+    type Error = String;
+
+    fn try_from(value: HlvmValue) -> Result<Self, Self::Error> {
+        match value {
+            HlvmValue::StructInstance(attributes, _) => attributes
+                .into_iter()
+                .map(|(k, v)| Ok((k, T::try_from(v)?)))
+                .collect(),
+            value => Err(mismatch("a struct instance", &value)),
+        }
+    }
+}
+
+/// Numbers are floats, so integers convert into them losslessly up to 2^53,
+/// and only whole numbers within range convert back.
+macro_rules! integer_conversions {
+    ($($int:ty),*) => {$(
+        impl From<$int> for HlvmValue {
+            fn from(value: $int) -> Self {
+                HlvmValue::Number(value as f64)
+            }
+        }
+
+        impl TryFrom<HlvmValue> for $int {
+            type Error = String;
+
+            fn try_from(value: HlvmValue) -> Result<Self, Self::Error> {
+                match value {
+                    HlvmValue::Number(number)
+                        if number.fract() == 0.0
+                            && number >= <$int>::MIN as f64
+                            && number <= <$int>::MAX as f64 =>
+                    {
+                        Ok(number as $int)
+                    }
+                    value => Err(mismatch(concat!("a number that fits in ", stringify!($int)), &value)),
+                }
+            }
+        }
+    )*};
+}
+
+integer_conversions!(i32, i64, u32, u64, usize);
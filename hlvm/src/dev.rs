@@ -2,17 +2,27 @@
 Contains all the default implementations for traits that can be used to customize the vm
 -------------------------------------------------------------- */
 
+use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
+use std::io::Write;
 
 use crate::{
-    lir::{HlvmCallFrame, HlvmValue},
+    error::ErrorCode,
+    lir::{Conversion, Frozen, HlvmCallFrame, HlvmValue},
     traits::*,
     vm::HighLevelVirtualMachine,
 };
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
+use std::rc::Rc;
 
+/// Joins the arguments of the printing primitives with spaces, without one after the last.
+pub(crate) fn join_arguments(var: &[HlvmValue]) -> String {
+    var.iter().map(ToString::to_string).collect::<Vec<String>>().join(" ")
+}
+
+/// `print!(a, b)` prints its arguments separated by spaces, followed by a newline.
 /* 0 */ pub fn hlvm_print(var: Vec<HlvmValue>) -> HlvmValue {
-    println!("{}", var[0]);
+    println!("{}", join_arguments(&var));
     HlvmValue::Number(0.0)
 }
 
@@ -20,11 +30,262 @@ use hashbrown::HashMap;
     std::process::exit(0)
 }
 
+/// `args!()` returns the amount of arguments passed to the program,
+/// `args!(n)` returns argument `n`, or an empty string if there are less arguments.
+/* 2 */ pub fn hlvm_args(var: Vec<HlvmValue>, arguments: &[String]) -> HlvmValue {
+    match var.first() {
+        Some(HlvmValue::Number(index)) => HlvmValue::String(
+            arguments
+                .get(*index as usize)
+                .cloned()
+                .unwrap_or_default(),
+        ),
+        _ => HlvmValue::Number(arguments.len() as f64),
+    }
+}
+
+/// `parse_number!(s)` parses a whole number, returning `none` if `s` isn't one.
+/* 3 */ pub fn hlvm_parse_number(var: Vec<HlvmValue>) -> HlvmValue {
+    match var.first() {
+        Some(HlvmValue::String(string)) => match string.trim().parse::<i64>() {
+            Ok(number) => HlvmValue::Number(number as f64),
+            Err(_) => HlvmValue::None,
+        },
+        _ => HlvmValue::None,
+    }
+}
+
+/// `parse_float!(s)` parses a decimal number like `-1.5` or `2e10`, returning `none` if `s` isn't one.
+/* 4 */ pub fn hlvm_parse_float(var: Vec<HlvmValue>) -> HlvmValue {
+    match var.first() {
+        Some(HlvmValue::String(string)) => {
+            let string = string.trim();
+
+            /* Rust also accepts words like "inf" and "NaN", which aren't valid lace numbers */
+            let is_numeric = string
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+
+            match string.parse::<f64>() {
+                Ok(number) if is_numeric => HlvmValue::Number(number),
+                _ => HlvmValue::None,
+            }
+        }
+        _ => HlvmValue::None,
+    }
+}
+
+/// `chars!(s)` splits `s` into an array of single-character strings.
+/* 5 */ pub fn hlvm_chars(var: Vec<HlvmValue>) -> HlvmValue {
+    match var.first() {
+        Some(HlvmValue::String(string)) => HlvmValue::array(
+            string
+                .chars()
+                .map(|c| HlvmValue::String(c.to_string()))
+                .collect(),
+        ),
+        _ => HlvmValue::None,
+    }
+}
+
+/// `ord!(c)` returns the unicode code point of the single-character string `c`, or `none`.
+/* 6 */ pub fn hlvm_ord(var: Vec<HlvmValue>) -> HlvmValue {
+    match var.first() {
+        Some(HlvmValue::String(string)) => {
+            let mut chars = string.chars();
+
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => HlvmValue::Number(c as u32 as f64),
+                _ => HlvmValue::None,
+            }
+        }
+        _ => HlvmValue::None,
+    }
+}
+
+/// `chr!(n)` returns the character with the unicode code point `n`, or `none` if there is none.
+/* 7 */ pub fn hlvm_chr(var: Vec<HlvmValue>) -> HlvmValue {
+    match var.first() {
+        Some(HlvmValue::Number(number)) if number.fract() == 0.0 && *number >= 0.0 => {
+            match char::from_u32(*number as u32) {
+                Some(c) => HlvmValue::String(c.to_string()),
+                None => HlvmValue::None,
+            }
+        }
+        _ => HlvmValue::None,
+    }
+}
+
+/// Calls `function` from within a primitive, binding `arguments` to its parameters in order.
+/// Errors if `function` takes a different amount of arguments than `count`.
+/// Calling it anyway would pop values that belong to the caller off the stack.
+pub fn check_arguments(function: &HlvmValue, count: usize) -> Result<(), String> {
+    match function {
+        HlvmValue::Function(_, parameters, ..) if parameters.len() != count => {
+            Err(ErrorCode::ArgumentCountMismatch.message(format!(
+                "Function takes {} argument{}, but {} {} given",
+                parameters.len(),
+                if parameters.len() == 1 { "" } else { "s" },
+                count,
+                if count == 1 { "was" } else { "were" }
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn call_with(
+    vm: &mut HighLevelVirtualMachine,
+    function: &HlvmValue,
+    arguments: Vec<HlvmValue>,
+) -> Result<HlvmValue, String> {
+    if let HlvmValue::Native(native) = function {
+        return native.call(arguments, vm);
+    }
+
+    check_arguments(function, arguments.len())?;
+    /* Parameters are popped in order, so the first argument has to end up on top */
+    vm.stack.extend(arguments.into_iter().rev());
+    function.call(vm)
+}
+
+/// `map!(array, f)` returns a new array with `f` applied to every element.
+/* 8 */ pub fn hlvm_map(
+    var: Vec<HlvmValue>,
+    vm: &mut HighLevelVirtualMachine,
+) -> Result<HlvmValue, String> {
+    match (var.first(), var.get(1)) {
+        (Some(HlvmValue::Array(array)), Some(function)) => Ok(HlvmValue::array(
+            array
+                .iter()
+                .map(|element| call_with(vm, function, vec![element.clone()]))
+                .collect::<Result<_, _>>()?,
+        )),
+        _ => Ok(HlvmValue::None),
+    }
+}
+
+/// `filter!(array, f)` returns the elements of `array` for which `f` returns a truthy value.
+/* 9 */ pub fn hlvm_filter(
+    var: Vec<HlvmValue>,
+    vm: &mut HighLevelVirtualMachine,
+) -> Result<HlvmValue, String> {
+    match (var.first(), var.get(1)) {
+        (Some(HlvmValue::Array(array)), Some(function)) => {
+            let mut filtered = vec![];
+
+            for element in array.iter() {
+                if call_with(vm, function, vec![element.clone()])?.is_truthy() {
+                    filtered.push(element.clone());
+                }
+            }
+
+            Ok(HlvmValue::array(filtered))
+        }
+        _ => Ok(HlvmValue::None),
+    }
+}
+
+/// `reduce!(array, f, initial)` folds `array` into one value, calling `f(accumulator, element)`.
+/* 10 */ pub fn hlvm_reduce(
+    var: Vec<HlvmValue>,
+    vm: &mut HighLevelVirtualMachine,
+) -> Result<HlvmValue, String> {
+    match (var.first(), var.get(1), var.get(2)) {
+        (Some(HlvmValue::Array(array)), Some(function), Some(initial)) => {
+            let mut accumulator = initial.clone();
+
+            for element in array.iter() {
+                accumulator = call_with(vm, function, vec![accumulator, element.clone()])?;
+            }
+
+            Ok(accumulator)
+        }
+        _ => Ok(HlvmValue::None),
+    }
+}
+
+/// `write!(a, b)` prints like `print!`, without the newline.
+/* 12 */ pub fn hlvm_write(var: Vec<HlvmValue>) -> HlvmValue {
+    print!("{}", join_arguments(&var));
+    std::io::stdout().flush().ok();
+    HlvmValue::Number(0.0)
+}
+
+/// `eprintln!(a, b)` prints like `print!`, but to stderr.
+/* 13 */ pub fn hlvm_eprintln(var: Vec<HlvmValue>) -> HlvmValue {
+    eprintln!("{}", join_arguments(&var));
+    HlvmValue::Number(0.0)
+}
+
+/// `repr!(value)` formats `value` with `repr`, so strings are quoted and escaped.
+/* 14 */ pub fn hlvm_repr(var: Vec<HlvmValue>) -> HlvmValue {
+    HlvmValue::String(var.first().map(repr).unwrap_or_default())
+}
+
+/// `clone!(value)` returns a deep copy of `value`.
+/// Values are copied on assignment anyway, this only makes the copy explicit.
+/* 15 */ pub fn hlvm_clone(var: Vec<HlvmValue>) -> HlvmValue {
+    var.into_iter().next().unwrap_or(HlvmValue::None)
+}
+
+/// `is!(a, b)` checks whether `a` and `b` are the same value, not just equal ones.
+/// Since values behave as copies, only immutable values (none, numbers, bools and strings)
+/// can be the same: they're identical whenever they're equal. Functions are the same when
+/// they come from the same declaration, and native objects when they're the same object.
+/// Arrays and struct instances are always distinct copies, even while an array still shares
+/// its elements with the array it was copied from.
+/* 16 */ pub fn hlvm_is(var: Vec<HlvmValue>) -> HlvmValue {
+    let none = HlvmValue::None;
+
+    HlvmValue::Bool(match (var.first().unwrap_or(&none), var.get(1).unwrap_or(&none)) {
+        (
+            a @ (HlvmValue::None
+            | HlvmValue::Number(_)
+            | HlvmValue::Bool(_)
+            | HlvmValue::String(_)),
+            b,
+        ) => a == b,
+        (HlvmValue::Function(.., a), HlvmValue::Function(.., b)) => a == b,
+        (HlvmValue::Native(a), HlvmValue::Native(b)) => a == b,
+        _ => false,
+    })
+}
+
+/// `memory_usage!()` returns about how many bytes the values the program keeps alive take,
+/// which is what `VmOptions::max_memory` limits.
+/* 24 */ pub fn hlvm_memory_usage(vm: &HighLevelVirtualMachine) -> HlvmValue {
+    HlvmValue::Number(vm.memory_usage() as f64)
+}
+
+/// `is_number!(value)`, `is_string!(value)` and the other `is_…!` primitives check whether
+/// `value` has the type `name`. Unlike comparing type names, a misspelled check is an error.
+/* 17-21 */ pub fn hlvm_is_type(var: Vec<HlvmValue>, name: &str) -> HlvmValue {
+    HlvmValue::Bool(var.first().is_some_and(|value| type_name(value) == name))
+}
+
+/// `each!(array, f)` calls `f` on every element of `array`, discarding the results.
+/* 11 */ pub fn hlvm_each(
+    var: Vec<HlvmValue>,
+    vm: &mut HighLevelVirtualMachine,
+) -> Result<HlvmValue, String> {
+    if let (Some(HlvmValue::Array(array)), Some(function)) = (var.first(), var.get(1)) {
+        for element in array.iter() {
+            call_with(vm, function, vec![element.clone()])?;
+        }
+    }
+
+    Ok(HlvmValue::None)
+}
+
 impl Initializable for HlvmValue {
-    fn initialize(&self, stack: &mut Vec<HlvmValue>) -> Result<HlvmValue, String> {
+    fn initialize(&self, _stack: &mut Vec<HlvmValue>) -> Result<HlvmValue, String> {
         match *self {
-            HlvmValue::StructBlueprint(ref attributes) => Ok(HlvmValue::StructInstance(attributes.clone())),
-            _ => Err(format!("Cannot initialize value {:?}", self)),
+            HlvmValue::StructBlueprint(ref attributes) => Ok(HlvmValue::instance(attributes.clone())),
+            _ => Err(ErrorCode::NotInitializable.message(format!(
+                "Cannot initialize value {:?}",
+                self
+            ))),
         }
     }
 }
@@ -32,21 +293,36 @@ impl Initializable for HlvmValue {
 impl Instance for HlvmValue {
     fn get(&self, name: String) -> Result<HlvmValue, String> {
         match *self {
-            HlvmValue::StructInstance(ref values) => Ok(match values.get(&name) {
+            HlvmValue::StructInstance(ref values, _) => Ok(match values.get(&name) {
                 Some(val) => val.clone(),
-                None => return Err(format!("Undefined parameter {}", name)),
+                None => {
+                    return Err(ErrorCode::UndefinedAttribute
+                        .message(format!("Undefined parameter {}", name)))
+                }
             }),
-            _ => Err(format!("Cannot get attribute value {:?}", self)),
+            HlvmValue::Native(ref native) => native.get(&name),
+            _ => Err(ErrorCode::NoAttributes.message(format!(
+                "Cannot get attribute value {:?}",
+                self
+            ))),
         }
     }
 
     fn set(&mut self, name: String, value: HlvmValue) -> Result<(), String> {
         match *self {
-            HlvmValue::StructInstance(ref mut values) => {
+            HlvmValue::StructInstance(_, Frozen(true)) => Err(ErrorCode::FrozenValue
+                .message(format!("Cannot set attribute {} of a frozen struct instance", name))),
+            /* Setting an attribute of a blueprint sets its default */
+            HlvmValue::StructInstance(ref mut values, _)
+            | HlvmValue::StructBlueprint(ref mut values) => {
                 values.insert(name, value);
                 Ok(())
             }
-            _ => Err(format!("Cannot set value {:?}", self)),
+            HlvmValue::Native(ref native) => native.set(&name, value),
+            _ => Err(ErrorCode::NoAttributes.message(format!(
+                "Cannot set value {:?}",
+                self
+            ))),
         }
     }
 }
@@ -54,14 +330,29 @@ impl Instance for HlvmValue {
 impl Callable for HlvmValue {
     fn call(&self, vm: &mut HighLevelVirtualMachine) -> Result<HlvmValue, String> {
         match self {
-            HlvmValue::Function(instructions, args, loc_prealloc) => {
+            HlvmValue::Function(instructions, args, loc_prealloc, stack_prealloc, _) => {
+                if let Some(max) = vm.options.max_call_depth {
+                    /* The first frame holds the globals, it isn't a call */
+                    if vm.call_stack.len() > max {
+                        return Err(ErrorCode::CallDepthExceeded
+                            .message(format!("Calls nested more than {} deep", max)));
+                    }
+                }
+
                 vm.call_stack.push(HlvmCallFrame {
                     locals: HashMap::with_capacity(loc_prealloc.unwrap_or(8)),
                 });
 
+                if let Some(depth) = stack_prealloc {
+                    vm.stack.reserve(*depth);
+                }
+
                 /* Push all of the arguments to the function's local scope */
                 for iden in args {
-                    let argument = vm.stack.pop().unwrap();
+                    let argument = vm.stack.pop().ok_or_else(|| {
+                        ErrorCode::StackUnderflow
+                            .message("Stack underflow while passing arguments to a function")
+                    })?;
                     vm.call_stack
                         .last_mut()
                         .unwrap()
@@ -69,103 +360,641 @@ impl Callable for HlvmValue {
                         .insert(iden.to_string(), argument);
                 }
 
-                let result = vm.execute(&instructions);
+                let result = vm.execute(instructions);
                 vm.call_stack.pop();
 
                 /* Push the result to the stack, return if an error occured */
-                match result {
-                    Ok(val) => Ok(val),
-                    Err(err) => return Result::Err(err),
-                }
+                result
             }
-            _ => Err(format!("Cannot call value {:?}", self)),
+            _ => Err(ErrorCode::NotCallable.message(format!(
+                "Cannot call value {:?}",
+                self
+            ))),
+        }
+    }
+}
+
+/// The name of the type of `value`, as it's written in error messages.
+pub fn type_name(value: &HlvmValue) -> &'static str {
+    match value {
+        HlvmValue::None => "none",
+        HlvmValue::Number(_) => "number",
+        HlvmValue::Bool(_) => "bool",
+        HlvmValue::String(_) => "string",
+        HlvmValue::Array(_) => "array",
+        HlvmValue::StructInstance(..) => "struct instance",
+        HlvmValue::StructBlueprint(_) => "struct",
+        HlvmValue::Function(..) | HlvmValue::BuiltInFunction(..) => "function",
+        HlvmValue::Native(_) => "native object",
+    }
+}
+
+/// The error for applying `operation` to values of the types of `a` and `b`.
+fn unsupported(operation: &str, a: &HlvmValue, b: &HlvmValue) -> String {
+    ErrorCode::UnsupportedOperands.message(format!(
+        "Unable to {} {} and {}",
+        operation,
+        type_name(a),
+        type_name(b)
+    ))
+}
+
+/// The length in bytes of `string` repeated `count` times, or `None` if no string can be
+/// that long.
+pub fn repeated_length(string: &str, count: f64) -> Option<usize> {
+    if string.is_empty() {
+        return Some(0);
+    }
+
+    string
+        .len()
+        .checked_mul(count as usize)
+        .filter(|&length| length <= isize::MAX as usize)
+}
+
+/// `string` repeated `count` times. Errors instead of aborting when there isn't enough memory
+/// for the result, since the count comes from the program.
+fn repeat(string: &str, count: f64) -> Result<String, String> {
+    let length = repeated_length(string, count).ok_or_else(|| {
+        ErrorCode::UnsupportedOperands.message(format!(
+            "Repeating a string of {} bytes {} times makes it too long",
+            string.len(),
+            format_number(count)
+        ))
+    })?;
+
+    let mut repeated = String::new();
+    repeated.try_reserve_exact(length).map_err(|_| {
+        ErrorCode::MemoryLimitExceeded
+            .message(format!("Unable to allocate {} bytes for a repeated string", length))
+    })?;
+
+    /* An empty string has a length of 0 however often it's repeated, so it's done already */
+    if !string.is_empty() {
+        for _ in 0..count as usize {
+            repeated.push_str(string);
         }
     }
+
+    Ok(repeated)
 }
 
 impl Operation<HlvmValue> for HlvmValue {
-    fn add(&self, b: HlvmValue) -> HlvmValue {
+    fn add(&self, b: HlvmValue) -> Result<HlvmValue, String> {
         match (self, b) {
-            (HlvmValue::Number(a), HlvmValue::Number(b)) => HlvmValue::Number(*a + b),
-            (HlvmValue::String(a), HlvmValue::String(_b)) => {
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => Ok(HlvmValue::Number(*a + b)),
+            (HlvmValue::String(a), HlvmValue::String(b)) => {
                 let mut a = a.clone();
-                a.push_str(stringify!(_b));
-                HlvmValue::String(a.to_string())
+                a.push_str(&b);
+                Ok(HlvmValue::String(a))
             }
-            _ => panic!("Unable to add values of different types"),
+            (a, b) => Err(unsupported("add", a, &b)),
         }
     }
 
-    fn sub(&self, b: HlvmValue) -> HlvmValue {
+    fn sub(&self, b: HlvmValue) -> Result<HlvmValue, String> {
         match (self, b) {
-            (HlvmValue::Number(a), HlvmValue::Number(b)) => HlvmValue::Number(*a - b),
-            _ => panic!("Unable to subtract values of different types"),
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => Ok(HlvmValue::Number(*a - b)),
+            (a, b) => Err(unsupported("subtract", a, &b)),
         }
     }
 
-    fn mul(&self, b: HlvmValue) -> HlvmValue {
+    fn mul(&self, b: HlvmValue) -> Result<HlvmValue, String> {
         match (self, b) {
-            (HlvmValue::Number(a), HlvmValue::Number(b)) => HlvmValue::Number(*a * b),
-            (HlvmValue::String(a), HlvmValue::Number(b)) => {
-                todo!()
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => Ok(HlvmValue::Number(*a * b)),
+            /* `"ab" * 3` repeats the string */
+            (HlvmValue::String(a), HlvmValue::Number(b)) if b >= 0.0 && b.fract() == 0.0 => {
+                repeat(a, b).map(HlvmValue::String)
             }
-            _ => panic!("Unable to multiply values of different types"),
+            (HlvmValue::String(_), HlvmValue::Number(b)) => {
+                Err(ErrorCode::UnsupportedOperands.message(format!(
+                    "Strings can only be repeated a whole, positive number of times, not {}",
+                    format_number(b)
+                )))
+            }
+            (a, b) => Err(unsupported("multiply", a, &b)),
         }
     }
 
-    fn div(&self, b: HlvmValue) -> HlvmValue {
+    fn div(&self, b: HlvmValue) -> Result<HlvmValue, String> {
         match (self, b) {
-            (HlvmValue::Number(a), HlvmValue::Number(b)) => HlvmValue::Number(*a / b),
-            _ => panic!("Unable to divide values of different types"),
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => Ok(HlvmValue::Number(*a / b)),
+            (a, b) => Err(unsupported("divide", a, &b)),
         }
     }
 
-    fn _eq(&self, b: HlvmValue) -> HlvmValue {
-        HlvmValue::Bool(self == &b)
+    fn rem(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        match (self, b) {
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => Ok(HlvmValue::Number(*a % b)),
+            (a, b) => Err(unsupported("take the remainder of", a, &b)),
+        }
     }
 
-    fn _ne(&self, b: HlvmValue) -> HlvmValue {
-        HlvmValue::Bool(self != &b)
+    fn pow(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        match (self, b) {
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => Ok(HlvmValue::Number(a.powf(b))),
+            (a, b) => Err(unsupported("exponentiate", a, &b)),
+        }
     }
 
-    fn gt(&self, b: HlvmValue) -> HlvmValue {
+    fn shl(&self, b: HlvmValue) -> Result<HlvmValue, String> {
         match (self, b) {
-            (HlvmValue::Number(a), HlvmValue::Number(b)) => HlvmValue::Bool(*a > b),
-            _ => panic!("Unable to compare values of different types"),
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => Ok(HlvmValue::Number(
+                (*a as i64).checked_shl(b as u32).unwrap_or(0) as f64,
+            )),
+            (a, b) => Err(unsupported("shift", a, &b)),
         }
     }
 
-    fn lt(&self, b: HlvmValue) -> HlvmValue {
+    fn shr(&self, b: HlvmValue) -> Result<HlvmValue, String> {
         match (self, b) {
-            (HlvmValue::Number(a), HlvmValue::Number(b)) => HlvmValue::Bool(*a < b),
-            _ => panic!("Unable to compare values of different types"),
+            /* Shifting out every bit leaves only the sign */
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => Ok(HlvmValue::Number(
+                (*a as i64).checked_shr(b as u32).unwrap_or(if *a < 0.0 { -1 } else { 0 }) as f64,
+            )),
+            (a, b) => Err(unsupported("shift", a, &b)),
         }
     }
 
-    fn ge(&self, b: HlvmValue) -> HlvmValue {
-        match (self, b) {
-            (HlvmValue::Number(a), HlvmValue::Number(b)) => HlvmValue::Bool(*a >= b),
-            _ => panic!("Unable to compare values of different types"),
+    fn _eq(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        Ok(HlvmValue::Bool(self == &b))
+    }
+
+    fn _ne(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        Ok(HlvmValue::Bool(self != &b))
+    }
+
+    fn gt(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        match self.partial_cmp(&b) {
+            Some(_) => Ok(HlvmValue::Bool(*self > b)),
+            None => Err(unsupported("compare", self, &b)),
         }
     }
 
-    fn le(&self, b: HlvmValue) -> HlvmValue {
-        match (self, b) {
-            (HlvmValue::Number(a), HlvmValue::Number(b)) => HlvmValue::Bool(*a <= b),
-            _ => panic!("Unable to compare values of different types"),
+    fn lt(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        match self.partial_cmp(&b) {
+            Some(_) => Ok(HlvmValue::Bool(*self < b)),
+            None => Err(unsupported("compare", self, &b)),
         }
     }
 
-    fn and(&self, b: HlvmValue) -> HlvmValue {
-        HlvmValue::Bool(self.is_truthy() && b.is_truthy())
+    fn ge(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        match self.partial_cmp(&b) {
+            Some(_) => Ok(HlvmValue::Bool(*self >= b)),
+            None => Err(unsupported("compare", self, &b)),
+        }
     }
 
-    fn or(&self, b: HlvmValue) -> HlvmValue {
-        HlvmValue::Bool(self.is_truthy() || b.is_truthy())
+    fn le(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        match self.partial_cmp(&b) {
+            Some(_) => Ok(HlvmValue::Bool(*self <= b)),
+            None => Err(unsupported("compare", self, &b)),
+        }
     }
 
-    fn not(&self) -> HlvmValue {
-        HlvmValue::Bool(!self.is_truthy())
+    fn and(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        Ok(HlvmValue::Bool(self.is_truthy() && b.is_truthy()))
+    }
+
+    fn or(&self, b: HlvmValue) -> Result<HlvmValue, String> {
+        Ok(HlvmValue::Bool(self.is_truthy() || b.is_truthy()))
+    }
+
+    fn not(&self) -> Result<HlvmValue, String> {
+        Ok(HlvmValue::Bool(!self.is_truthy()))
+    }
+
+    fn neg(&self) -> Result<HlvmValue, String> {
+        match self {
+            HlvmValue::Number(a) => Ok(HlvmValue::Number(-a)),
+            a => Err(ErrorCode::UnsupportedOperands
+                .message(format!("Unable to negate {}", type_name(a)))),
+        }
+    }
+}
+
+/// Approximately how many bytes `value` takes, including what it owns on the heap.
+/// Arrays shared between copies are only counted the first time they're `seen`.
+pub fn memory_size(value: &HlvmValue, seen: &mut HashSet<*const Vec<HlvmValue>>) -> usize {
+    let owned = match value {
+        HlvmValue::String(string) => string.capacity(),
+        HlvmValue::Array(elements) if seen.insert(Rc::as_ptr(elements)) => {
+            elements.iter().map(|element| memory_size(element, seen)).sum()
+        }
+        HlvmValue::StructInstance(attributes, _) | HlvmValue::StructBlueprint(attributes) => {
+            attributes
+                .iter()
+                .map(|(name, value)| name.capacity() + memory_size(value, seen))
+                .sum()
+        }
+        /* Function bodies are part of the program rather than data it created */
+        _ => 0,
+    };
+
+    std::mem::size_of::<HlvmValue>() + owned
+}
+
+/// Formats a number the same way on every platform.
+///
+/// Numbers are printed with the fewest digits that still read back as the same value,
+/// whole numbers are printed without a fraction, and very large or very small magnitudes
+/// use exponent notation (`1e21`, `1.5e-7`).
+pub fn format_number(number: f64) -> String {
+    if number.is_nan() {
+        "nan".to_string()
+    } else if number.is_infinite() {
+        if number > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else if number == 0.0 {
+        /* Don't print negative zero as -0 */
+        "0".to_string()
+    } else if !(1e-7..1e21).contains(&number.abs()) {
+        format!("{:e}", number)
+    } else {
+        format!("{}", number)
+    }
+}
+
+/// The number of digits passed to `to_fixed!` and `to_precision!`, if it's a whole number
+/// in `range`.
+fn digits(value: Option<&HlvmValue>, range: std::ops::RangeInclusive<f64>) -> Option<usize> {
+    match value {
+        Some(HlvmValue::Number(digits)) if digits.fract() == 0.0 && range.contains(digits) => {
+            Some(*digits as usize)
+        }
+        _ => None,
+    }
+}
+
+/// Drops the sign of numbers that were rounded to zero, which `format_number` never prints.
+fn unsigned_zero(formatted: String) -> String {
+    match formatted.strip_prefix('-') {
+        Some(rest) if rest.chars().all(|c| matches!(c, '0' | '.')) => rest.to_string(),
+        _ => formatted,
+    }
+}
+
+/// `to_fixed!(x, digits)` formats `x` with exactly `digits` digits after the decimal point,
+/// rounding halfway cases to even, so `to_fixed!(2.5, 0)` is `2`.
+/// Returns `none` unless `digits` is a whole number from 0 to 100.
+/* 22 */ pub fn hlvm_to_fixed(var: Vec<HlvmValue>) -> HlvmValue {
+    match (var.first(), digits(var.get(1), 0.0..=100.0)) {
+        (Some(HlvmValue::Number(number)), Some(_)) if !number.is_finite() => {
+            HlvmValue::String(format_number(*number))
+        }
+        (Some(HlvmValue::Number(number)), Some(digits)) => {
+            HlvmValue::String(unsigned_zero(format!("{:.*}", digits, number)))
+        }
+        _ => HlvmValue::None,
+    }
+}
+
+/// `to_precision!(x, digits)` formats `x` rounded to `digits` significant digits, using
+/// exponent notation for the same magnitudes as `format_number`.
+/// Returns `none` unless `digits` is a whole number from 1 to 100.
+/* 23 */ pub fn hlvm_to_precision(var: Vec<HlvmValue>) -> HlvmValue {
+    let (number, digits) = match (var.first(), digits(var.get(1), 1.0..=100.0)) {
+        (Some(HlvmValue::Number(number)), Some(digits)) => (*number, digits),
+        _ => return HlvmValue::None,
+    };
+
+    if !number.is_finite() {
+        return HlvmValue::String(format_number(number));
+    }
+
+    if number == 0.0 {
+        return HlvmValue::String(format!("{:.*}", digits - 1, 0.0));
+    }
+
+    /* Rounding can carry into another digit, so the exponent is read from the rounded number */
+    let scientific = format!("{:.*e}", digits - 1, number);
+    let (_, exponent) = scientific.split_once('e').expect("Exponent notation has an exponent");
+    let exponent: i32 = exponent.parse().expect("Exponents are whole numbers");
+
+    if !(-7..21).contains(&exponent) {
+        return HlvmValue::String(scientific);
+    }
+
+    let rounded: f64 = scientific.parse().expect("Formatted numbers parse back");
+    let decimals = (digits as i32 - 1 - exponent).max(0) as usize;
+    HlvmValue::String(format!("{:.*}", decimals, rounded))
+}
+
+/// Converts `value` to `target`, for `value as type`.
+///
+/// * Strings: values are formatted like `print!` does, except arrays, which are joined
+///   without a separator and may only contain strings. This undoes `chars!`.
+/// * Numbers: strings are parsed like `parse_float!`, bools are `1` or `0` and `none` is `0`.
+/// * Bools: every value converts by whether it's truthy.
+pub fn convert(value: HlvmValue, target: Conversion) -> Result<HlvmValue, String> {
+    let fail = |value: &HlvmValue| {
+        let target = match target {
+            Conversion::Number => "a number",
+            Conversion::String => "a string",
+            Conversion::Bool => "a bool",
+        };
+
+        let message = format!("Cannot convert {} to {}", repr(value), target);
+        Err(ErrorCode::InvalidConversion.message(message))
+    };
+
+    match (target, value) {
+        (Conversion::Bool, value) => Ok(HlvmValue::Bool(value.is_truthy())),
+
+        (Conversion::String, HlvmValue::Array(elements)) => {
+            let mut joined = String::new();
+
+            for element in elements.iter() {
+                match element {
+                    HlvmValue::String(string) => joined.push_str(string),
+                    _ => return fail(&HlvmValue::Array(elements)),
+                }
+            }
+
+            Ok(HlvmValue::String(joined))
+        }
+        (
+            Conversion::String,
+            value @ (HlvmValue::StructInstance(..)
+            | HlvmValue::StructBlueprint(..)
+            | HlvmValue::Function(..)
+            | HlvmValue::BuiltInFunction(..)),
+        ) => fail(&value),
+        (Conversion::String, value) => Ok(HlvmValue::String(value.to_string())),
+
+        (Conversion::Number, number @ HlvmValue::Number(_)) => Ok(number),
+        (Conversion::Number, HlvmValue::Bool(bool)) => Ok(HlvmValue::Number(bool as u8 as f64)),
+        (Conversion::Number, HlvmValue::None) => Ok(HlvmValue::Number(0.0)),
+        (Conversion::Number, string @ HlvmValue::String(_)) => {
+            match hlvm_parse_float(vec![string.clone()]) {
+                HlvmValue::None => fail(&string),
+                number => Ok(number),
+            }
+        }
+        (Conversion::Number, value) => fail(&value),
+    }
+}
+
+/// The position `index` refers to in a string or array of `length` elements. `length` itself
+/// is only a valid position for the end of a slice, so `exclusive` allows it.
+fn position(index: &HlvmValue, length: usize, exclusive: bool) -> Result<usize, String> {
+    let index = match index {
+        HlvmValue::Number(index) if index.fract() == 0.0 => *index,
+        index => {
+            let message = format!("Index {} isn't a whole number", repr(index));
+            return Err(ErrorCode::NotIndexable.message(message));
+        }
+    };
+
+    let limit = if exclusive { length + 1 } else { length };
+
+    if index < 0.0 || index >= limit as f64 {
+        return Err(ErrorCode::IndexOutOfBounds.message(format!(
+            "Index {} is out of bounds for a length of {}",
+            format_number(index),
+            length
+        )));
+    }
+
+    Ok(index as usize)
+}
+
+/// The element of `value` at `index`, for `value[index]`. Strings are indexed by character.
+pub fn index_value(value: HlvmValue, index: HlvmValue) -> Result<HlvmValue, String> {
+    match value {
+        HlvmValue::String(string) => {
+            let index = position(&index, string.chars().count(), false)?;
+            Ok(HlvmValue::String(string.chars().nth(index).unwrap().to_string()))
+        }
+        HlvmValue::Array(elements) => {
+            let index = position(&index, elements.len(), false)?;
+            Ok(elements[index].clone())
+        }
+        value => Err(ErrorCode::NotIndexable
+            .message(format!("Cannot index {}", type_name(&value)))),
+    }
+}
+
+/// The elements of `value` from `start` up to `end`, for `value[start..end]`.
+/// Bounds that are none are the first and last positions.
+pub fn slice_value(
+    value: HlvmValue,
+    start: HlvmValue,
+    end: HlvmValue,
+) -> Result<HlvmValue, String> {
+    let length = match &value {
+        HlvmValue::String(string) => string.chars().count(),
+        HlvmValue::Array(elements) => elements.len(),
+        value => {
+            return Err(ErrorCode::NotIndexable
+                .message(format!("Cannot slice {}", type_name(value))))
+        }
+    };
+
+    let start = match start {
+        HlvmValue::None => 0,
+        start => position(&start, length, true)?,
+    };
+
+    let end = match end {
+        HlvmValue::None => length,
+        end => position(&end, length, true)?,
+    };
+
+    if start > end {
+        return Err(ErrorCode::IndexOutOfBounds
+            .message(format!("Slice starts at {} but ends at {}", start, end)));
+    }
+
+    Ok(match value {
+        HlvmValue::String(string) => {
+            HlvmValue::String(string.chars().skip(start).take(end - start).collect())
+        }
+        HlvmValue::Array(elements) => HlvmValue::array(elements[start..end].to_vec()),
+        _ => unreachable!("only strings and arrays have a length"),
+    })
+}
+
+/// How many differences `assert_eq!` lists before leaving the rest out.
+const MAX_DIFFERENCES: usize = 20;
+
+/// `assert_eq!(actual, expected)` errors if the values aren't equal, listing where they differ.
+/* 25 */ pub fn hlvm_assert_eq(var: Vec<HlvmValue>) -> Result<HlvmValue, String> {
+    let actual = var.first().unwrap_or(&HlvmValue::None);
+    let expected = var.get(1).unwrap_or(&HlvmValue::None);
+
+    if actual == expected {
+        return Ok(HlvmValue::None);
+    }
+
+    let mut differences = vec![];
+    diff_into(&mut differences, String::new(), actual, expected);
+
+    let mut message = String::from("The values differ");
+
+    for difference in differences.iter().take(MAX_DIFFERENCES) {
+        message.push_str("\n  ");
+        message.push_str(difference);
+    }
+
+    if differences.len() > MAX_DIFFERENCES {
+        let more = differences.len() - MAX_DIFFERENCES;
+        message.push_str(&format!("\n  ...and {} more", more));
+    }
+
+    Err(ErrorCode::AssertionFailed.message(message))
+}
+
+/// Lists where `actual` differs from `expected`, descending into arrays and struct instances
+/// so only the elements and attributes that differ are listed. `path` leads to the values,
+/// like `[1].x`.
+fn diff_into(out: &mut Vec<String>, path: String, actual: &HlvmValue, expected: &HlvmValue) {
+    match (actual, expected) {
+        _ if actual == expected => {}
+        (HlvmValue::Array(actual), HlvmValue::Array(expected)) => {
+            for idx in 0..actual.len().max(expected.len()) {
+                let path = format!("{}[{}]", path, idx);
+
+                diff_entry(out, path, actual.get(idx), expected.get(idx));
+            }
+        }
+        (HlvmValue::StructInstance(actual, _), HlvmValue::StructInstance(expected, _)) => {
+            let mut names: Vec<&String> = actual.keys().chain(expected.keys()).collect();
+            names.sort();
+            names.dedup();
+
+            for name in names {
+                let path = format!("{}.{}", path, name);
+
+                diff_entry(out, path, actual.get(name), expected.get(name));
+            }
+        }
+        _ if path.is_empty() => {
+            out.push(format!("expected {}, found {}", repr(expected), repr(actual)))
+        }
+        _ => out.push(format!(
+            "{}: expected {}, found {}",
+            path,
+            repr(expected),
+            repr(actual)
+        )),
+    }
+}
+
+/// Like `diff_into`, for an element or attribute that either value may not have.
+fn diff_entry(
+    out: &mut Vec<String>,
+    path: String,
+    actual: Option<&HlvmValue>,
+    expected: Option<&HlvmValue>,
+) {
+    match (actual, expected) {
+        (Some(actual), Some(expected)) => diff_into(out, path, actual, expected),
+        (Some(actual), None) => out.push(format!("{}: extra {}", path, repr(actual))),
+        (None, Some(expected)) => out.push(format!("{}: missing {}", path, repr(expected))),
+        (None, None) => {}
+    }
+}
+
+/// `freeze!(value)` returns `value` with every struct instance in it frozen, so none of
+/// their attributes can be set anymore. Other values can't be changed in place, so they're
+/// returned as they are, apart from the struct instances they hold.
+/* 26 */ pub fn hlvm_freeze(var: Vec<HlvmValue>) -> HlvmValue {
+    var.into_iter().next().map_or(HlvmValue::None, freeze)
+}
+
+fn freeze(value: HlvmValue) -> HlvmValue {
+    match value {
+        HlvmValue::Array(elements) => HlvmValue::array(
+            Rc::unwrap_or_clone(elements).into_iter().map(freeze).collect(),
+        ),
+        HlvmValue::StructInstance(attributes, _) => HlvmValue::StructInstance(
+            attributes.into_iter().map(|(name, value)| (name, freeze(value))).collect(),
+            Frozen(true),
+        ),
+        value => value,
+    }
+}
+
+/// How deeply `repr` descends into nested arrays and struct instances.
+pub const REPR_MAX_DEPTH: usize = 8;
+
+/// Formats `value` the way it would be written in source code: strings are quoted with
+/// their control characters escaped, and struct instances list their attributes sorted
+/// by name. Values nested deeper than `REPR_MAX_DEPTH` are elided as `[...]` or `{...}`.
+pub fn repr(value: &HlvmValue) -> String {
+    let mut out = String::new();
+    repr_into(&mut out, value, 0);
+    out
+}
+
+fn repr_into(out: &mut String, value: &HlvmValue, depth: usize) {
+    match value {
+        HlvmValue::String(string) => {
+            out.push('"');
+
+            for c in string.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+
+            out.push('"');
+        }
+        HlvmValue::Array(_) if depth >= REPR_MAX_DEPTH => out.push_str("[...]"),
+        HlvmValue::Array(values) => {
+            out.push('[');
+
+            for (i, value) in values.iter().enumerate() {
+                if i != 0 {
+                    out.push_str(", ");
+                }
+
+                repr_into(out, value, depth + 1);
+            }
+
+            out.push(']');
+        }
+        HlvmValue::StructInstance(..) if depth >= REPR_MAX_DEPTH => out.push_str("{...}"),
+        HlvmValue::StructInstance(attributes, _) => {
+            let mut names: Vec<&String> = attributes.keys().collect();
+            names.sort();
+            out.push('{');
+
+            for (i, name) in names.into_iter().enumerate() {
+                if i != 0 {
+                    out.push_str(", ");
+                }
+
+                out.push_str(name);
+                out.push_str(": ");
+                repr_into(out, &attributes[name], depth + 1);
+            }
+
+            out.push('}');
+        }
+        value => out.push_str(&value.to_string()),
+    }
+}
+
+/// Numbers, strings and bools compare by value, arrays compare element by element.
+/// Values of different types, and values like functions, aren't ordered.
+impl PartialOrd for HlvmValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (HlvmValue::Number(a), HlvmValue::Number(b)) => a.partial_cmp(b),
+            (HlvmValue::String(a), HlvmValue::String(b)) => a.partial_cmp(b),
+            (HlvmValue::Bool(a), HlvmValue::Bool(b)) => a.partial_cmp(b),
+            (HlvmValue::Array(a), HlvmValue::Array(b)) => a.partial_cmp(b),
+            (HlvmValue::None, HlvmValue::None) => Some(Ordering::Equal),
+            (a, b) if a == b => Some(Ordering::Equal),
+            _ => None,
+        }
     }
 }
 
@@ -174,12 +1003,214 @@ impl Display for HlvmValue {
         match self {
             HlvmValue::Bool(true) => write!(f, "true"),
             HlvmValue::Bool(false) => write!(f, "false"),
-            HlvmValue::Number(a) => write!(f, "{}", a),
+            HlvmValue::None => write!(f, "none"),
+            HlvmValue::Number(a) => write!(f, "{}", format_number(*a)),
             HlvmValue::String(a) => write!(f, "{}", a),
+            /* Elements are written like literals, so `["a, b"]` can't pass for `["a", "b"]` */
+            HlvmValue::Array(_) => write!(f, "{}", repr(self)),
             HlvmValue::BuiltInFunction(..) => write!(f, "<rust-function>"),
             HlvmValue::Function(..) => write!(f, "<hlvm-function>"),
             HlvmValue::StructBlueprint(..) => write!(f, "<struct-blueprint>"),
             HlvmValue::StructInstance(..) => write!(f, "<struct-instance>"),
+            HlvmValue::Native(native) => write!(f, "{}", native.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_compares_identity() {
+        let array = HlvmValue::array(vec![HlvmValue::Number(1.0)]);
+
+        assert_eq!(hlvm_is(vec![HlvmValue::Number(1.0), HlvmValue::Number(1.0)]), true.into());
+        assert_eq!(hlvm_is(vec![array.clone(), array]), false.into());
+    }
+
+    #[test]
+    fn is_treats_missing_arguments_as_none() {
+        assert_eq!(hlvm_is(vec![HlvmValue::Number(1.0)]), false.into());
+        assert_eq!(hlvm_is(vec![HlvmValue::None]), true.into());
+        assert_eq!(hlvm_is(vec![]), true.into());
+    }
+
+    #[test]
+    fn unsupported_operands_are_errors() {
+        let string = HlvmValue::String("a".to_string());
+        let array = HlvmValue::array(vec![HlvmValue::Number(1.0)]);
+
+        let error = HlvmValue::Number(1.0).add(string).unwrap_err();
+        assert!(error.contains("E0318") && error.contains("add number and string"));
+
+        let error = array.clone().rem(HlvmValue::Number(2.0)).unwrap_err();
+        assert!(error.contains("array and number"));
+
+        assert!(array.clone().pow(HlvmValue::Number(2.0)).is_err());
+        assert!(array.clone().shl(HlvmValue::Number(1.0)).is_err());
+        assert!(HlvmValue::Number(1.0).shr(array.clone()).is_err());
+        assert!(Operation::lt(&array, HlvmValue::None).is_err());
+        assert!(array.neg().is_err());
+        assert_eq!(HlvmValue::Number(2.0).neg(), Ok(HlvmValue::Number(-2.0)));
+    }
+
+    #[test]
+    fn strings_repeat_a_whole_number_of_times() {
+        let string = HlvmValue::String("ab".to_string());
+
+        assert_eq!(string.mul(HlvmValue::Number(3.0)), Ok(HlvmValue::String("ababab".into())));
+        assert_eq!(string.mul(HlvmValue::Number(0.0)), Ok(HlvmValue::String(String::new())));
+        assert!(string.mul(HlvmValue::Number(1.5)).is_err());
+        assert!(string.mul(HlvmValue::Number(-1.0)).is_err());
+    }
+
+    #[test]
+    fn strings_too_long_to_exist_are_errors() {
+        let string = HlvmValue::String("ab".to_string());
+
+        let error = string.mul(HlvmValue::Number(1e300)).unwrap_err();
+        assert!(error.contains("E0318") && error.contains("too long"), "{}", error);
+
+        let empty = HlvmValue::String(String::new());
+        assert_eq!(empty.mul(HlvmValue::Number(1e300)), Ok(HlvmValue::String(String::new())));
+    }
+
+    #[test]
+    fn power_follows_ieee_754() {
+        let pow = |a: f64, b: f64| match HlvmValue::Number(a).pow(HlvmValue::Number(b)) {
+            Ok(HlvmValue::Number(n)) => n,
+            other => panic!("Expected a number, got {:?}", other),
+        };
+
+        assert_eq!(pow(2.0, -1.0), 0.5);
+        assert_eq!(pow(-2.0, -3.0), -0.125);
+        assert_eq!(pow(0.0, -1.0), f64::INFINITY);
+        assert_eq!(pow(-0.0, -1.0), f64::NEG_INFINITY);
+
+        assert_eq!(pow(0.0, 0.0), 1.0);
+        assert_eq!(pow(f64::NAN, 0.0), 1.0);
+        assert_eq!(pow(1.0, f64::NAN), 1.0);
+        assert!(pow(f64::NAN, 1.0).is_nan());
+
+        assert_eq!(pow(2.25, 0.5), 1.5);
+        assert_eq!(pow(1.5, 2.0), 2.25);
+        assert_eq!(pow(4.0, -0.5), 0.5);
+        assert!(pow(-8.0, 1.0 / 3.0).is_nan());
+        assert_eq!(pow(-2.0, 3.0), -8.0);
+    }
+
+    #[test]
+    fn frozen_instances_reject_attributes() {
+        let inner = HlvmValue::instance(HashMap::from([("y".to_string(), 2.0.into())]));
+        let point = HlvmValue::instance(HashMap::from([("inner".to_string(), inner)]));
+        let mut frozen = hlvm_freeze(vec![point.clone()]);
+
+        assert_eq!(frozen, point);
+
+        let error = frozen.set("x".to_string(), 1.0.into()).unwrap_err();
+        assert!(error.contains("E0319"));
+
+        let mut inner = frozen.get("inner".to_string()).unwrap();
+        assert!(inner.set("y".to_string(), 3.0.into()).is_err());
+        assert!(point.clone().set("x".to_string(), 1.0.into()).is_ok());
+    }
+
+    #[test]
+    fn freeze_reaches_into_arrays() {
+        let point = HlvmValue::instance(HashMap::new());
+        let frozen = hlvm_freeze(vec![HlvmValue::array(vec![point, 1.0.into()])]);
+
+        match frozen {
+            HlvmValue::Array(elements) => {
+                assert!(matches!(elements[0], HlvmValue::StructInstance(_, Frozen(true))));
+                assert_eq!(elements[1], HlvmValue::Number(1.0));
+            }
+            value => panic!("expected an array, found {:?}", value),
+        }
+    }
+
+    fn fixed(number: f64, digits: impl Into<HlvmValue>) -> HlvmValue {
+        hlvm_to_fixed(vec![number.into(), digits.into()])
+    }
+
+    fn precision(number: f64, digits: impl Into<HlvmValue>) -> HlvmValue {
+        hlvm_to_precision(vec![number.into(), digits.into()])
+    }
+
+    #[test]
+    fn formats_extreme_magnitudes() {
+        assert_eq!(format_number(1e20), "100000000000000000000");
+        assert_eq!(format_number(1e21), "1e21");
+        assert_eq!(format_number(-1e300), "-1e300");
+        assert_eq!(format_number(f64::MAX), "1.7976931348623157e308");
+        assert_eq!(format_number(1e-7), "0.0000001");
+        assert_eq!(format_number(1.5e-8), "1.5e-8");
+        assert_eq!(format_number(f64::MIN_POSITIVE), "2.2250738585072014e-308");
+        assert_eq!(format_number(5e-324), "5e-324");
+    }
+
+    #[test]
+    fn formats_negative_zero_as_zero() {
+        assert_eq!(format_number(-0.0), "0");
+        assert_eq!(fixed(-0.0, 2), "0.00".into());
+        assert_eq!(fixed(-0.001, 2), "0.00".into());
+        assert_eq!(fixed(-1e-300, 0), "0".into());
+        assert_eq!(precision(-0.0, 3), "0.00".into());
+        assert_eq!(fixed(-0.5, 0), "0".into());
+        assert_eq!(fixed(-1.5, 0), "-2".into());
+    }
+
+    #[test]
+    fn to_fixed_handles_extreme_magnitudes() {
+        assert_eq!(fixed(1e21, 2), "1000000000000000000000.00".into());
+        assert_eq!(fixed(1e-10, 3), "0.000".into());
+        assert_eq!(fixed(1e-10, 12), "0.000000000100".into());
+        assert_eq!(fixed(0.125, 2), "0.12".into());
+        assert_eq!(fixed(2.5, 0), "2".into());
+        assert_eq!(fixed(f64::INFINITY, 2), "inf".into());
+
+        match fixed(f64::MAX, 1) {
+            HlvmValue::String(formatted) => {
+                assert!(formatted.starts_with("17976931348623157") && formatted.ends_with(".0"));
+                assert_eq!(formatted.len(), 311);
+            }
+            value => panic!("expected a string, found {:?}", value),
+        }
+    }
+
+    #[test]
+    fn to_precision_handles_extreme_magnitudes() {
+        assert_eq!(precision(123456.0, 2), "120000".into());
+        assert_eq!(precision(9.99, 2), "10".into());
+        assert_eq!(precision(0.000123456, 3), "0.000123".into());
+        assert_eq!(precision(1e21, 3), "1.00e21".into());
+        assert_eq!(precision(f64::MAX, 3), "1.80e308".into());
+        assert_eq!(precision(1.5e-8, 2), "1.5e-8".into());
+        assert_eq!(precision(-1e-300, 2), "-1.0e-300".into());
+        assert_eq!(precision(5e-324, 1), "5e-324".into());
+    }
+
+    #[test]
+    fn rejects_invalid_digits() {
+        assert_eq!(fixed(1.0, -1.0), HlvmValue::None);
+        assert_eq!(fixed(1.0, 101.0), HlvmValue::None);
+        assert_eq!(fixed(1.0, 1.5), HlvmValue::None);
+        assert_eq!(precision(1.0, 0.0), HlvmValue::None);
+    }
+
+    #[test]
+    fn parses_floats_without_a_locale() {
+        let parse = |text: &str| hlvm_parse_float(vec![text.into()]);
+
+        assert_eq!(parse("1.5e-8"), HlvmValue::Number(1.5e-8));
+        assert_eq!(parse("1e308"), HlvmValue::Number(1e308));
+        assert_eq!(parse("1,5"), HlvmValue::None);
+        assert_eq!(parse("inf"), HlvmValue::None);
+
+        match parse("-0") {
+            HlvmValue::Number(zero) => assert!(zero == 0.0 && zero.is_sign_negative()),
+            value => panic!("expected a number, found {:?}", value),
         }
     }
 }
@@ -0,0 +1,434 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Identifies every kind of error lace can report, from scanning all the way to execution.
+///
+/// * `E00xx` - Scanner
+/// * `E01xx` - Parser
+/// * `E02xx` - Typechecker
+/// * `E03xx` - Virtual machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidCharacter,
+
+    UnexpectedToken,
+    ExpectedToken,
+    UnknownType,
+    TooDeeplyNested,
+    UnknownPrimitive,
+    DanglingDocComment,
+    ReservedKeyword,
+    AssignToConstant,
+    InvalidAssembly,
+    ImportNotFound,
+    CircularImport,
+    InvalidDefer,
+
+    MismatchedTypes,
+    InvalidOperands,
+    UndefinedVariable,
+    UndefinedFunction,
+    WrongArgumentCount,
+    UnknownAttribute,
+
+    NotCallable,
+    UndefinedAttribute,
+    NoAttributes,
+    NotInitializable,
+    UndefinedName,
+    PatternMismatch,
+    InvalidConversion,
+    CallDepthExceeded,
+    InstructionLimitExceeded,
+    ArgumentCountMismatch,
+    StackUnderflow,
+    TimeLimitExceeded,
+    OutputLimitExceeded,
+    IndexOutOfBounds,
+    NotIndexable,
+    MemoryLimitExceeded,
+    AssertionFailed,
+    UnsupportedOperands,
+    FrozenValue,
+    InvalidInstruction,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 39] = [
+        ErrorCode::InvalidCharacter,
+        ErrorCode::UnexpectedToken,
+        ErrorCode::ExpectedToken,
+        ErrorCode::UnknownType,
+        ErrorCode::TooDeeplyNested,
+        ErrorCode::UnknownPrimitive,
+        ErrorCode::DanglingDocComment,
+        ErrorCode::ReservedKeyword,
+        ErrorCode::AssignToConstant,
+        ErrorCode::InvalidAssembly,
+        ErrorCode::ImportNotFound,
+        ErrorCode::CircularImport,
+        ErrorCode::InvalidDefer,
+        ErrorCode::MismatchedTypes,
+        ErrorCode::InvalidOperands,
+        ErrorCode::UndefinedVariable,
+        ErrorCode::UndefinedFunction,
+        ErrorCode::WrongArgumentCount,
+        ErrorCode::UnknownAttribute,
+        ErrorCode::NotCallable,
+        ErrorCode::UndefinedAttribute,
+        ErrorCode::NoAttributes,
+        ErrorCode::NotInitializable,
+        ErrorCode::UndefinedName,
+        ErrorCode::PatternMismatch,
+        ErrorCode::InvalidConversion,
+        ErrorCode::CallDepthExceeded,
+        ErrorCode::InstructionLimitExceeded,
+        ErrorCode::ArgumentCountMismatch,
+        ErrorCode::StackUnderflow,
+        ErrorCode::TimeLimitExceeded,
+        ErrorCode::OutputLimitExceeded,
+        ErrorCode::IndexOutOfBounds,
+        ErrorCode::NotIndexable,
+        ErrorCode::MemoryLimitExceeded,
+        ErrorCode::AssertionFailed,
+        ErrorCode::UnsupportedOperands,
+        ErrorCode::FrozenValue,
+        ErrorCode::InvalidInstruction,
+    ];
+
+    /// The code as written in diagnostics, like `E0101`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidCharacter => "E0001",
+            ErrorCode::UnexpectedToken => "E0101",
+            ErrorCode::ExpectedToken => "E0102",
+            ErrorCode::UnknownType => "E0103",
+            ErrorCode::TooDeeplyNested => "E0104",
+            ErrorCode::UnknownPrimitive => "E0105",
+            ErrorCode::DanglingDocComment => "E0106",
+            ErrorCode::ReservedKeyword => "E0107",
+            ErrorCode::AssignToConstant => "E0108",
+            ErrorCode::InvalidAssembly => "E0109",
+            ErrorCode::ImportNotFound => "E0110",
+            ErrorCode::CircularImport => "E0111",
+            ErrorCode::InvalidDefer => "E0112",
+            ErrorCode::MismatchedTypes => "E0201",
+            ErrorCode::InvalidOperands => "E0202",
+            ErrorCode::UndefinedVariable => "E0203",
+            ErrorCode::UndefinedFunction => "E0204",
+            ErrorCode::WrongArgumentCount => "E0205",
+            ErrorCode::UnknownAttribute => "E0206",
+            ErrorCode::NotCallable => "E0301",
+            ErrorCode::UndefinedAttribute => "E0302",
+            ErrorCode::NoAttributes => "E0303",
+            ErrorCode::NotInitializable => "E0304",
+            ErrorCode::UndefinedName => "E0305",
+            ErrorCode::PatternMismatch => "E0306",
+            ErrorCode::InvalidConversion => "E0307",
+            ErrorCode::CallDepthExceeded => "E0308",
+            ErrorCode::InstructionLimitExceeded => "E0309",
+            ErrorCode::ArgumentCountMismatch => "E0310",
+            ErrorCode::StackUnderflow => "E0311",
+            ErrorCode::TimeLimitExceeded => "E0312",
+            ErrorCode::OutputLimitExceeded => "E0313",
+            ErrorCode::IndexOutOfBounds => "E0314",
+            ErrorCode::NotIndexable => "E0315",
+            ErrorCode::MemoryLimitExceeded => "E0316",
+            ErrorCode::AssertionFailed => "E0317",
+            ErrorCode::UnsupportedOperands => "E0318",
+            ErrorCode::FrozenValue => "E0319",
+            ErrorCode::InvalidInstruction => "E0320",
+        }
+    }
+
+    /// Prefixes `message` with the code, like `[E0301] Cannot call value`.
+    pub fn message<T: Display>(&self, message: T) -> String {
+        format!("[{}] {}", self.code(), message)
+    }
+
+    /// A longer description of the error, followed by an example of how to fix it.
+    pub fn explain(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidCharacter => {
+                "The source contains a character that isn't part of any token.\n\n\
+                 Remove the character, or put it inside a string:\n\n    let s: string = \"#\""
+            }
+            ErrorCode::UnexpectedToken => {
+                "A token appeared where no statement or expression can start.\n\n\
+                 Check for stray operators or keywords at the start of a line:\n\n    \
+                 // error\n    ) print!(1)\n\n    // fixed\n    print!(1)"
+            }
+            ErrorCode::ExpectedToken => {
+                "The parser needed a specific token, like ':' or '{', but found something else.\n\n\
+                 The diagnostic names the missing token. For example, every variable needs a type:\n\n    \
+                 // error\n    let x = 1\n\n    // fixed\n    let x: number = 1"
+            }
+            ErrorCode::UnknownType => {
+                "A type annotation names a type that doesn't exist.\n\n\
+                 The builtin types are `number`, `string` and `bool`:\n\n    \
+                 // error\n    let x: int = 1\n\n    // fixed\n    let x: number = 1"
+            }
+            ErrorCode::TooDeeplyNested => {
+                "An expression or block is nested deeper than the parser allows.\n\n\
+                 Split the expression into several variables:\n\n    \
+                 let a: number = (((1 + 2)))\n    let b: number = ((a * 3))"
+            }
+            ErrorCode::UnknownPrimitive => {
+                "A primitive function (a name ending in '!') was called, but no such primitive exists.\n\n\
+                 Check the spelling of the primitive:\n\n    // error\n    prnt!(1)\n\n    // fixed\n    print!(1)"
+            }
+            ErrorCode::DanglingDocComment => {
+                "A `///` doc comment isn't followed by anything it could document.\n\n\
+                 Turn it into a regular comment, or write the item below it:\n\n    \
+                 // error\n    /// Adds numbers\n\n    // fixed\n    // Adds numbers"
+            }
+            ErrorCode::ReservedKeyword => {
+                "A keyword was used as the name of a variable, function, parameter or type.\n\n\
+                 Keywords like `type`, `async` and `pub` are reserved, so pick another name:\n\n    \
+                 // error\n    let type: string = \"circle\"\n\n    // fixed\n    let kind: string = \"circle\""
+            }
+            ErrorCode::AssignToConstant => {
+                "A builtin constant like `PI`, `VERSION` or `MAX_NUMBER` was declared or assigned to.\n\n\
+                 Constants are read-only and can't be shadowed, so use another name:\n\n    \
+                 // error\n    let PI: number = 3\n\n    // fixed\n    let pi: number = 3"
+            }
+            ErrorCode::InvalidAssembly => {
+                "An `asm` block contains an unknown instruction, a missing operand, or a jump\n\
+                 outside of the block. Jump addresses count from the first instruction of the block:\n\n    \
+                 // error\n    asm { jump 5 }\n\n    // fixed\n    asm { push 1 jump_if 3 push 2 }"
+            }
+            ErrorCode::ImportNotFound => {
+                "A `use` statement names a file that can't be read.\n\n\
+                 Paths are relative to the file containing the `use` statement:\n\n    \
+                 // error, if util.lc is next to this file\n    use \"src/util.lc\" as util\n\n    \
+                 // fixed\n    use \"util.lc\" as util"
+            }
+            ErrorCode::CircularImport => {
+                "A file imports itself, directly or through other files.\n\n\
+                 The diagnostic lists the files that form the cycle. Move what both files need\n\
+                 into a third file that imports neither of them."
+            }
+            ErrorCode::InvalidDefer => {
+                "A `defer` block contains a `return` or another `defer`, or a `defer` is inside\n\
+                 a loop.\n\n\
+                 Deferred code runs while the function is already returning, so it can't return\n\
+                 itself, and it runs once, however often the loop reaches it. Put the code in the\n\
+                 block directly, or defer it before the loop:\n\n    \
+                 // error\n    defer { defer { close(file) } }\n\n    \
+                 // fixed\n    defer { close(file) }"
+            }
+            ErrorCode::MismatchedTypes => {
+                "A value's type doesn't match the type it was annotated with.\n\n\
+                 Change the annotation or the value:\n\n    \
+                 // error\n    let x: number = \"one\"\n\n    // fixed\n    let x: string = \"one\""
+            }
+            ErrorCode::InvalidOperands => {
+                "An operator was used on values it doesn't support.\n\n\
+                 Only use operators on compatible types:\n\n    \
+                 // error\n    let x: number = \"a\" - 1\n\n    // fixed\n    let x: number = 2 - 1"
+            }
+            ErrorCode::UndefinedVariable => {
+                "A variable was used before it was declared.\n\n\
+                 Declare the variable with `let` first:\n\n    \
+                 // error\n    print!(x)\n\n    // fixed\n    let x: number = 1\n    print!(x)"
+            }
+            ErrorCode::UndefinedFunction => {
+                "A function was called, but no function with that name is declared.\n\n\
+                 Declare the function, or fix the spelling of the call:\n\n    \
+                 fn double(x: number): number {\n        return x * 2\n    }\n\n    double(2)"
+            }
+            ErrorCode::WrongArgumentCount => {
+                "A function was called with more or fewer arguments than it has parameters.\n\n\
+                 Pass exactly one argument for every parameter:\n\n    \
+                 fn add(a: number, b: number): number {\n        return a + b\n    }\n\n    \
+                 // error\n    add(1)\n\n    // fixed\n    add(1, 2)"
+            }
+            ErrorCode::UnknownAttribute => {
+                "A struct literal or an attribute names an attribute the struct doesn't declare.\n\n\
+                 Check the attribute's name against the struct's declaration:\n\n    \
+                 struct Point { x: number, y: number }\n\n    \
+                 // error\n    let p: Point = Point { x: 1, z: 2 }\n\n    \
+                 // fixed\n    let p: Point = Point { x: 1, y: 2 }"
+            }
+            ErrorCode::NotCallable => {
+                "A value that isn't a function was called at runtime.\n\n\
+                 Only call functions:\n\n    // error\n    let x: number = 1\n    x()"
+            }
+            ErrorCode::UndefinedAttribute => {
+                "An attribute was read from a struct instance that doesn't have it.\n\n\
+                 Check the attribute's name against the struct's declaration."
+            }
+            ErrorCode::NoAttributes => {
+                "An attribute was read from or written to a value that isn't a struct instance.\n\n\
+                 Only struct instances have attributes."
+            }
+            ErrorCode::NotInitializable => {
+                "`new` was used on a value that isn't a struct blueprint.\n\n\
+                 Only types declared with `type` can be initialized:\n\n    \
+                 type Point {\n        let x: number = 0\n    }\n\n    new Point()"
+            }
+            ErrorCode::UndefinedName => {
+                "A name was looked up at runtime, but neither the local nor the global scope defines it.\n\n\
+                 Declare the variable or function before using it."
+            }
+            ErrorCode::PatternMismatch => {
+                "A destructuring `let` was given a value that doesn't fit its pattern.\n\n\
+                 Array patterns need an array with exactly as many elements as names, or at least\n\
+                 as many if the pattern ends in a rest name:\n\n    \
+                 let [first, rest...] = chars!(\"lace\")"
+            }
+            ErrorCode::InvalidConversion => {
+                "A value was converted to a type it doesn't fit, like `\"x\" as number`, or a program\n\
+                 embedding lace converted a value into a Rust type it doesn't fit, like `1.5` into\n\
+                 an integer.\n\n\
+                 Check the value before converting it, for example with `parse_float!`, which\n\
+                 returns `none` instead of failing."
+            }
+            ErrorCode::CallDepthExceeded => {
+                "Functions called each other more deeply than the VM was configured to allow,\n\
+                 usually because of recursion that never stops.\n\n\
+                 Make sure recursive functions have a case that returns without calling itself,\n\
+                 or raise the limit with `--max-call-depth`."
+            }
+            ErrorCode::InstructionLimitExceeded => {
+                "The program ran more instructions than the VM was configured to allow,\n\
+                 usually because of a loop that never ends.\n\n\
+                 Check the loop's condition, or raise the limit with `--max-instructions`."
+            }
+            ErrorCode::ArgumentCountMismatch => {
+                "A function was called at runtime with more or fewer arguments than it has\n\
+                 parameters, for example by `map!` or from assembly.\n\n\
+                 Functions passed to `map!`, `filter!` and `each!` take one parameter, and\n\
+                 functions passed to `reduce!` take two."
+            }
+            ErrorCode::StackUnderflow => {
+                "An instruction needed more values than the stack held. Programs produced by the\n\
+                 compiler never do this, so either the compiler has a bug, the program file was\n\
+                 corrupted, or an `asm` block doesn't push what it pops.\n\n\
+                 `shoelace build --verify` checks a program for this before it runs."
+            }
+            ErrorCode::TimeLimitExceeded => {
+                "The program ran for longer than the VM was configured to allow,\n\
+                 usually because of a loop that never ends.\n\n\
+                 Check the loop's condition, or raise the limit with `--timeout`."
+            }
+            ErrorCode::OutputLimitExceeded => {
+                "The program printed more than the VM was configured to allow,\n\
+                 usually because it prints inside a loop that never ends.\n\n\
+                 Everything printed before the limit was reached is kept. Check the loop's\n\
+                 condition, or raise the limit with `--max-output`."
+            }
+            ErrorCode::IndexOutOfBounds => {
+                "A string or array was indexed or sliced past its end, or with a negative index.\n\
+                 Indexes start at 0, so the last element of `s` is at `len - 1`, while a slice\n\
+                 may end at the length itself:\n\n    \
+                 let s: string = \"lace\"\n    \
+                 print!(s[3], s[1..4])"
+            }
+            ErrorCode::NotIndexable => {
+                "Something other than a string or array was indexed, or the index wasn't a\n\
+                 whole number.\n\n\
+                 Only strings and arrays can be indexed, and only with whole numbers, like `s[0]`."
+            }
+            ErrorCode::MemoryLimitExceeded => {
+                "The values the program kept alive took more memory than the VM was configured\n\
+                 to allow, usually because an array or string grows in a loop that never ends.\n\n\
+                 The usage is an estimate, `memory_usage!()` returns it. Check what the program\n\
+                 keeps around, or raise the limit with `--max-memory`."
+            }
+            ErrorCode::AssertionFailed => {
+                "`assert_eq!(actual, expected)` was called with values that aren't equal.\n\n\
+                 The error lists where they differ: `[i]` is an element of an array and `.name`\n\
+                 an attribute of a struct instance, so `[1].x` is the attribute `x` of the\n\
+                 second element."
+            }
+            ErrorCode::UnsupportedOperands => {
+                "An operator was used on values it doesn't work on, like adding a number to a\n\
+                 string or an array. The typechecker catches this when it knows both types,\n\
+                 but attributes and array elements are only known when the program runs.\n\n\
+                 Convert one of the values first:\n\n    \
+                 // error\n    print!(point.x + \"!\")\n\n    \
+                 // fixed\n    print!(point.x as string + \"!\")"
+            }
+            ErrorCode::FrozenValue => {
+                "An attribute of a struct instance was set after the instance was frozen with\n\
+                 `freeze!`. Copies of a frozen instance are frozen too, including the instances\n\
+                 stored in its attributes. Build a new instance instead of changing it:\n\n    \
+                 // error\n    let point: Point = freeze!(Point { x: 1 })\n    point.x = 2\n\n    \
+                 // fixed\n    let point: Point = freeze!(Point { x: 2 })"
+            }
+            ErrorCode::InvalidInstruction => {
+                "The program contains an instruction the VM can't run, like a call to a primitive\n\
+                 function that doesn't exist. Programs produced by the compiler never do this, so\n\
+                 either the program file was corrupted or it was built by another version of lace.\n\n\
+                 `shoelace check-bytecode` checks a program for this before it runs."
+            }
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ErrorCode::ALL
+            .iter()
+            .find(|code| code.code().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(format!("Unknown error code '{}'", s))
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + (a != *b) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Finds the candidate most similar to `name`, to suggest when `name` can't be resolved.
+/// Candidates that are too different from `name` to be a typo of it are ignored.
+pub fn suggest<'a, I: IntoIterator<Item = &'a str>>(name: &str, candidates: I) -> Option<&'a str> {
+    let threshold = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Formats the message for a name that couldn't be resolved, suggesting a similar name if there is one.
+pub fn not_found<'a, I: IntoIterator<Item = &'a str>>(
+    kind: &str,
+    name: &str,
+    candidates: I,
+) -> String {
+    match suggest(name, candidates) {
+        Some(suggestion) => format!(
+            "{} '{}' not found; did you mean '{}'?",
+            kind, name, suggestion
+        ),
+        None => format!("{} '{}' not found", kind, name),
+    }
+}
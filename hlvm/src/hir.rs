@@ -1,20 +1,32 @@
-use crate::lir::{Arguments, HlvmInstruction, HlvmValue, LocalPreAlloc};
+use crate::{
+    ir::{BlockId, Ir, IrBuilder, Terminator},
+    lir::{Conversion, HlvmInstruction, HlvmValue},
+};
 use hashbrown::HashMap;
 
 type CodeBlock = Vec<HlvmHirInstruction>;
 type Expression = Vec<HlvmHirInstruction>;
-pub(crate) type Module = HashMap<String, HlvmValue>;
+pub type Module = HashMap<String, HlvmValue>;
 
+#[derive(Debug, Clone)]
 pub enum HlvmHirInstruction {
     Push(HlvmValue),
 
-    /// Pops the value from the stack and calls it.
+    /// Raw instructions, written with `asm { ... }`.
+    /// Their jump addresses count from the first instruction of the block.
+    Asm(Vec<HlvmInstruction>),
+
+    /// Marks the following instructions as generated from `line` of the source.
+    /// Doesn't lower to any instruction, it's only recorded in the source map.
+    Line(usize),
+
+    /// Pops the value from the stack and calls it with the given amount of arguments.
     /// Top of the stack must be a function, otherwise HLVM will panic.
     /// The arguments passed into the function must be in reversed order.
-    Call,
+    Call(usize),
     CallPrimitive(usize, usize),
 
-    /// Returns HlvmValue::Int32(0)
+    /// Returns `HlvmValue::None`.
     Return,
     /// Returns the value at the top of the stack
     ReturnValue,
@@ -40,6 +52,24 @@ pub enum HlvmHirInstruction {
     /// followed by any attributes.
     Instantiate,
 
+    /// Pops `n` values and pushes an array of them, in the order they were pushed.
+    BuildArray(usize),
+
+    /// Pops an index and the string or array before it, and pushes the element at the index.
+    /// Strings are indexed by character. Errors if the index is out of bounds.
+    Index,
+
+    /// Pops an end, a start and the string or array before them, and pushes the elements from
+    /// the start up to, but not including, the end. Bounds that are none are the first and
+    /// last positions. Errors if a bound is out of bounds, or the start is after the end.
+    Slice,
+
+    /// Pops an array and pushes its first `n` elements in reverse order, so that the
+    /// first element ends up on top of the stack.
+    /// If the flag is set, the remaining elements are pushed as an array before the others.
+    /// Errors if the value isn't an array, or if its length doesn't fit the pattern.
+    Unpack(usize, bool),
+
     /// Assigns a `Module` to a variable.
     ///
     /// * `LoadModule` expands to `Push(HlvmValue::StructInstance(module.1)), SetGlobal(module.0), GetGlobal(module.0), GetAttribute(module.0, "<hlvm:main>"), Call`.
@@ -50,6 +80,10 @@ pub enum HlvmHirInstruction {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
+    Power,
+    ShiftLeft,
+    ShiftRight,
     Equal,
     NotEqual,
     GreaterThan,
@@ -63,6 +97,9 @@ pub enum HlvmHirInstruction {
     Not,
     Negate,
     Typeof,
+    Convert(Conversion),
+    /// Discards the value at the top of the stack.
+    Pop,
 
     IfStatement {
         /// The code to execute if the value on top of the stack is truthy
@@ -82,20 +119,53 @@ pub enum HlvmHirInstruction {
     /// * Expression -> The expression to evaluate at on every iteration.
     /// * CodeBlock -> The block of code to execute if the value on top of the stack is truthy
     WhileStatement(Expression, CodeBlock),
+
+    /// Runs `body`, then `exit`. A `Leave` anywhere in `body` skips the rest of it and
+    /// continues with `exit` right away, so every way out of a function can share the code
+    /// that cleans up after it.
+    Guarded { body: CodeBlock, exit: CodeBlock },
+
+    /// Continues with the `exit` of the innermost `Guarded` this is in.
+    Leave,
 }
 
 /// Converts HIR (High \[Level] Intermediate Representation) to LIR (Low \[Level] Intermediate Representation),
 /// which can be understood by the HLVM.
 pub fn from_hir(source: Vec<HlvmHirInstruction>) -> Vec<HlvmInstruction> {
-    let mut instructions = vec![];
+    from_hir_mapped(source).0
+}
 
+/// Lowers `source` like `from_hir`, also returning the `(instruction, line)` pairs
+/// recorded by its `Line` markers.
+pub fn from_hir_mapped(
+    source: Vec<HlvmHirInstruction>,
+) -> (Vec<HlvmInstruction>, Vec<(usize, usize)>) {
+    to_ir(source).emit()
+}
+
+/// Splits `source` into basic blocks, turning its control flow into explicit jumps.
+pub fn to_ir(source: Vec<HlvmHirInstruction>) -> Ir {
+    let mut instructions = IrBuilder::new();
+    lower_into(&mut instructions, source, None);
+    instructions.finish()
+}
+
+/// Appends the lowered form of `source` to the current block of `instructions`.
+/// `exit` is the block a `Leave` continues with, the exit of the innermost `Guarded`.
+fn lower_into(
+    instructions: &mut IrBuilder,
+    source: Vec<HlvmHirInstruction>,
+    exit: Option<BlockId>,
+) {
     for instruction in source {
         match instruction {
+            HlvmHirInstruction::Line(line) => instructions.line(line),
+            HlvmHirInstruction::Asm(block) => instructions.raw(block),
             HlvmHirInstruction::Push(value) => instructions.push(HlvmInstruction::Push(value)),
-            HlvmHirInstruction::Call => instructions.push(HlvmInstruction::Call),
+            HlvmHirInstruction::Call(count) => instructions.push(HlvmInstruction::Call(count)),
             HlvmHirInstruction::CallPrimitive(index, args) => instructions.push(HlvmInstruction::CallPrimitive(index, args)),
-            HlvmHirInstruction::Return => instructions.push(HlvmInstruction::Return),
-            HlvmHirInstruction::ReturnValue => instructions.push(HlvmInstruction::ReturnValue),
+            HlvmHirInstruction::Return => instructions.terminate(Terminator::Return),
+            HlvmHirInstruction::ReturnValue => instructions.terminate(Terminator::ReturnValue),
             HlvmHirInstruction::GetLocal(name) => {
                 instructions.push(HlvmInstruction::GetLocal(name))
             }
@@ -118,10 +188,22 @@ pub fn from_hir(source: Vec<HlvmHirInstruction>) -> Vec<HlvmInstruction> {
                 instructions.push(HlvmInstruction::SetAttribute(name))
             }
             HlvmHirInstruction::Instantiate => instructions.push(HlvmInstruction::Instantiate),
+            HlvmHirInstruction::Index => instructions.push(HlvmInstruction::Index),
+            HlvmHirInstruction::Slice => instructions.push(HlvmInstruction::Slice),
+            HlvmHirInstruction::BuildArray(count) => {
+                instructions.push(HlvmInstruction::BuildArray(count))
+            }
+            HlvmHirInstruction::Unpack(count, rest) => {
+                instructions.push(HlvmInstruction::Unpack(count, rest))
+            }
             HlvmHirInstruction::Add => instructions.push(HlvmInstruction::Add),
             HlvmHirInstruction::Subtract => instructions.push(HlvmInstruction::Subtract),
             HlvmHirInstruction::Multiply => instructions.push(HlvmInstruction::Multiply),
             HlvmHirInstruction::Divide => instructions.push(HlvmInstruction::Divide),
+            HlvmHirInstruction::Modulo => instructions.push(HlvmInstruction::Modulo),
+            HlvmHirInstruction::Power => instructions.push(HlvmInstruction::Power),
+            HlvmHirInstruction::ShiftLeft => instructions.push(HlvmInstruction::ShiftLeft),
+            HlvmHirInstruction::ShiftRight => instructions.push(HlvmInstruction::ShiftRight),
             HlvmHirInstruction::Equal => instructions.push(HlvmInstruction::Equal),
             HlvmHirInstruction::NotEqual => instructions.push(HlvmInstruction::NotEqual),
             HlvmHirInstruction::GreaterThan => instructions.push(HlvmInstruction::GreaterThan),
@@ -139,84 +221,284 @@ pub fn from_hir(source: Vec<HlvmHirInstruction>) -> Vec<HlvmInstruction> {
             HlvmHirInstruction::Not => instructions.push(HlvmInstruction::Not),
             HlvmHirInstruction::Negate => instructions.push(HlvmInstruction::Negate),
             HlvmHirInstruction::Typeof => instructions.push(HlvmInstruction::Typeof),
+            HlvmHirInstruction::Pop => instructions.push(HlvmInstruction::Pop),
+            HlvmHirInstruction::Convert(target) => {
+                instructions.push(HlvmInstruction::Convert(target))
+            }
             HlvmHirInstruction::LoadModule(name, module) => {
-                instructions.push(HlvmInstruction::Push(HlvmValue::StructInstance(module)));
+                instructions.push(HlvmInstruction::Push(HlvmValue::instance(module)));
                 instructions.push(HlvmInstruction::SetGlobal(name.to_string()));
                 instructions.push(HlvmInstruction::GetGlobal(name));
                 instructions.push(HlvmInstruction::GetAttribute("<hlvm:main>".to_string()));
-                instructions.push(HlvmInstruction::Call);
+                instructions.push(HlvmInstruction::Call(0));
             }
             HlvmHirInstruction::IfStatement { ontrue, onelseif, onfalse } => {
-                enum JumpType {
-                    Next, 
-                    End
-                }
-                
-                let mut jump_offsets: Vec<(usize, JumpType)> = vec![];
-                let mut block_offsets: Vec<usize> = vec![];
-
-                instructions.push(HlvmInstruction::Not);
-                instructions.push(HlvmInstruction::JumpIf(0)); // NEXT
-                jump_offsets.push((instructions.len() - 1, JumpType::Next));
-                instructions.append(&mut from_hir(ontrue));
-                instructions.push(HlvmInstruction::Jump(0)); // END
-                jump_offsets.push((instructions.len() - 1, JumpType::End));
-                block_offsets.push(instructions.len());
-
-                if let Some(elseifs) = onelseif {
-                    for (condition, code) in elseifs {
-                        instructions.append(&mut from_hir(condition));
-                        instructions.push(HlvmInstruction::Not);
-                        instructions.push(HlvmInstruction::JumpIf(0)); // NEXT
-                        jump_offsets.push((instructions.len() - 1, JumpType::Next));
-                        instructions.append(&mut from_hir(code));
-                        instructions.push(HlvmInstruction::Jump(0)); // END 
-                        jump_offsets.push((instructions.len() - 1, JumpType::End));
-                        block_offsets.push(instructions.len());
-                    }
+                let end = instructions.block();
+
+                /* The condition of the first branch is already on the stack */
+                let mut branches = vec![(vec![], ontrue)];
+                branches.extend(onelseif.unwrap_or_default());
+                let count = branches.len();
+
+                for (idx, (condition, code)) in branches.into_iter().enumerate() {
+                    /* Without an else, the last condition skips straight to the end */
+                    let then = instructions.block();
+                    let next = if idx + 1 == count && onfalse.is_empty() {
+                        end
+                    } else {
+                        instructions.block()
+                    };
+
+                    lower_into(instructions, condition, exit);
+                    instructions.branch(then, next);
+                    lower_into(instructions, code, exit);
+                    instructions.terminate(Terminator::Jump(end));
+                    instructions.switch_to(next);
                 }
 
-                println!("{}", block_offsets.len());
-
-                instructions.append(&mut from_hir(onfalse));
-
-                let end_offset = instructions.len();
-                let mut index = 0;
-
-                for (offset, jump_type) in jump_offsets {                    
-                    match jump_type {
-                        JumpType::Next => {
-                            instructions[offset] = HlvmInstruction::JumpIf(
-                                if index + 1 == block_offsets.len() {
-                                    end_offset
-                                } else {
-                                    println!("{} {:?}", index, block_offsets);
-                                    index = index + 1;
-                                    block_offsets[index]
-                                }
-                            );
-                        },
-                        JumpType::End => {
-                            instructions[offset] = HlvmInstruction::Jump(end_offset);
-                        }
-                    }
+                if !onfalse.is_empty() {
+                    lower_into(instructions, onfalse, exit);
+                    instructions.continue_in(end);
                 }
             }
             HlvmHirInstruction::WhileStatement(condition, body) => {
-                let start_offset = instructions.len();
-                instructions.append(&mut from_hir(condition));
-                instructions.push(HlvmInstruction::Not);
-                let jmpif_offset = instructions.len();
-                instructions.push(HlvmInstruction::JumpIf(0)); // END
+                let start = instructions.block();
+                let body_block = instructions.block();
+                let end = instructions.block();
+
+                instructions.continue_in(start);
+                lower_into(instructions, condition, exit);
+                instructions.branch(body_block, end);
 
-                instructions.append(&mut from_hir(body));
-                instructions.push(HlvmInstruction::Jump(start_offset)); // START
-                let end_offset = instructions.len();
+                lower_into(instructions, body, exit);
+                instructions.terminate(Terminator::Jump(start));
+                instructions.switch_to(end);
+            }
+            HlvmHirInstruction::Guarded { body, exit: cleanup } => {
+                let block = instructions.block();
 
-                instructions[jmpif_offset] = HlvmInstruction::JumpIf(end_offset);
+                lower_into(instructions, body, Some(block));
+                instructions.continue_in(block);
+                lower_into(instructions, cleanup, exit);
+            }
+            HlvmHirInstruction::Leave => {
+                let exit = exit.expect("'Leave' is only emitted inside 'Guarded'");
+                instructions.terminate(Terminator::Jump(exit));
             }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::HighLevelVirtualMachine;
+
+    use HlvmHirInstruction::*;
+
+    /// Lowers `source` and runs it, returning the value it returns.
+    fn run(source: Vec<HlvmHirInstruction>) -> HlvmValue {
+        HighLevelVirtualMachine::new(Some(1)).execute(&from_hir(source)).unwrap()
+    }
+
+    fn number(n: f64) -> HlvmHirInstruction {
+        Push(HlvmValue::Number(n))
+    }
+
+    fn condition(value: bool) -> CodeBlock {
+        vec![Push(HlvmValue::Bool(value))]
+    }
+
+    /// Runs an if statement that sets `x` to the number of the branch that was taken,
+    /// starting from 1. `x` stays 0 if no branch is taken.
+    fn branch(first: bool, elseifs: &[bool], onfalse: bool) -> HlvmValue {
+        let take = |n: usize| vec![number(n as f64), SetLocal("x".into())];
+
+        let onelseif = elseifs
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (condition(*value), take(i + 2)))
+            .collect::<Vec<_>>();
+
+        let onfalse = if onfalse { take(elseifs.len() + 2) } else { vec![] };
+
+        run(vec![
+            number(0.0),
+            SetLocal("x".into()),
+            Push(HlvmValue::Bool(first)),
+            IfStatement {
+                ontrue: take(1),
+                onelseif: (!onelseif.is_empty()).then_some(onelseif),
+                onfalse,
+            },
+            GetLocal("x".into()),
+            ReturnValue,
+        ])
+    }
 
-    return instructions;
+    #[test]
+    fn if_without_else() {
+        assert_eq!(branch(true, &[], false), HlvmValue::Number(1.0));
+        assert_eq!(branch(false, &[], false), HlvmValue::Number(0.0));
+    }
+
+    #[test]
+    fn if_with_else() {
+        assert_eq!(branch(true, &[], true), HlvmValue::Number(1.0));
+        assert_eq!(branch(false, &[], true), HlvmValue::Number(2.0));
+    }
+
+    #[test]
+    fn else_if_chains_without_else() {
+        assert_eq!(branch(true, &[true, true], false), HlvmValue::Number(1.0));
+        assert_eq!(branch(false, &[true, true], false), HlvmValue::Number(2.0));
+        assert_eq!(branch(false, &[false, true], false), HlvmValue::Number(3.0));
+        assert_eq!(branch(false, &[false, false], false), HlvmValue::Number(0.0));
+    }
+
+    #[test]
+    fn else_if_chains_with_else() {
+        assert_eq!(branch(false, &[true], true), HlvmValue::Number(2.0));
+        assert_eq!(branch(false, &[false], true), HlvmValue::Number(3.0));
+        assert_eq!(branch(false, &[false, false], true), HlvmValue::Number(4.0));
+    }
+
+    #[test]
+    fn empty_blocks() {
+        let source = vec![
+            Push(HlvmValue::Bool(true)),
+            IfStatement { ontrue: vec![], onelseif: None, onfalse: vec![] },
+            WhileStatement(condition(false), vec![]),
+            number(1.0),
+            ReturnValue,
+        ];
+
+        assert_eq!(run(source), HlvmValue::Number(1.0));
+    }
+
+    #[test]
+    fn while_loops() {
+        /* Sums the numbers below 5 */
+        let source = vec![
+            number(0.0),
+            SetLocal("i".into()),
+            number(0.0),
+            SetLocal("sum".into()),
+            WhileStatement(
+                vec![GetLocal("i".into()), number(5.0), LessThan],
+                vec![
+                    GetLocal("sum".into()),
+                    GetLocal("i".into()),
+                    Add,
+                    SetLocal("sum".into()),
+                    GetLocal("i".into()),
+                    number(1.0),
+                    Add,
+                    SetLocal("i".into()),
+                ],
+            ),
+            GetLocal("sum".into()),
+            ReturnValue,
+        ];
+
+        assert_eq!(run(source), HlvmValue::Number(10.0));
+    }
+
+    #[test]
+    fn leaving_continues_with_the_exit() {
+        /* Sets `x` to 1 and leaves if the condition holds, otherwise sets it to 2. Either way
+           the exit adds 10 to it */
+        let guarded = |leave: bool| Guarded {
+            body: vec![
+                Push(HlvmValue::Bool(leave)),
+                IfStatement {
+                    ontrue: vec![number(1.0), SetLocal("x".into()), Leave],
+                    onelseif: None,
+                    onfalse: vec![],
+                },
+                number(2.0),
+                SetLocal("x".into()),
+            ],
+            exit: vec![GetLocal("x".into()), number(10.0), Add, ReturnValue],
+        };
+
+        assert_eq!(run(vec![guarded(true)]), HlvmValue::Number(11.0));
+        assert_eq!(run(vec![guarded(false)]), HlvmValue::Number(12.0));
+        assert_eq!(crate::cfg::verify(&to_ir(vec![guarded(true)])), Ok(()));
+    }
+
+    #[test]
+    fn asm_jumps_are_relative_to_the_block() {
+        /* The jump skips the second push, wherever the block ends up */
+        let block = vec![
+            HlvmInstruction::Push(HlvmValue::Number(5.0)),
+            HlvmInstruction::Jump(3),
+            HlvmInstruction::Push(HlvmValue::Number(7.0)),
+        ];
+
+        let source = vec![
+            number(1.0),
+            SetLocal("x".into()),
+            Push(HlvmValue::Bool(true)),
+            IfStatement { ontrue: vec![Asm(block)], onelseif: None, onfalse: vec![number(0.0)] },
+            ReturnValue,
+        ];
+
+        assert_eq!(run(source), HlvmValue::Number(5.0));
+    }
+
+    #[test]
+    fn asm_reads_of_undefined_names_are_errors() {
+        let reads = [
+            HlvmInstruction::GetLocal("nope".into()),
+            HlvmInstruction::GetGlobal("nope".into()),
+        ];
+
+        for read in reads {
+            let source = vec![number(1.0), SetLocal("nop".into()), Asm(vec![read]), ReturnValue];
+            let error = HighLevelVirtualMachine::new(Some(1))
+                .execute(&from_hir(source))
+                .unwrap_err();
+
+            assert!(error.contains("E0305") && error.contains("did you mean 'nop'?"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn instructions_the_vm_cant_run_are_errors() {
+        let invalid = [
+            HlvmInstruction::CallPrimitive(crate::vm::PRIMITIVES.len(), 0),
+            HlvmInstruction::BinaryAnd,
+        ];
+
+        for instruction in invalid {
+            let source = vec![number(1.0), number(2.0), Asm(vec![instruction]), ReturnValue];
+            let error = HighLevelVirtualMachine::new(Some(1))
+                .execute(&from_hir(source))
+                .unwrap_err();
+
+            assert!(error.contains("E0320"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn repeating_strings_past_the_memory_limit_is_an_error() {
+        let options = crate::vm::VmOptions::default().max_memory(1024 * 1024);
+
+        /* A string too long to ever exist isn't a matter of the limit */
+        for (count, code) in [(1e7, "E0316"), (1e300, "E0318")] {
+            let source = vec![
+                Push(HlvmValue::String("ab".into())),
+                number(count),
+                Multiply,
+                ReturnValue,
+            ];
+
+            let error = HighLevelVirtualMachine::with_options(Some(1), options)
+                .execute(&from_hir(source))
+                .unwrap_err();
+
+            assert!(error.contains(code), "{}", error);
+        }
+    }
 }
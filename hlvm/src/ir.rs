@@ -0,0 +1,230 @@
+/* --------------------------------------------------------------
+IR: the instructions of a program split into basic blocks with explicit control flow.
+HIR is lowered into it before instructions are emitted, so that passes can work on
+whole blocks instead of patching jump addresses.
+-------------------------------------------------------------- */
+
+use crate::{
+    builder::{InstructionBuilder, Label},
+    lir::HlvmInstruction,
+};
+
+pub type BlockId = usize;
+
+/// How control leaves a basic block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    /// Continues with the given block.
+    Jump(BlockId),
+
+    /// Pops a value, continuing with `then` if it's truthy and with `otherwise` if it isn't.
+    Branch { then: BlockId, otherwise: BlockId },
+
+    Return,
+    ReturnValue,
+
+    /// Falls off the end of the program.
+    Exit,
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    /// Never contains jumps or returns, those are only expressed through the terminator.
+    pub instructions: Vec<HlvmInstruction>,
+
+    /// `(instruction, line)` pairs, with instructions counted from the start of the block.
+    pub lines: Vec<(usize, usize)>,
+
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone)]
+pub struct Ir {
+    pub blocks: Vec<BasicBlock>,
+
+    /// The order blocks are emitted in. The first block is the entry.
+    pub layout: Vec<BlockId>,
+}
+
+/// Builds an `Ir`, appending instructions to the current block.
+pub struct IrBuilder {
+    blocks: Vec<BasicBlock>,
+    layout: Vec<BlockId>,
+    current: BlockId,
+
+    /// Whether the current block was terminated and nothing can be appended to it.
+    terminated: bool,
+}
+
+impl Default for IrBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IrBuilder {
+    pub fn new() -> IrBuilder {
+        let mut builder = IrBuilder {
+            blocks: vec![],
+            layout: vec![],
+            current: 0,
+            terminated: false,
+        };
+
+        let entry = builder.block();
+        builder.switch_to(entry);
+        builder
+    }
+
+    /// Creates an empty block. It's placed in the layout once it's switched to.
+    pub fn block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            instructions: vec![],
+            lines: vec![],
+            terminator: Terminator::Exit,
+        });
+
+        self.blocks.len() - 1
+    }
+
+    /// Continues appending instructions to `block`.
+    pub fn switch_to(&mut self, block: BlockId) {
+        self.current = block;
+        self.layout.push(block);
+        self.terminated = false;
+    }
+
+    /// The block instructions are appended to. If the current block was terminated,
+    /// code after it can't be reached and goes into a new block.
+    fn current(&mut self) -> &mut BasicBlock {
+        if self.terminated {
+            let unreachable = self.block();
+            self.switch_to(unreachable);
+        }
+
+        &mut self.blocks[self.current]
+    }
+
+    pub fn push(&mut self, instruction: HlvmInstruction) {
+        self.current().instructions.push(instruction);
+    }
+
+    /// Marks the next instruction as generated from `line` of the source.
+    pub fn line(&mut self, line: usize) {
+        let block = self.current();
+        block.lines.push((block.instructions.len(), line));
+    }
+
+    /// Ends the current block with `terminator`.
+    /// Does nothing if it already ended, since control can't reach this point.
+    pub fn terminate(&mut self, terminator: Terminator) {
+        if !self.terminated {
+            self.blocks[self.current].terminator = terminator;
+            self.terminated = true;
+        }
+    }
+
+    /// Ends the current block by jumping to `block`, then continues in it.
+    pub fn continue_in(&mut self, block: BlockId) {
+        self.terminate(Terminator::Jump(block));
+        self.switch_to(block);
+    }
+
+    /// Ends the current block with a branch, then continues in `then`.
+    pub fn branch(&mut self, then: BlockId, otherwise: BlockId) {
+        self.terminate(Terminator::Branch { then, otherwise });
+        self.switch_to(then);
+    }
+
+    /// Lowers raw instructions whose jump addresses count from the first of them.
+    pub fn raw(&mut self, instructions: Vec<HlvmInstruction>) {
+        let len = instructions.len();
+        let mut targets: Vec<Option<BlockId>> = vec![None; len + 1];
+
+        for instruction in &instructions {
+            if let HlvmInstruction::Jump(addr) | HlvmInstruction::JumpIf(addr) = instruction {
+                if targets[*addr].is_none() {
+                    targets[*addr] = Some(self.block());
+                }
+            }
+        }
+
+        for (idx, instruction) in instructions.into_iter().enumerate() {
+            if let Some(block) = targets[idx] {
+                self.continue_in(block);
+            }
+
+            match instruction {
+                HlvmInstruction::Jump(addr) => {
+                    self.terminate(Terminator::Jump(targets[addr].unwrap()))
+                }
+                HlvmInstruction::JumpIf(addr) => {
+                    let otherwise = self.block();
+                    self.terminate(Terminator::Branch {
+                        then: targets[addr].unwrap(),
+                        otherwise,
+                    });
+                    self.switch_to(otherwise);
+                }
+                HlvmInstruction::Return => self.terminate(Terminator::Return),
+                HlvmInstruction::ReturnValue => self.terminate(Terminator::ReturnValue),
+                instruction => self.push(instruction),
+            }
+        }
+
+        if let Some(block) = targets[len] {
+            self.continue_in(block);
+        }
+    }
+
+    pub fn finish(self) -> Ir {
+        Ir {
+            blocks: self.blocks,
+            layout: self.layout,
+        }
+    }
+}
+
+impl Ir {
+    /// Emits the blocks in layout order, leaving out jumps to the block that follows.
+    /// Returns the instructions along with the `(instruction, line)` pairs of every block.
+    pub fn emit(self) -> (Vec<HlvmInstruction>, Vec<(usize, usize)>) {
+        let mut instructions = InstructionBuilder::new();
+        let mut lines = vec![];
+
+        let labels: Vec<Label> = self.blocks.iter().map(|_| instructions.label()).collect();
+        let end = instructions.label();
+
+        for (position, &id) in self.layout.iter().enumerate() {
+            let block = &self.blocks[id];
+            let next = self.layout.get(position + 1).copied();
+
+            instructions.bind(labels[id]);
+            lines.extend(block.lines.iter().map(|(idx, line)| (instructions.len() + idx, *line)));
+            instructions.extend(block.instructions.iter().cloned());
+
+            match block.terminator {
+                Terminator::Jump(target) if Some(target) == next => {}
+                Terminator::Jump(target) => instructions.jump(labels[target]),
+                Terminator::Branch { then, otherwise } if Some(then) == next => {
+                    instructions.push(HlvmInstruction::Not);
+                    instructions.jump_if(labels[otherwise]);
+                }
+                Terminator::Branch { then, otherwise } => {
+                    instructions.jump_if(labels[then]);
+
+                    if Some(otherwise) != next {
+                        instructions.jump(labels[otherwise]);
+                    }
+                }
+                Terminator::Return => instructions.push(HlvmInstruction::Return),
+                Terminator::ReturnValue => instructions.push(HlvmInstruction::ReturnValue),
+                Terminator::Exit if next.is_none() => {}
+                Terminator::Exit => instructions.jump(end),
+            }
+        }
+
+        instructions.bind(end);
+        (instructions.finish(), lines)
+    }
+}
@@ -1,6 +1,14 @@
+pub mod builder;
+pub mod cfg;
+pub mod convert;
 pub mod dev;
+pub mod error;
 pub mod hir;
+pub mod ir;
 pub mod lir;
 pub mod module;
+pub mod object;
+pub mod sourcemap;
+pub mod stream;
 pub mod traits;
 pub mod vm;
@@ -1,56 +1,174 @@
+use crate::{traits::NativeObject, vm::HighLevelVirtualMachine};
 use hashbrown::HashMap;
 use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{Debug, Formatter},
+    rc::Rc,
+};
 
 type Address = usize;
 
+/// The largest operand an instruction may have, like a jump target or an argument count.
+/// Operands are `usize` in memory, but programs are limited to 32-bit operands so that they
+/// run the same on 32-bit targets. See `cfg::check_limits`.
+pub const MAX_OPERAND: usize = u32::MAX as usize;
+
 /// The amount of arguments a function takes, along with their names.
 pub(crate) type Arguments = Vec<String>;
 
 /// The amount of space that needs to be allocated for a function's locals.
 pub(crate) type LocalPreAlloc = Option<usize>;
 
+/// The maximum depth the operand stack reaches while a function runs.
+pub(crate) type StackPreAlloc = Option<usize>;
+
+/// Whether a struct instance was frozen with `freeze!`, which stops its attributes from being
+/// changed. Freezing doesn't change what an instance is equal to, and since it only happens
+/// while a program runs it isn't part of compiled programs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Frozen(pub bool);
+
+impl PartialEq for Frozen {
+    fn eq(&self, _other: &Frozen) -> bool {
+        true
+    }
+}
+
+/// Identifies the declaration a function was created from.
+/// Functions are equal only if they have the same id, however alike their bodies are.
+pub type FunctionId = usize;
+
 /// Values supported by the high level virtual machine.
 /// * Number - 64 bit float
 /// * String - String
-/// * Function - Functions are values that can be called.
+/// * Function - Functions are values that can be called. Copies of a function are equal to
+///   each other, functions from different declarations never are.
+/// * None - The absence of a value.
+/// * Array - An ordered list of values. Copies share their elements until one of them needs
+///   its own, so copying an array is cheap however long it is.
+///
+/// * Native - A Rust object the host exposes to programs, see `NativeObject`.
+///
+/// There is no separate character type; characters are strings containing a single character.
+///
+/// Values behave as copies: assigning an array or struct instance to a variable, passing it
+/// to a function or storing it in another value copies it, so changes made through one name
+/// are never visible through another. Arrays are copied on write to keep this cheap, so their
+/// copies do share elements in an `Rc` until one of them changes: code that changes one goes
+/// through `Rc::make_mut` or `Rc::unwrap_or_clone`, which only copy the elements if another
+/// copy still shares them. Native objects are the exception: they can't be copied, so every
+/// copy refers to the same object.
+///
+/// Copies of a frozen struct instance are frozen as well, so `freeze!` makes a value safe to
+/// hand out without any copy of it being changed.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum HlvmValue {
+    None,
     Number(f64),
     Bool(bool),
     String(String),
+    Array(Rc<Vec<HlvmValue>>),
 
-    StructInstance(HashMap<String, HlvmValue>),
+    StructInstance(HashMap<String, HlvmValue>, #[serde(skip)] Frozen),
     StructBlueprint(HashMap<String, HlvmValue>),
 
-    Function(Vec<HlvmInstruction>, Arguments, LocalPreAlloc),
+    Function(Vec<HlvmInstruction>, Arguments, LocalPreAlloc, StackPreAlloc, FunctionId),
     BuiltInFunction(usize, usize),
+
+    /* Native objects only exist while a program runs, they're never part of a compiled one */
+    #[serde(skip)]
+    Native(Native),
 }
 
 impl HlvmValue {
+    pub fn array(elements: Vec<HlvmValue>) -> HlvmValue {
+        HlvmValue::Array(Rc::new(elements))
+    }
+
+    pub fn instance(attributes: HashMap<String, HlvmValue>) -> HlvmValue {
+        HlvmValue::StructInstance(attributes, Frozen::default())
+    }
+
+    /// Wraps `object` so it can be passed to a program, e.g. with `HighLevelVirtualMachine::call`.
+    pub fn native(object: impl NativeObject + 'static) -> HlvmValue {
+        HlvmValue::Native(Native(Rc::new(object)))
+    }
+
     pub fn is_truthy(&self) -> bool {
         match self {
             HlvmValue::Number(val) => *val != 0.0,
             HlvmValue::String(val) => !val.is_empty(),
+            HlvmValue::Array(val) => !val.is_empty(),
             HlvmValue::Bool(val) => *val,
+            HlvmValue::None => false,
             HlvmValue::Function(..)
             | HlvmValue::StructInstance(..)
             | HlvmValue::StructBlueprint(..)
-            | HlvmValue::BuiltInFunction(..) => true,
+            | HlvmValue::BuiltInFunction(..)
+            | HlvmValue::Native(..) => true,
         }
     }
 }
 
+/// A handle to a native object. Handles are equal when they refer to the same object.
+/// Nothing is borrowed while the object runs, so a call can re-enter the VM and use the
+/// object again.
+#[derive(Clone)]
+pub struct Native(pub Rc<dyn NativeObject>);
+
+impl Native {
+    pub fn get(&self, name: &str) -> Result<HlvmValue, String> {
+        self.0.get(name)
+    }
+
+    pub fn set(&self, name: &str, value: HlvmValue) -> Result<(), String> {
+        self.0.set(name, value)
+    }
+
+    pub fn call(
+        &self,
+        arguments: Vec<HlvmValue>,
+        vm: &mut HighLevelVirtualMachine,
+    ) -> Result<HlvmValue, String> {
+        self.0.call(arguments, vm)
+    }
+
+    pub fn display(&self) -> String {
+        self.0.display()
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for Native {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Native({})", self.display())
+    }
+}
+
+/// The types a value can be converted to with `Convert`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Conversion {
+    Number,
+    String,
+    Bool,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum HlvmInstruction {
     Push(HlvmValue),
 
-    /// Pops the value from the stack and calls it.
+    /// Pops the value from the stack and calls it with the given amount of arguments.
     /// Top of the stack must be a function, otherwise HLVM will panic.
     /// The arguments passed into the function must be in reversed order.
-    Call,
+    Call(usize),
     CallPrimitive(usize, usize),
 
-    /// Returns HlvmValue::Int32(0)
+    /// Returns `HlvmValue::None`, which is also returned when a function runs out of instructions.
     Return,
     /// Returns the value at the top of the stack
     ReturnValue,
@@ -76,10 +194,38 @@ pub enum HlvmInstruction {
     /// followed by any attributes.
     Instantiate,
 
+    /// Pops `n` values and pushes an array of them, in the order they were pushed.
+    BuildArray(usize),
+
+    /// Pops an index and the string or array before it, and pushes the element at the index.
+    /// Strings are indexed by character. Errors if the index is out of bounds.
+    Index,
+
+    /// Pops an end, a start and the string or array before them, and pushes the elements from
+    /// the start up to, but not including, the end. Bounds that are none are the first and
+    /// last positions. Errors if a bound is out of bounds, or the start is after the end.
+    Slice,
+
+    /// Pops an array and pushes its first `n` elements in reverse order, so that the
+    /// first element ends up on top of the stack.
+    /// If the flag is set, the remaining elements are pushed as an array before the others.
+    /// Errors if the value isn't an array, or if its length doesn't fit the pattern.
+    Unpack(usize, bool),
+
     Add,
     Subtract,
     Multiply,
     Divide,
+    /// The remainder of dividing the numbers, which has the sign of the dividend.
+    Modulo,
+    /// Raises the first number to the second, following IEEE 754 `pow`:
+    /// * Negative exponents divide, so `2 ** -1` is `0.5`, and `0 ** -1` is `inf`.
+    /// * Anything raised to `0` is `1`, including `0 ** 0` and `nan ** 0`.
+    /// * Fractional exponents take roots, so a negative base with one is `nan`.
+    Power,
+    /// Shifts the bits of the first number, truncated to an integer, by the second.
+    ShiftLeft,
+    ShiftRight,
     Equal,
     NotEqual,
     GreaterThan,
@@ -94,6 +240,13 @@ pub enum HlvmInstruction {
     Negate,
     Typeof,
 
+    /// Converts the value at the top of the stack, see `dev::convert` for which conversions
+    /// are possible. Errors if the value can't be converted.
+    Convert(Conversion),
+
+    /// Discards the value at the top of the stack, like the result of a call made as a statement.
+    Pop,
+
     Jump(Address),
     JumpIf(Address),
 }
@@ -1,16 +1,14 @@
 use crate::lir::HlvmValue;
 use hashbrown::HashMap;
 
-/// Get the default System module
-pub fn system() -> HlvmValue {
-    HlvmValue::StructInstance(HashMap::from([
+/// Get the builtin constants, which are read-only and looked up before any other name.
+pub fn constants() -> HashMap<String, HlvmValue> {
+    HashMap::from([
+        (String::from("PI"), HlvmValue::Number(std::f64::consts::PI)),
         (
-            String::from("print"),
-            HlvmValue::BuiltInFunction(0, 1),
+            String::from("VERSION"),
+            HlvmValue::String(String::from(env!("CARGO_PKG_VERSION"))),
         ),
-        (
-            String::from("exit"),
-            HlvmValue::BuiltInFunction(1, 0),
-        ),
-    ]))
+        (String::from("MAX_NUMBER"), HlvmValue::Number(f64::MAX)),
+    ])
 }
@@ -0,0 +1,49 @@
+/* --------------------------------------------------------------
+The format of compiled programs: a header that identifies the file and the version of the
+format, followed by the instructions encoded with bincode.
+-------------------------------------------------------------- */
+
+use crate::lir::HlvmInstruction;
+
+/// Starts every compiled program.
+pub const MAGIC: &[u8; 4] = b"LACE";
+
+/// The version of the format, which follows the magic as a little-endian `u16`.
+/// Increase it whenever `HlvmInstruction` or `HlvmValue` change, since bincode can't tell
+/// programs encoded with a different layout apart from corrupted ones.
+pub const VERSION: u16 = 2;
+
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+/// Encodes `instructions` as a compiled program, header included.
+pub fn encode(instructions: &[HlvmInstruction]) -> Vec<u8> {
+    let mut program = Vec::with_capacity(HEADER_LEN);
+    program.extend_from_slice(MAGIC);
+    program.extend_from_slice(&VERSION.to_le_bytes());
+
+    bincode::serialize_into(&mut program, instructions).expect("Instructions are always encodable");
+    program
+}
+
+/// Decodes a compiled program, explaining what went wrong if it wasn't built by this version
+/// of lace.
+pub fn decode(program: &[u8]) -> Result<Vec<HlvmInstruction>, String> {
+    if program.len() < HEADER_LEN || &program[..MAGIC.len()] != MAGIC {
+        return Err("This isn't a compiled lace program, or it was built by a version of lace \
+                    from before programs had a header. Rebuild it with 'shoelace build'."
+            .to_string());
+    }
+
+    let version = u16::from_le_bytes([program[MAGIC.len()], program[MAGIC.len() + 1]]);
+
+    if version != VERSION {
+        return Err(format!(
+            "This program was built for version {} of the program format, but this version of \
+             lace runs version {}. Rebuild it with 'shoelace build'.",
+            version, VERSION
+        ));
+    }
+
+    bincode::deserialize(&program[HEADER_LEN..])
+        .map_err(|err| format!("The program is corrupted: {}", err))
+}
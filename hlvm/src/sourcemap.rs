@@ -0,0 +1,87 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Maps instruction indices of a program back to lines of the file it was compiled from.
+///
+/// It's stored next to the program as plain text, a `file` header followed by one
+/// `<instruction> <line>` pair per line:
+///
+/// ```text
+/// file main.lc
+/// 0 1
+/// 4 3
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    pub file: String,
+
+    /// Sorted by instruction. Each line covers every instruction up to the next entry.
+    pub entries: Vec<(usize, usize)>,
+}
+
+impl SourceMap {
+    pub fn new(file: String, entries: Vec<(usize, usize)>) -> SourceMap {
+        SourceMap { file, entries }
+    }
+
+    /// The source line `instruction` was generated from, if it's covered by the map.
+    pub fn line_of(&self, instruction: usize) -> Option<usize> {
+        match self
+            .entries
+            .binary_search_by_key(&instruction, |(start, _)| *start)
+        {
+            Ok(idx) => Some(self.entries[idx].1),
+            Err(0) => None,
+            Err(idx) => Some(self.entries[idx - 1].1),
+        }
+    }
+
+    /// Whether `instruction` is the first one generated from its line.
+    pub fn starts_line(&self, instruction: usize) -> Option<usize> {
+        self.entries
+            .binary_search_by_key(&instruction, |(start, _)| *start)
+            .ok()
+            .map(|idx| self.entries[idx].1)
+    }
+}
+
+impl Display for SourceMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "file {}", self.file)?;
+
+        for (instruction, line) in &self.entries {
+            writeln!(f, "{} {}", instruction, line)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for SourceMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+
+        let file = match lines.next().and_then(|header| header.strip_prefix("file ")) {
+            Some(file) => file.to_string(),
+            None => return Err("Source map is missing its 'file' header.".to_string()),
+        };
+
+        let mut entries = vec![];
+
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let entry = line.split_once(' ').and_then(|(instruction, line)| {
+                Some((instruction.parse().ok()?, line.parse().ok()?))
+            });
+
+            match entry {
+                Some(entry) => entries.push(entry),
+                None => return Err(format!("Invalid source map entry '{}'.", line)),
+            }
+        }
+
+        entries.sort_unstable();
+        Ok(SourceMap { file, entries })
+    }
+}
@@ -0,0 +1,135 @@
+/* --------------------------------------------------------------
+Walks the instructions of a program along with those of every function it pushes, so tools
+like the disassembler and the limit checks don't each have to recurse into function bodies
+and pick operands out of instructions themselves.
+-------------------------------------------------------------- */
+
+use crate::{
+    lir::{HlvmInstruction, HlvmValue},
+    sourcemap::SourceMap,
+};
+
+/// An instruction, with what tools usually want to know about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedInstruction<'a> {
+    /// The index of the instruction in its function, which is what jumps refer to.
+    pub address: usize,
+
+    /// The addresses of the `Push` instructions of the functions the instruction is in,
+    /// outermost first. Empty for the instructions of the program itself.
+    pub path: Vec<usize>,
+
+    pub instruction: &'a HlvmInstruction,
+
+    /// The numeric operands, like jump targets and argument counts.
+    pub operands: Vec<usize>,
+
+    /// The value pushed by a `Push`, unless it's a function: those are walked instead.
+    pub constant: Option<&'a HlvmValue>,
+
+    /// The source line the instruction was generated from. Source maps only cover the
+    /// program itself, so this is always `None` inside functions.
+    pub line: Option<usize>,
+}
+
+impl DecodedInstruction<'_> {
+    /// How many functions deep the instruction is, 0 for the program itself.
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+
+    /// Where the instruction is, like `instruction 4 of the function pushed by instruction 2`.
+    pub fn location(&self) -> String {
+        let mut location = format!("instruction {}", self.address);
+
+        for push in self.path.iter().rev() {
+            location.push_str(&format!(" of the function pushed by instruction {}", push));
+        }
+
+        location
+    }
+}
+
+/// The numeric operands of `instruction`, in the order they're written.
+pub fn operands(instruction: &HlvmInstruction) -> Vec<usize> {
+    match instruction {
+        HlvmInstruction::Jump(operand)
+        | HlvmInstruction::JumpIf(operand)
+        | HlvmInstruction::Call(operand)
+        | HlvmInstruction::BuildArray(operand)
+        | HlvmInstruction::Unpack(operand, _) => vec![*operand],
+        HlvmInstruction::CallPrimitive(index, count) => vec![*index, *count],
+        _ => vec![],
+    }
+}
+
+/// Walks the instructions of a program in order. The body of a function follows the
+/// instruction that pushes it.
+pub struct Instructions<'a> {
+    /// The instructions being walked and the address of the next one, outermost first.
+    frames: Vec<(&'a [HlvmInstruction], usize)>,
+
+    map: Option<&'a SourceMap>,
+}
+
+/// Walks `program` and the functions it pushes.
+pub fn instructions(program: &[HlvmInstruction]) -> Instructions<'_> {
+    Instructions {
+        frames: vec![(program, 0)],
+        map: None,
+    }
+}
+
+impl<'a> Instructions<'a> {
+    /// Fills in the lines of the program's instructions from `map`.
+    pub fn with_map(mut self, map: &'a SourceMap) -> Self {
+        self.map = Some(map);
+        self
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = DecodedInstruction<'a>;
+
+    fn next(&mut self) -> Option<DecodedInstruction<'a>> {
+        loop {
+            let (instructions, address) = *self.frames.last()?;
+
+            let instruction = match instructions.get(address) {
+                Some(instruction) => instruction,
+                None => {
+                    self.frames.pop();
+                    continue;
+                }
+            };
+
+            /* Every frame but the last is positioned right after the push of the next one */
+            let last = self.frames.len() - 1;
+            let path: Vec<usize> = self.frames[..last].iter().map(|(_, next)| next - 1).collect();
+            self.frames[last].1 += 1;
+
+            let line = match self.map {
+                Some(map) if path.is_empty() => map.line_of(address),
+                _ => None,
+            };
+
+            let constant = match instruction {
+                HlvmInstruction::Push(HlvmValue::Function(body, ..)) => {
+                    self.frames.push((body, 0));
+                    None
+                }
+                HlvmInstruction::Push(value) => Some(value),
+                _ => None,
+            };
+
+            return Some(DecodedInstruction {
+                address,
+                path,
+                instruction,
+                operands: operands(instruction),
+                constant,
+                line,
+            });
+        }
+    }
+}
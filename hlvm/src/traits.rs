@@ -1,29 +1,40 @@
-use crate::{lir::HlvmValue, vm::HighLevelVirtualMachine};
+use crate::{error::ErrorCode, lir::HlvmValue, vm::HighLevelVirtualMachine};
 
 /// Used to define how operations behave in the VM.
+/// Every operation errors if it can't be applied to the types of its operands.
 pub trait Operation<T> {
-    fn add(&self, b: T) -> T;
-    fn sub(&self, b: T) -> T;
-    fn mul(&self, b: T) -> T;
-    fn div(&self, b: T) -> T;
+    fn add(&self, b: T) -> Result<T, String>;
+    fn sub(&self, b: T) -> Result<T, String>;
+    fn mul(&self, b: T) -> Result<T, String>;
+    fn div(&self, b: T) -> Result<T, String>;
+    /// Remainder
+    fn rem(&self, b: T) -> Result<T, String>;
+    /// Exponentiation
+    fn pow(&self, b: T) -> Result<T, String>;
+    /// Left shift
+    fn shl(&self, b: T) -> Result<T, String>;
+    /// Right shift
+    fn shr(&self, b: T) -> Result<T, String>;
     /// Equality
-    fn _eq(&self, b: T) -> T;
+    fn _eq(&self, b: T) -> Result<T, String>;
     /// Inequality
-    fn _ne(&self, b: T) -> T;
+    fn _ne(&self, b: T) -> Result<T, String>;
     /// Greater than
-    fn gt(&self, b: T) -> T;
+    fn gt(&self, b: T) -> Result<T, String>;
     /// Less than
-    fn lt(&self, b: T) -> T;
+    fn lt(&self, b: T) -> Result<T, String>;
     /// Greater than or equal to
-    fn ge(&self, b: T) -> T;
+    fn ge(&self, b: T) -> Result<T, String>;
     /// Less than or equal to
-    fn le(&self, b: T) -> T;
+    fn le(&self, b: T) -> Result<T, String>;
     /// Logical or
-    fn or(&self, b: T) -> T;
+    fn or(&self, b: T) -> Result<T, String>;
     /// Logical and
-    fn and(&self, b: T) -> T;
+    fn and(&self, b: T) -> Result<T, String>;
     /// Logical not
-    fn not(&self) -> T;
+    fn not(&self) -> Result<T, String>;
+    /// Negation
+    fn neg(&self) -> Result<T, String>;
 }
 
 /// Should be implemented on all types that can be initialized.
@@ -43,3 +54,36 @@ pub trait Instance {
 pub trait Callable {
     fn call(&self, vm: &mut HighLevelVirtualMachine) -> Result<HlvmValue, String>;
 }
+
+/// Implemented by Rust objects that a host exposes to programs with `HlvmValue::native`.
+/// Objects only implement what they support, everything else errors like it does for values
+/// that don't support it.
+///
+/// Every copy of a native value shares the object, so objects with state that changes keep it
+/// in a `Cell` or `RefCell`, and shouldn't hold a borrow of it while calling back into the VM.
+pub trait NativeObject {
+    /// Reads the attribute `name`, for `object.name`.
+    fn get(&self, name: &str) -> Result<HlvmValue, String> {
+        Err(ErrorCode::UndefinedAttribute.message(format!("Undefined attribute {}", name)))
+    }
+
+    /// Sets the attribute `name`, for `object.name = value`.
+    fn set(&self, name: &str, _value: HlvmValue) -> Result<(), String> {
+        Err(ErrorCode::NoAttributes
+            .message(format!("Cannot set attribute {} of a native object", name)))
+    }
+
+    /// Calls the object with `arguments`, which are in the order they were passed.
+    fn call(
+        &self,
+        _arguments: Vec<HlvmValue>,
+        _vm: &mut HighLevelVirtualMachine,
+    ) -> Result<HlvmValue, String> {
+        Err(ErrorCode::NotCallable.message("Cannot call a native object"))
+    }
+
+    /// What `print!` writes for the object.
+    fn display(&self) -> String {
+        "<native-object>".to_string()
+    }
+}
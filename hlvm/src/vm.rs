@@ -1,17 +1,284 @@
 use crate::{
+    error::{not_found, ErrorCode},
     lir::{HlvmCallFrame, HlvmInstruction, HlvmValue},
-    traits::*, dev::{hlvm_print, hlvm_exit},
+    module::constants,
+    traits::*,
+    dev::{
+        check_arguments, convert, index_value, join_arguments, slice_value, type_name,
+        hlvm_args, hlvm_assert_eq, hlvm_chars, hlvm_chr, hlvm_clone, hlvm_each, hlvm_eprintln,
+        hlvm_exit, hlvm_filter, hlvm_freeze, hlvm_is, hlvm_is_type, hlvm_map, hlvm_memory_usage,
+        hlvm_ord, hlvm_parse_float, hlvm_parse_number, hlvm_print, hlvm_reduce, hlvm_repr,
+        hlvm_to_fixed, hlvm_to_precision, hlvm_write, memory_size, repeated_length,
+    },
 };
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// A primitive function, called with its arguments in order.
+pub type Primitive = fn(Vec<HlvmValue>, &mut HighLevelVirtualMachine) -> Result<HlvmValue, String>;
+
+/// A primitive function, along with what the compiler needs to know to call it.
+pub struct PrimitiveFunction {
+    /// The name it's called by, like `print!`.
+    pub name: &'static str,
+
+    /// How many arguments it takes, at least and at most.
+    /// `None` means there's no upper limit, like for `print!`.
+    pub arity: (usize, Option<usize>),
+
+    /// The type it always returns, named the way `typeof` names it,
+    /// or `None` if that depends on its arguments.
+    pub returns: Option<&'static str>,
+
+    pub function: Primitive,
+}
+
+const fn primitive(
+    name: &'static str,
+    arity: (usize, Option<usize>),
+    function: Primitive,
+) -> PrimitiveFunction {
+    PrimitiveFunction {
+        name,
+        arity,
+        returns: None,
+        function,
+    }
+}
+
+impl PrimitiveFunction {
+    const fn returns(mut self, datatype: &'static str) -> Self {
+        self.returns = Some(datatype);
+        self
+    }
+}
+
+/// Every primitive function, indexed by the id the compiler resolved its name to.
+pub const PRIMITIVES: [PrimitiveFunction; 27] = [
+    primitive("print!", (0, None), |arguments, vm| {
+        vm.count_output(&arguments, true)?;
+        Ok(hlvm_print(arguments))
+    }),
+    primitive("exit!", (0, Some(0)), |arguments, _| Ok(hlvm_exit(arguments))),
+    primitive("args!", (0, Some(1)), |arguments, vm| Ok(hlvm_args(arguments, &vm.arguments))),
+    primitive("parse_number!", (1, Some(1)), |arguments, _| Ok(hlvm_parse_number(arguments))),
+    primitive("parse_float!", (1, Some(1)), |arguments, _| Ok(hlvm_parse_float(arguments))),
+    primitive("chars!", (1, Some(1)), |arguments, _| Ok(hlvm_chars(arguments))),
+    primitive("ord!", (1, Some(1)), |arguments, _| Ok(hlvm_ord(arguments))),
+    primitive("chr!", (1, Some(1)), |arguments, _| Ok(hlvm_chr(arguments))),
+    /* Higher-order primitives call back into the VM */
+    primitive("map!", (2, Some(2)), hlvm_map),
+    primitive("filter!", (2, Some(2)), hlvm_filter),
+    primitive("reduce!", (3, Some(3)), hlvm_reduce),
+    primitive("each!", (2, Some(2)), hlvm_each),
+    primitive("write!", (0, None), |arguments, vm| {
+        vm.count_output(&arguments, false)?;
+        Ok(hlvm_write(arguments))
+    }),
+    primitive("eprintln!", (0, None), |arguments, vm| {
+        vm.count_output(&arguments, true)?;
+        Ok(hlvm_eprintln(arguments))
+    }),
+    primitive("repr!", (1, Some(1)), |arguments, _| Ok(hlvm_repr(arguments))),
+    primitive("clone!", (1, Some(1)), |arguments, _| Ok(hlvm_clone(arguments))),
+    primitive("is!", (2, Some(2)), |arguments, _| Ok(hlvm_is(arguments))).returns("bool"),
+    primitive("is_number!", (1, Some(1)), |arguments, _| {
+        Ok(hlvm_is_type(arguments, "number"))
+    })
+    .returns("bool"),
+    primitive("is_string!", (1, Some(1)), |arguments, _| {
+        Ok(hlvm_is_type(arguments, "string"))
+    })
+    .returns("bool"),
+    primitive("is_bool!", (1, Some(1)), |arguments, _| Ok(hlvm_is_type(arguments, "bool")))
+        .returns("bool"),
+    primitive("is_array!", (1, Some(1)), |arguments, _| Ok(hlvm_is_type(arguments, "array")))
+        .returns("bool"),
+    primitive("is_none!", (1, Some(1)), |arguments, _| Ok(hlvm_is_type(arguments, "none")))
+        .returns("bool"),
+    primitive("to_fixed!", (2, Some(2)), |arguments, _| Ok(hlvm_to_fixed(arguments))),
+    primitive("to_precision!", (2, Some(2)), |arguments, _| Ok(hlvm_to_precision(arguments))),
+    primitive("memory_usage!", (0, Some(0)), |_, vm| Ok(hlvm_memory_usage(vm))),
+    primitive("assert_eq!", (2, Some(2)), |arguments, _| hlvm_assert_eq(arguments)),
+    primitive("freeze!", (1, Some(1)), |arguments, _| Ok(hlvm_freeze(arguments))),
+];
+
+/// The index of the primitive function `name`, like `print!`, in `PRIMITIVES`.
+pub fn primitive_index(name: &str) -> Option<usize> {
+    PRIMITIVES.iter().position(|primitive| primitive.name == name)
+}
+
+/// Describes a stack underflow at instruction `ip`, along with the instructions around it.
+/// Compiled programs never underflow, so this means the compiler has a bug or the program
+/// was corrupted.
+fn underflow(instructions: &[HlvmInstruction], ip: usize) -> String {
+    let mut message = format!("Stack underflow at instruction {}", ip);
+
+    let first = ip.saturating_sub(2);
+
+    for (idx, instruction) in instructions.iter().enumerate().skip(first).take(ip + 3 - first) {
+        let marker = if idx == ip { ">" } else { " " };
+        message.push_str(&format!("\n  {} {:05}  {:?}", marker, idx, instruction));
+    }
+
+    ErrorCode::StackUnderflow.message(message)
+}
+
+fn memory_limit_exceeded(limit: usize) -> String {
+    ErrorCode::MemoryLimitExceeded.message(format!("Used more than {} bytes of memory", limit))
+}
+
+/// The value of `name` in `scope`, or an error naming the closest name it does define.
+fn lookup(scope: &HashMap<String, HlvmValue>, kind: &str, name: &str) -> Result<HlvmValue, String> {
+    match scope.get(name) {
+        Some(value) => Ok(value.clone()),
+        None => Err(ErrorCode::UndefinedName
+            .message(not_found(kind, name, scope.keys().map(String::as_str)))),
+    }
+}
+
+/// The name of the variable called by the `Call` at `ip`. Calls don't carry the callee's
+/// name, so it's only known when the callee was read from a variable right before the call.
+fn callee_name(instructions: &[HlvmInstruction], ip: usize) -> Option<&str> {
+    match instructions.get(ip.checked_sub(1)?)? {
+        HlvmInstruction::Get(name)
+        | HlvmInstruction::GetLocal(name)
+        | HlvmInstruction::GetGlobal(name) => {
+            /* Drop the suffix the compiler's resolver gives names, like `x@3` */
+            name.split('@').next()
+        }
+        _ => None,
+    }
+}
+
+/// The name of the method a struct instance can define to overload `instruction`.
+fn operator_method(instruction: &HlvmInstruction) -> Option<&'static str> {
+    match instruction {
+        HlvmInstruction::Add => Some("__add"),
+        HlvmInstruction::Subtract => Some("__sub"),
+        HlvmInstruction::Multiply => Some("__mul"),
+        HlvmInstruction::Divide => Some("__div"),
+        HlvmInstruction::Modulo => Some("__mod"),
+        HlvmInstruction::Power => Some("__pow"),
+        HlvmInstruction::ShiftLeft => Some("__shl"),
+        HlvmInstruction::ShiftRight => Some("__shr"),
+        HlvmInstruction::Equal => Some("__eq"),
+        HlvmInstruction::NotEqual => Some("__ne"),
+        HlvmInstruction::GreaterThan => Some("__gt"),
+        HlvmInstruction::LessThan => Some("__lt"),
+        HlvmInstruction::GreaterThanOrEqual => Some("__ge"),
+        HlvmInstruction::LessThanOrEqual => Some("__le"),
+        _ => None,
+    }
+}
+
+/// Limits and preallocations for a `HighLevelVirtualMachine`.
+/// Every limit is off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmOptions {
+    /// How many values the operand stack has room for before it has to grow.
+    pub stack_capacity: usize,
+
+    /// How deeply functions may call each other before execution stops with E0308.
+    pub max_call_depth: Option<usize>,
+
+    /// How many instructions may run before execution stops with E0309.
+    pub max_instructions: Option<u64>,
+
+    /// How long the VM may run, from when it's created, before execution stops with E0312.
+    pub timeout: Option<Duration>,
+
+    /// How many bytes `print!`, `write!` and `eprintln!` may write in total before execution
+    /// stops with E0313. The call that would go over the limit writes nothing.
+    pub max_output: Option<usize>,
+
+    /// About how many bytes the values on the stack and in variables may take before
+    /// execution stops with E0316. See `memory_usage`.
+    pub max_memory: Option<usize>,
+}
+
+impl Default for VmOptions {
+    fn default() -> Self {
+        VmOptions {
+            stack_capacity: 8,
+            max_call_depth: None,
+            max_instructions: None,
+            timeout: None,
+            max_output: None,
+            max_memory: None,
+        }
+    }
+}
+
+impl VmOptions {
+    pub fn stack_capacity(mut self, capacity: usize) -> Self {
+        self.stack_capacity = capacity;
+        self
+    }
+
+    pub fn max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = Some(depth);
+        self
+    }
+
+    pub fn max_instructions(mut self, instructions: u64) -> Self {
+        self.max_instructions = Some(instructions);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_output(mut self, bytes: usize) -> Self {
+        self.max_output = Some(bytes);
+        self
+    }
+
+    pub fn max_memory(mut self, bytes: usize) -> Self {
+        self.max_memory = Some(bytes);
+        self
+    }
+}
 
 pub struct HighLevelVirtualMachine {
     pub stack: Vec<HlvmValue>,
     pub call_stack: Vec<HlvmCallFrame>,
+
+    /// The arguments passed to the program, available through `args!`.
+    pub arguments: Vec<String>,
+
+    /// Read-only names like `PI`, checked before globals and locals.
+    pub constants: HashMap<String, HlvmValue>,
+
+    pub options: VmOptions,
+
+    /// How many instructions have run, only counted if `options.max_instructions`,
+    /// `options.timeout` or `options.max_memory` is set.
+    pub executed: u64,
+
+    /// When `options.timeout` runs out.
+    deadline: Option<Instant>,
+
+    /// How many bytes the printing primitives wrote, only counted if `options.max_output` is set.
+    pub written: usize,
 }
 
 impl HighLevelVirtualMachine {
     /// Instantiate a new HighLevelVirtualMachine.
     pub fn new(local_prealloc: Option<usize>) -> HighLevelVirtualMachine {
+        HighLevelVirtualMachine::with_options(local_prealloc, VmOptions::default())
+    }
+
+    /// Instantiate a new HighLevelVirtualMachine with the given limits.
+    pub fn with_options(
+        local_prealloc: Option<usize>,
+        options: VmOptions,
+    ) -> HighLevelVirtualMachine {
         let mut call_stack = Vec::with_capacity(8);
 
         /* Push the main frame to the call stack */
@@ -20,8 +287,14 @@ impl HighLevelVirtualMachine {
         });
 
         HighLevelVirtualMachine {
-            stack: Vec::with_capacity(8),
+            stack: Vec::with_capacity(options.stack_capacity),
             call_stack,
+            arguments: vec![],
+            constants: constants(),
+            options,
+            executed: 0,
+            deadline: options.timeout.map(|timeout| Instant::now() + timeout),
+            written: 0,
         }
     }
 
@@ -61,6 +334,106 @@ impl HighLevelVirtualMachine {
             .locals
     }
 
+    /// About how many bytes the values on the stack and in variables take. Arrays shared
+    /// between copies are counted once, and function bodies, being part of the program,
+    /// aren't counted at all.
+    pub fn memory_usage(&self) -> usize {
+        let mut seen = HashSet::new();
+        let locals = self.call_stack.iter().flat_map(|frame| frame.locals.iter());
+
+        let variables: usize = locals
+            .map(|(name, value)| name.capacity() + memory_size(value, &mut seen))
+            .sum();
+        let stack: usize = self.stack.iter().map(|value| memory_size(value, &mut seen)).sum();
+
+        variables + stack
+    }
+
+    /// Errors if the program ran more instructions, for longer, or kept more memory alive than
+    /// it's allowed to.
+    fn check_limits(&self) -> Result<(), String> {
+        if let Some(limit) = self.options.max_instructions {
+            if self.executed > limit {
+                return Err(ErrorCode::InstructionLimitExceeded
+                    .message(format!("Ran more than {} instructions", limit)));
+            }
+        }
+
+        /* Reading the clock takes longer than most instructions, so it's only read now and then */
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.options.timeout) {
+            if self.executed.is_multiple_of(1024) && Instant::now() >= deadline {
+                return Err(ErrorCode::TimeLimitExceeded
+                    .message(format!("Ran for longer than {:?}", timeout)));
+            }
+        }
+
+        /* Measuring memory walks every live value, so it's only measured now and then too */
+        if let Some(limit) = self.options.max_memory {
+            if self.executed.is_multiple_of(1024) && self.memory_usage() > limit {
+                return Err(memory_limit_exceeded(limit));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Errors if multiplying `left` by `right` repeats a string past `options.max_memory`.
+    /// It's checked before the string is made, since a large count can make a huge one.
+    fn check_repeat(&self, left: &HlvmValue, right: &HlvmValue) -> Result<(), String> {
+        if let (HlvmValue::String(string), HlvmValue::Number(count), Some(limit)) =
+            (left, right, self.options.max_memory)
+        {
+            if repeated_length(string, *count).is_some_and(|length| length > limit) {
+                return Err(memory_limit_exceeded(limit));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts what printing `arguments` writes towards `options.max_output`,
+    /// erroring instead if it doesn't fit.
+    fn count_output(&mut self, arguments: &[HlvmValue], newline: bool) -> Result<(), String> {
+        if let Some(limit) = self.options.max_output {
+            let bytes = join_arguments(arguments).len() + newline as usize;
+
+            if self.written + bytes > limit {
+                return Err(ErrorCode::OutputLimitExceeded
+                    .message(format!("Printed more than {} bytes", limit)));
+            }
+
+            self.written += bytes;
+        }
+
+        Ok(())
+    }
+
+    /// Calls the global function `name` with `arguments`, returning its result.
+    /// Lets host applications call into lace code after its top-level statements have run.
+    pub fn call(&mut self, name: &str, arguments: Vec<HlvmValue>) -> Result<HlvmValue, String> {
+        let function = match self.get_global_scope().get(name) {
+            Some(function) => function.clone(),
+            None => {
+                return Err(ErrorCode::UndefinedName.message(not_found(
+                    "Function",
+                    name,
+                    self.get_global_scope().keys().map(String::as_str),
+                )))
+            }
+        };
+
+        check_arguments(&function, arguments.len())?;
+
+        /* Parameters are popped in order, so the first argument has to end up on top */
+        self.stack.extend(arguments.into_iter().rev());
+        function.call(self)
+    }
+
+    /// Pops the top of the stack, which instruction `ip` of `instructions` expects to be there.
+    fn pop(&mut self, instructions: &[HlvmInstruction], ip: usize) -> Result<HlvmValue, String> {
+        self.stack.pop().ok_or_else(|| underflow(instructions, ip))
+    }
+
     /// Main entry point of the VM.
     /// Returns a `Result::Err` if an error occurs, with an appropriate error message.
     pub fn execute(&mut self, instructions: &[HlvmInstruction]) -> Result<HlvmValue, String> {
@@ -72,31 +445,56 @@ impl HighLevelVirtualMachine {
                 break;
             }
             
+            if self.options.max_instructions.is_some()
+                || self.deadline.is_some()
+                || self.options.max_memory.is_some()
+            {
+                self.executed += 1;
+                self.check_limits()?;
+            }
+
             let instruction = &instructions[ip];
 
             match instruction {
                 /* Setting and getting globals and locals */
                 SetGlobal(name) => {
-                    let top = self.stack.pop().unwrap();
+                    let top = self.pop(instructions, ip)?;
                     self.get_mut_global_scope().insert(name.to_string(), top);
                 }
                 SetLocal(name) => {
-                    let top = self.stack.pop().unwrap();
+                    let top = self.pop(instructions, ip)?;
                     self.get_mut_local_scope().insert(name.to_string(), top);
                 }
 
-                GetGlobal(name) => self
-                    .stack
-                    .push(self.get_global_scope().get(name).unwrap().clone()),
-                GetLocal(name) => self
-                    .stack
-                    .push(self.get_local_scope().get(name).unwrap().clone()),
+                /* The compiler only emits these for names it declared, but `asm` blocks can
+                   name anything */
+                GetGlobal(name) => {
+                    let value = lookup(self.get_global_scope(), "Global", name)?;
+                    self.stack.push(value);
+                }
+                GetLocal(name) => {
+                    let value = lookup(self.get_local_scope(), "Local", name)?;
+                    self.stack.push(value);
+                }
                 Get(name) => {
-                    let val = match self.get_global_scope().get(name) {
+                    let val = match self
+                        .constants
+                        .get(name)
+                        .or_else(|| self.get_global_scope().get(name))
+                    {
                         Some(a) => a,
                         None => match self.get_local_scope().get(name) {
                             Some(a) => a,
-                            None => panic!()
+                            None => {
+                                let scopes = self
+                                    .constants
+                                    .keys()
+                                    .chain(self.get_global_scope().keys())
+                                    .chain(self.get_local_scope().keys());
+
+                                return Err(ErrorCode::UndefinedName
+                                    .message(not_found("Name", name, scopes.map(String::as_str))));
+                            }
                         },
                     };
 
@@ -104,12 +502,41 @@ impl HighLevelVirtualMachine {
                 }
 
                 /* Returning values */
-                ReturnValue => return Ok(self.stack.pop().unwrap()),
-                Return => return Result::Ok(HlvmValue::Number(0.0)),
+                ReturnValue => return self.pop(instructions, ip),
+                Return => return Result::Ok(HlvmValue::None),
 
                 Push(val) => self.stack.push(val.clone()),
-                Call => {
-                    let function = self.stack.pop().unwrap();
+                Call(count) => {
+                    let function = self.pop(instructions, ip)?;
+
+                    /* Native objects take their arguments in order, like primitives */
+                    if let HlvmValue::Native(native) = &function {
+                        let mut arguments = Vec::with_capacity(*count);
+
+                        for _ in 0..*count {
+                            arguments.push(self.pop(instructions, ip)?);
+                        }
+
+                        let value = native.call(arguments, self)?;
+                        self.stack.push(value);
+                        ip += 1;
+                        continue;
+                    }
+
+                    if !matches!(function, HlvmValue::Function(..)) {
+                        let value = match callee_name(instructions, ip) {
+                            Some(name) => format!("variable '{}'", name),
+                            None => "value".to_string(),
+                        };
+
+                        return Err(ErrorCode::NotCallable.message(format!(
+                            "Cannot call {} of type {}",
+                            value,
+                            type_name(&function)
+                        )));
+                    }
+
+                    check_arguments(&function, *count)?;
 
                     match function.call(self) {
                         Ok(val) => self.stack.push(val),
@@ -117,47 +544,104 @@ impl HighLevelVirtualMachine {
                     }
                 }
                 CallPrimitive(index, args) => {
+                    let primitive = match PRIMITIVES.get(*index) {
+                        Some(primitive) => primitive.function,
+                        None => {
+                            return Err(ErrorCode::InvalidInstruction.message(format!(
+                                "Instruction {} calls primitive function {}, but there are only {}",
+                                ip,
+                                index,
+                                PRIMITIVES.len()
+                            )))
+                        }
+                    };
+
                     let mut arguments = Vec::with_capacity(*args);
 
                     for _ in 0..*args {
-                        arguments.push(self.stack.pop().unwrap());
+                        arguments.push(self.pop(instructions, ip)?);
                     }
 
-                    let value = match index {
-                        0 => hlvm_print(arguments),
-                        1 => hlvm_exit(arguments),
-                        _ => panic!("Invalid primitive function")
-                    };
+                    let value = primitive(arguments, self)?;
 
                     self.stack.push(value);
                 }
 
-                Add | Subtract | Multiply | Divide | Equal | NotEqual | GreaterThan | LessThan
-                | GreaterThanOrEqual | LessThanOrEqual | And | Or => {
-                    let right = self.stack.pop().unwrap();
-                    let left = self.stack.pop().unwrap();
-
-                    match instruction {
-                        HlvmInstruction::Add => self.stack.push(left.add(right)),
-                        HlvmInstruction::Subtract => self.stack.push(left.sub(right)),
-                        HlvmInstruction::Multiply => self.stack.push(left.mul(right)),
-                        HlvmInstruction::Divide => self.stack.push(left.div(right)),
-                        HlvmInstruction::Equal => self.stack.push(left._eq(right)),
-                        HlvmInstruction::NotEqual => self.stack.push(left._ne(right)),
-                        HlvmInstruction::GreaterThan => self.stack.push(left.gt(right)),
-                        HlvmInstruction::LessThan => self.stack.push(left.lt(right)),
-                        HlvmInstruction::GreaterThanOrEqual => self.stack.push(left.ge(right)),
-                        HlvmInstruction::LessThanOrEqual => self.stack.push(left.le(right)),
-                        HlvmInstruction::And => self.stack.push(left.and(right)),
-                        HlvmInstruction::Or => self.stack.push(left.or(right)),
-                        HlvmInstruction::Not => self.stack.push(left.not()),
+                Add | Subtract | Multiply | Divide | Modulo | Power | ShiftLeft | ShiftRight
+                | Equal | NotEqual | GreaterThan | LessThan | GreaterThanOrEqual
+                | LessThanOrEqual | And | Or => {
+                    let right = self.pop(instructions, ip)?;
+                    let left = self.pop(instructions, ip)?;
+
+                    /* Struct instances may overload operators by defining e.g. `__add` */
+                    if let HlvmValue::StructInstance(ref attributes, _) = left {
+                        if let Some(method) = operator_method(instruction)
+                            .and_then(|method| attributes.get(method))
+                            .cloned()
+                        {
+                            /* Arguments are popped in order, so `self` goes on top */
+                            check_arguments(&method, 2)?;
+                            self.stack.push(right);
+                            self.stack.push(left);
+                            let value = method.call(self)?;
+                            self.stack.push(value);
+                            ip += 1;
+                            continue;
+                        }
+                    }
+
+                    let value = match instruction {
+                        HlvmInstruction::Add => left.add(right),
+                        HlvmInstruction::Subtract => left.sub(right),
+                        HlvmInstruction::Multiply => {
+                            self.check_repeat(&left, &right).and_then(|()| left.mul(right))
+                        }
+                        HlvmInstruction::Divide => left.div(right),
+                        HlvmInstruction::Modulo => left.rem(right),
+                        HlvmInstruction::Power => left.pow(right),
+                        HlvmInstruction::ShiftLeft => left.shl(right),
+                        HlvmInstruction::ShiftRight => left.shr(right),
+                        HlvmInstruction::Equal => left._eq(right),
+                        HlvmInstruction::NotEqual => left._ne(right),
+                        HlvmInstruction::GreaterThan => Operation::gt(&left, right),
+                        HlvmInstruction::LessThan => Operation::lt(&left, right),
+                        HlvmInstruction::GreaterThanOrEqual => Operation::ge(&left, right),
+                        HlvmInstruction::LessThanOrEqual => Operation::le(&left, right),
+                        HlvmInstruction::And => left.and(right),
+                        HlvmInstruction::Or => left.or(right),
                         _ => panic!("The universe should've collapsed by now."),
+                    };
+
+                    self.stack.push(value?);
+
+                    /* Repeated `+` can double a value every few instructions, faster than memory
+                       is measured, so the result is checked on its own right away */
+                    if let (Some(limit), Some(value)) = (self.options.max_memory, self.stack.last())
+                    {
+                        if memory_size(value, &mut HashSet::new()) > limit {
+                            return Err(memory_limit_exceeded(limit));
+                        }
                     }
                 }
 
                 Not => {
-                    let value = self.stack.pop().unwrap();
-                    self.stack.push(value.not());
+                    let value = self.pop(instructions, ip)?;
+                    self.stack.push(value.not()?);
+                }
+
+                Negate => {
+                    let value = self.pop(instructions, ip)?;
+                    self.stack.push(value.neg()?);
+                }
+
+                Pop => {
+                    self.pop(instructions, ip)?;
+                }
+
+                /* The names are the ones `is_number!` and the other `is_…!` primitives check */
+                Typeof => {
+                    let value = self.pop(instructions, ip)?;
+                    self.stack.push(HlvmValue::String(type_name(&value).to_string()));
                 }
 
                 Jump(addr) => {
@@ -166,14 +650,14 @@ impl HighLevelVirtualMachine {
                 }
 
                 JumpIf(addr) => {
-                    if self.stack.pop().unwrap().is_truthy() {
+                    if self.pop(instructions, ip)?.is_truthy() {
                         ip = *addr;
                         continue;
                     }
                 }
 
                 Instantiate => {
-                    let obj = self.stack.pop().unwrap();
+                    let obj = self.pop(instructions, ip)?;
 
                     match obj.initialize(&mut self.stack) {
                         Ok(obj) => self.stack.push(obj),
@@ -181,31 +665,90 @@ impl HighLevelVirtualMachine {
                     };
                 }
 
+                Index => {
+                    let index = self.pop(instructions, ip)?;
+                    let value = self.pop(instructions, ip)?;
+                    self.stack.push(index_value(value, index)?);
+                }
+
+                Slice => {
+                    let end = self.pop(instructions, ip)?;
+                    let start = self.pop(instructions, ip)?;
+                    let value = self.pop(instructions, ip)?;
+                    self.stack.push(slice_value(value, start, end)?);
+                }
+
+                BuildArray(count) => {
+                    if self.stack.len() < *count {
+                        return Err(underflow(instructions, ip));
+                    }
+
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    self.stack.push(HlvmValue::array(elements));
+                }
+
+                Unpack(count, rest) => {
+                    let elements = match self.pop(instructions, ip)? {
+                        HlvmValue::Array(elements) => elements,
+                        value => {
+                            return Err(ErrorCode::PatternMismatch
+                                .message(format!("Cannot destructure {:?} as an array", value)))
+                        }
+                    };
+
+                    if elements.len() < *count || (!rest && elements.len() != *count) {
+                        return Err(ErrorCode::PatternMismatch.message(format!(
+                            "Expected {}{} elements, found {}",
+                            if *rest { "at least " } else { "" },
+                            count,
+                            elements.len()
+                        )));
+                    }
+
+                    /* The elements are only copied if another copy of the array shares them */
+                    let mut elements = Rc::unwrap_or_clone(elements).into_iter();
+                    let mut head: Vec<HlvmValue> = elements.by_ref().take(*count).collect();
+
+                    if *rest {
+                        self.stack.push(HlvmValue::array(elements.collect()));
+                    }
+
+                    head.reverse();
+                    self.stack.append(&mut head);
+                }
+
+                Convert(target) => {
+                    let value = self.pop(instructions, ip)?;
+                    self.stack.push(convert(value, *target)?);
+                }
+
                 GetAttribute(attr) => {
-                    let obj = self.stack.pop().unwrap();
+                    let obj = self.pop(instructions, ip)?;
 
-                    self.stack.push(match obj.get(attr.to_string()) {
-                        Ok(val) => val,
-                        Err(err) => return Result::Err(err),
-                    });
+                    self.stack.push(obj.get(attr.to_string())?);
                 }
 
                 SetAttribute(attr) => {
-                    let val = self.stack.pop().unwrap();
-                    let obj = self.stack.last_mut().unwrap();
+                    let val = self.pop(instructions, ip)?;
+                    let obj = match self.stack.last_mut() {
+                        Some(obj) => obj,
+                        None => return Err(underflow(instructions, ip)),
+                    };
 
-                    match obj.set(attr.to_string(), val) {
-                        Ok(val) => val,
-                        Err(err) => return Result::Err(err),
-                    }
+                    obj.set(attr.to_string(), val)?;
                 }
 
-                unimplemented => todo!("{:?}", unimplemented)
+                unimplemented => {
+                    return Err(ErrorCode::InvalidInstruction.message(format!(
+                        "Instruction {} ({:?}) isn't supported by the VM",
+                        ip, unimplemented
+                    )))
+                }
             }
 
             ip += 1;
         }
 
-        Result::Ok(HlvmValue::Number(0.0))
+        Result::Ok(HlvmValue::None)
     }
 }
@@ -0,0 +1,213 @@
+//! Native objects, the way a host embedding the VM uses them.
+
+use hlvm::{
+    lir::{HlvmInstruction, HlvmValue},
+    traits::NativeObject,
+    vm::{primitive_index, HighLevelVirtualMachine},
+};
+use std::cell::Cell;
+
+/// A counter with a `count` attribute. Calling it adds its arguments to the count.
+#[derive(Default)]
+struct Counter {
+    count: Cell<f64>,
+}
+
+impl NativeObject for Counter {
+    fn get(&self, name: &str) -> Result<HlvmValue, String> {
+        match name {
+            "count" => Ok(HlvmValue::Number(self.count.get())),
+            _ => Err(format!("Counters have no attribute {}", name)),
+        }
+    }
+
+    fn set(&self, name: &str, value: HlvmValue) -> Result<(), String> {
+        match (name, value) {
+            ("count", HlvmValue::Number(count)) => self.count.set(count),
+            _ => return Err(format!("Can't set {} of a counter", name)),
+        }
+
+        Ok(())
+    }
+
+    fn call(
+        &self,
+        arguments: Vec<HlvmValue>,
+        _vm: &mut HighLevelVirtualMachine,
+    ) -> Result<HlvmValue, String> {
+        for argument in arguments {
+            match argument {
+                HlvmValue::Number(n) => self.count.set(self.count.get() + n),
+                argument => return Err(format!("Can't count {:?}", argument)),
+            }
+        }
+
+        Ok(HlvmValue::Number(self.count.get()))
+    }
+
+    fn display(&self) -> String {
+        format!("<counter at {}>", self.count.get())
+    }
+}
+
+/// A counter that, when called, runs a program setting `count` of the object it's given to 7.
+#[derive(Default)]
+struct Reentrant(Counter);
+
+impl NativeObject for Reentrant {
+    fn get(&self, name: &str) -> Result<HlvmValue, String> {
+        self.0.get(name)
+    }
+
+    fn set(&self, name: &str, value: HlvmValue) -> Result<(), String> {
+        self.0.set(name, value)
+    }
+
+    fn call(
+        &self,
+        arguments: Vec<HlvmValue>,
+        vm: &mut HighLevelVirtualMachine,
+    ) -> Result<HlvmValue, String> {
+        vm.execute(&[
+            HlvmInstruction::Push(arguments[0].clone()),
+            HlvmInstruction::Push(HlvmValue::Number(7.0)),
+            HlvmInstruction::SetAttribute("count".to_string()),
+        ])?;
+
+        Ok(HlvmValue::None)
+    }
+}
+
+fn counter_get(counter: &HlvmValue) -> f64 {
+    match counter {
+        HlvmValue::Native(native) => match native.get("count") {
+            Ok(HlvmValue::Number(count)) => count,
+            other => panic!("Expected a count, got {:?}", other),
+        },
+        _ => panic!("Expected a native object"),
+    }
+}
+
+fn counter_set(counter: &HlvmValue, count: f64) {
+    match counter {
+        HlvmValue::Native(native) => native.set("count", HlvmValue::Number(count)).unwrap(),
+        _ => panic!("Expected a native object"),
+    }
+}
+
+fn execute(instructions: &[HlvmInstruction]) -> Result<HlvmValue, String> {
+    HighLevelVirtualMachine::new(Some(1)).execute(instructions)
+}
+
+#[test]
+fn programs_read_attributes() {
+    let counter = HlvmValue::native(Counter::default());
+    counter_set(&counter, 3.0);
+
+    let value = execute(&[
+        HlvmInstruction::Push(counter),
+        HlvmInstruction::GetAttribute("count".to_string()),
+        HlvmInstruction::ReturnValue,
+    ]);
+
+    assert_eq!(value, Ok(HlvmValue::Number(3.0)));
+}
+
+#[test]
+fn programs_set_attributes_on_every_copy() {
+    let counter = HlvmValue::native(Counter::default());
+
+    execute(&[
+        HlvmInstruction::Push(counter.clone()),
+        HlvmInstruction::Push(HlvmValue::Number(5.0)),
+        HlvmInstruction::SetAttribute("count".to_string()),
+    ])
+    .unwrap();
+
+    assert_eq!(counter_get(&counter), 5.0);
+}
+
+#[test]
+fn objects_report_their_own_errors() {
+    let error = execute(&[
+        HlvmInstruction::Push(HlvmValue::native(Counter::default())),
+        HlvmInstruction::GetAttribute("size".to_string()),
+    ])
+    .unwrap_err();
+
+    assert!(error.contains("Counters have no attribute size"), "{}", error);
+}
+
+#[test]
+fn programs_call_objects_with_arguments_in_order() {
+    struct First;
+
+    impl NativeObject for First {
+        fn call(
+            &self,
+            arguments: Vec<HlvmValue>,
+            _vm: &mut HighLevelVirtualMachine,
+        ) -> Result<HlvmValue, String> {
+            Ok(arguments[0].clone())
+        }
+    }
+
+    /* Like compiled calls, the first argument is pushed last */
+    let first = execute(&[
+        HlvmInstruction::Push(HlvmValue::Number(2.0)),
+        HlvmInstruction::Push(HlvmValue::Number(1.0)),
+        HlvmInstruction::Push(HlvmValue::native(First)),
+        HlvmInstruction::Call(2),
+        HlvmInstruction::ReturnValue,
+    ]);
+
+    assert_eq!(first, Ok(HlvmValue::Number(1.0)));
+}
+
+#[test]
+fn calls_can_reenter_the_vm_with_the_same_object() {
+    let counter = HlvmValue::native(Reentrant::default());
+
+    /* The object sets an attribute on itself while it's being called */
+    execute(&[
+        HlvmInstruction::Push(counter.clone()),
+        HlvmInstruction::Push(counter.clone()),
+        HlvmInstruction::Call(1),
+        HlvmInstruction::Pop,
+    ])
+    .unwrap();
+
+    assert_eq!(counter_get(&counter), 7.0);
+}
+
+#[test]
+fn objects_display_themselves() {
+    let counter = HlvmValue::native(Counter::default());
+    counter_set(&counter, 2.0);
+
+    assert_eq!(counter.to_string(), "<counter at 2>");
+    assert_eq!(format!("{:?}", counter), "Native(Native(<counter at 2>))");
+
+    struct Plain;
+    impl NativeObject for Plain {}
+
+    assert_eq!(HlvmValue::native(Plain).to_string(), "<native-object>");
+}
+
+#[test]
+fn is_compares_objects_by_identity() {
+    let counter = HlvmValue::native(Counter::default());
+    let is = |a: HlvmValue, b: HlvmValue| {
+        execute(&[
+            HlvmInstruction::Push(a),
+            HlvmInstruction::Push(b),
+            HlvmInstruction::CallPrimitive(primitive_index("is!").unwrap(), 2),
+            HlvmInstruction::ReturnValue,
+        ])
+    };
+
+    assert_eq!(is(counter.clone(), counter.clone()), Ok(true.into()));
+
+    let other = HlvmValue::native(Counter::default());
+    assert_eq!(is(counter, other), Ok(false.into()));
+}
@@ -0,0 +1,117 @@
+/* --------------------------------------------------------------
+`shoelace check-bytecode`: verifies a compiled program and summarizes what's in it,
+without running it. Meant for looking into programs attached to bug reports.
+-------------------------------------------------------------- */
+
+use crate::listing;
+use hlvm::{
+    dev,
+    lir::{HlvmInstruction, HlvmValue},
+    stream::{self, DecodedInstruction},
+};
+use std::collections::BTreeMap;
+
+/// What `check-bytecode` reports about a program.
+pub struct Report {
+    /// Why the program failed verification, if it did.
+    pub error: Option<String>,
+
+    instructions: usize,
+
+    /// How many times each instruction appears, by name.
+    histogram: BTreeMap<String, usize>,
+
+    /// How many constants of each type are pushed.
+    constants: BTreeMap<&'static str, usize>,
+
+    /// Every function, with where it's pushed and how many instructions it has.
+    functions: Vec<(String, String, usize)>,
+}
+
+pub fn check(instructions: &[HlvmInstruction]) -> Report {
+    let error = hlvm::cfg::check_limits(instructions)
+        .and_then(|_| hlvm::cfg::verify_lir(instructions))
+        .err();
+
+    let decoded: Vec<DecodedInstruction> = stream::instructions(instructions).collect();
+    let mut report = Report {
+        error,
+        instructions: decoded.len(),
+        histogram: BTreeMap::new(),
+        constants: BTreeMap::new(),
+        functions: vec![],
+    };
+
+    for (idx, instruction) in decoded.iter().enumerate() {
+        *report.histogram.entry(name(instruction.instruction)).or_default() += 1;
+
+        if let Some(constant) = instruction.constant {
+            *report.constants.entry(dev::type_name(constant)).or_default() += 1;
+        }
+
+        if let HlvmInstruction::Push(function @ HlvmValue::Function(body, ..)) =
+            instruction.instruction
+        {
+            /* Declarations store the function right after pushing it, which names it */
+            let stored = decoded[idx + 1..]
+                .iter()
+                .find(|next| next.path == instruction.path)
+                .filter(|next| next.address == instruction.address + 1)
+                .and_then(|next| match next.instruction {
+                    HlvmInstruction::SetLocal(name) | HlvmInstruction::SetGlobal(name) => {
+                        Some(format!("{} ", name))
+                    }
+                    _ => None,
+                })
+                .unwrap_or_default();
+
+            report.functions.push((
+                format!("{}{}", stored, listing::function_name(function)),
+                instruction.location(),
+                body.len(),
+            ));
+        }
+    }
+
+    report
+}
+
+/// The name of `instruction` without its operands, like `Push` or `JumpIf`.
+fn name(instruction: &HlvmInstruction) -> String {
+    let text = format!("{:?}", instruction);
+    text.split('(').next().unwrap_or_default().to_string()
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error {
+            Some(err) => writeln!(f, "Verification  failed: {}", err)?,
+            None => writeln!(f, "Verification  ok")?,
+        }
+
+        writeln!(f, "Instructions  {}", self.instructions)?;
+        writeln!(f, "Constants     {}", self.constants.values().sum::<usize>())?;
+        writeln!(f, "Functions     {}", self.functions.len())?;
+
+        writeln!(f, "\nConstants by type")?;
+        for (datatype, count) in &self.constants {
+            writeln!(f, "  {:<16} {}", datatype, count)?;
+        }
+
+        /* The most common instructions first, ties in alphabetical order */
+        let mut histogram: Vec<_> = self.histogram.iter().collect();
+        histogram.sort_by(|a, b| b.1.cmp(a.1));
+
+        writeln!(f, "\nInstructions by kind")?;
+        for (name, count) in histogram {
+            writeln!(f, "  {:<16} {}", name, count)?;
+        }
+
+        writeln!(f, "\nFunctions")?;
+        for (function, location, length) in &self.functions {
+            writeln!(f, "  {}, {} instructions, pushed by {}", function, length, location)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+/* --------------------------------------------------------------
+`shoelace disasm`: prints the instructions of a compiled program.
+If a source map was written next to the program, the source lines are interleaved with them.
+-------------------------------------------------------------- */
+
+use colored::*;
+use crate::listing;
+use hlvm::{
+    lir::{HlvmInstruction, HlvmValue},
+    sourcemap::SourceMap,
+    stream,
+};
+use std::path::{Path, PathBuf};
+
+/// The path of the source map written next to `program`, which is `program` with `.map`
+/// appended to its file name. The name isn't required to be valid UTF-8.
+pub fn map_path(program: &Path) -> PathBuf {
+    let mut path = program.as_os_str().to_owned();
+    path.push(".map");
+    PathBuf::from(path)
+}
+
+/// Reads the source map of `program`, returning `None` if it has none.
+fn read_map(program: &Path) -> Option<SourceMap> {
+    let contents = std::fs::read_to_string(map_path(program)).ok()?;
+
+    match contents.parse() {
+        Ok(map) => Some(map),
+        Err(err) => {
+            println!("{}: {}", "Warning".bright_yellow(), err);
+            None
+        }
+    }
+}
+
+pub fn disassemble(program: &Path, instructions: &[HlvmInstruction]) -> String {
+    let map = read_map(program);
+    let source = map
+        .as_ref()
+        .and_then(|map| std::fs::read_to_string(Path::new(&map.file)).ok());
+    let source_lines: Vec<&str> = source
+        .as_deref()
+        .map(|s| s.lines().collect())
+        .unwrap_or_default();
+
+    let mut output = String::new();
+    let mut decoded = stream::instructions(instructions);
+
+    if let Some(map) = &map {
+        decoded = decoded.with_map(map);
+    }
+
+    let mut last_line = None;
+
+    /* The bodies of functions are listed below the instruction that pushes them, indented */
+    for decoded in decoded {
+        if let Some(line) = decoded.line.filter(|line| last_line != Some(*line)) {
+            let text = source_lines
+                .get(line - 1)
+                .map(|text| text.trim())
+                .unwrap_or("");
+            output.push_str(&format!(
+                "{}\n",
+                format!("{:>5} | {}", line, text).bright_black()
+            ));
+            last_line = Some(line);
+        }
+
+        let text = match decoded.instruction {
+            HlvmInstruction::Push(value @ HlvmValue::Function(..)) => {
+                format!("Push({})", listing::function_name(value))
+            }
+            instruction => format!("{:?}", instruction),
+        };
+
+        output.push_str(&format!(
+            "{}{:05}  {}\n",
+            listing::INDENT.repeat(decoded.depth()),
+            decoded.address,
+            text
+        ));
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_paths_append_to_the_file_name() {
+        let cases = [
+            ("main.o", "main.o.map"),
+            ("main", "main.map"),
+            ("out.d/main", "out.d/main.map"),
+            ("dir with space/プログラム.o", "dir with space/プログラム.o.map"),
+            (r"C:\Users\me\main.o", r"C:\Users\me\main.o.map"),
+            (r"\\server\share\main.o", r"\\server\share\main.o.map"),
+            (r"\\?\C:\ユーザー\main.exe", r"\\?\C:\ユーザー\main.exe.map"),
+        ];
+
+        for (program, expected) in cases {
+            assert_eq!(map_path(Path::new(program)), PathBuf::from(expected));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn map_paths_dont_need_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let program = Path::new(OsStr::from_bytes(b"out/m\xe9in.o"));
+
+        assert_eq!(map_path(program).as_os_str().as_bytes(), b"out/m\xe9in.o.map");
+    }
+}
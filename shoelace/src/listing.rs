@@ -0,0 +1,138 @@
+/* --------------------------------------------------------------
+`shoelace build --emit hir|lir`: readable listings of the instructions a program compiles to.
+The bodies of functions are listed below the instruction that pushes them, indented.
+-------------------------------------------------------------- */
+
+use hlvm::{
+    hir::HlvmHirInstruction,
+    lir::{HlvmInstruction, HlvmValue},
+};
+
+pub const INDENT: &str = "    ";
+
+/// Lists HIR instructions one per line, with the blocks of `if` and `while` statements and
+/// of guarded bodies indented under them.
+pub fn hir(instructions: &[HlvmHirInstruction]) -> String {
+    let mut output = String::new();
+    hir_into(&mut output, instructions, 0);
+    output
+}
+
+fn hir_into(output: &mut String, instructions: &[HlvmHirInstruction], depth: usize) {
+    let indent = INDENT.repeat(depth);
+
+    for instruction in instructions {
+        match instruction {
+            HlvmHirInstruction::Push(value @ HlvmValue::Function(body, ..)) => {
+                output.push_str(&format!("{}Push({})\n", indent, function_name(value)));
+                lir_into(output, body, depth + 1);
+            }
+            HlvmHirInstruction::Asm(block) => {
+                output.push_str(&format!("{}Asm\n", indent));
+                lir_into(output, block, depth + 1);
+            }
+            HlvmHirInstruction::LoadModule(name, _) => {
+                output.push_str(&format!("{}LoadModule({:?})\n", indent, name));
+            }
+            HlvmHirInstruction::IfStatement { ontrue, onelseif, onfalse } => {
+                output.push_str(&format!("{}If\n", indent));
+                hir_into(output, ontrue, depth + 1);
+
+                for (condition, code) in onelseif.iter().flatten() {
+                    output.push_str(&format!("{}ElseIf\n", indent));
+                    hir_into(output, condition, depth + 1);
+                    output.push_str(&format!("{}Then\n", indent));
+                    hir_into(output, code, depth + 1);
+                }
+
+                output.push_str(&format!("{}Else\n", indent));
+                hir_into(output, onfalse, depth + 1);
+                output.push_str(&format!("{}End\n", indent));
+            }
+            HlvmHirInstruction::WhileStatement(condition, body) => {
+                output.push_str(&format!("{}While\n", indent));
+                hir_into(output, condition, depth + 1);
+                output.push_str(&format!("{}Do\n", indent));
+                hir_into(output, body, depth + 1);
+                output.push_str(&format!("{}End\n", indent));
+            }
+            HlvmHirInstruction::Guarded { body, exit } => {
+                output.push_str(&format!("{}Guarded\n", indent));
+                hir_into(output, body, depth + 1);
+                output.push_str(&format!("{}Exit\n", indent));
+                hir_into(output, exit, depth + 1);
+                output.push_str(&format!("{}End\n", indent));
+            }
+            instruction => output.push_str(&format!("{}{:?}\n", indent, instruction)),
+        }
+    }
+}
+
+/// Lists LIR instructions one per line, prefixed with their address.
+/// Instructions that are jumped to are annotated with the addresses of the jumps.
+pub fn lir(instructions: &[HlvmInstruction]) -> String {
+    let mut output = String::new();
+    lir_into(&mut output, instructions, 0);
+    output
+}
+
+fn lir_into(output: &mut String, instructions: &[HlvmInstruction], depth: usize) {
+    let indent = INDENT.repeat(depth);
+    let mut sources: Vec<Vec<usize>> = vec![vec![]; instructions.len() + 1];
+
+    for (idx, instruction) in instructions.iter().enumerate() {
+        if let HlvmInstruction::Jump(addr) | HlvmInstruction::JumpIf(addr) = instruction {
+            if let Some(sources) = sources.get_mut(*addr) {
+                sources.push(idx);
+            }
+        }
+    }
+
+    for (idx, instruction) in instructions.iter().enumerate() {
+        let text = match instruction {
+            HlvmInstruction::Push(value @ HlvmValue::Function(..)) => {
+                format!("Push({})", function_name(value))
+            }
+            instruction => format!("{:?}", instruction),
+        };
+
+        output.push_str(&format!("{}{:05}  {}", indent, idx, text));
+
+        if !sources[idx].is_empty() {
+            output.push_str(&format!("  ; from {}", addresses(&sources[idx])));
+        }
+
+        output.push('\n');
+
+        if let HlvmInstruction::Push(HlvmValue::Function(body, ..)) = instruction {
+            lir_into(output, body, depth + 1);
+        }
+    }
+
+    /* Jumps to the end of the instructions have no instruction to annotate */
+    if let Some(sources) = sources.last().filter(|sources| !sources.is_empty()) {
+        output.push_str(&format!(
+            "{}{:05}  <end>  ; from {}\n",
+            indent,
+            instructions.len(),
+            addresses(sources)
+        ));
+    }
+}
+
+pub fn function_name(function: &HlvmValue) -> String {
+    match function {
+        HlvmValue::Function(_, parameters, .., id) => {
+            format!("<function #{}({})>", id, parameters.join(", "))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn addresses(addresses: &[usize]) -> String {
+    addresses
+        .iter()
+        .map(|addr| format!("{:05}", addr))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
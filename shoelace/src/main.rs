@@ -1,73 +1,730 @@
+mod check;
+mod disasm;
+mod listing;
+mod package;
+#[cfg(test)]
+mod scratch;
+mod stats;
+mod watch;
+
 use colored::*;
-use compiler;
-use hlvm;
-use std::{env, process::exit, time::Instant};
-use std::io::Read;
-use std::io::BufReader;
+use hlvm::{error::ErrorCode, lir::HlvmValue};
+use std::{
+    env,
+    process::exit,
+    time::{Duration, Instant},
+};
+use std::io::{Read, Write};
+use std::io::{BufReader, Seek, SeekFrom};
 use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Marks the end of an executable produced with `--emit exe`.
+/// The serialized program sits right before the payload length and this magic.
+const EMBED_MAGIC: &[u8; 8] = b"LACEEXE\0";
 
 fn error(err: &str) -> ! {
     println!("{}: {}", "Error".red(), err);
-    exit(0)
+    exit(1)
+}
+
+/// The exit code of programs stopped by one of the limits of `run`, like running too long or
+/// using too much memory, so that they can be told apart from programs that failed on their own.
+const WATCHDOG_EXIT_CODE: i32 = 124;
+
+/// Reports an error raised while running a program, then exits.
+fn runtime_error(err: &str) -> ! {
+    let stopped = [
+        ErrorCode::InstructionLimitExceeded,
+        ErrorCode::TimeLimitExceeded,
+        ErrorCode::OutputLimitExceeded,
+        ErrorCode::MemoryLimitExceeded,
+    ]
+    .iter()
+    .any(|code| err.starts_with(&code.message("")));
+
+    println!("{}: {}", "Error".red(), err);
+    exit(if stopped { WATCHDOG_EXIT_CODE } else { 1 })
+}
+
+/// The value after flag `name` with its unit, like `--timeout 5s`, if the flag is passed.
+/// Returns the number multiplied by the unit's factor from `units`, and `default` is the
+/// factor of a number without a unit.
+fn unit_flag(args: &[String], name: &str, units: &[(&str, f64)], default: f64) -> Option<f64> {
+    let idx = args.iter().position(|arg| arg == name)?;
+    let value = args.get(idx + 1).map(String::as_str).unwrap_or_default();
+
+    let split = value.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let factor = match unit {
+        "" => Some(default),
+        unit => units
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+            .map(|(_, factor)| *factor),
+    };
+
+    match (number.parse::<f64>(), factor) {
+        (Ok(number), Some(factor)) if number >= 0.0 => Some(number * factor),
+        _ => {
+            let units = units.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ");
+            error(&format!("Expected a number after '{}', optionally followed by {}.", name, units))
+        }
+    }
+}
+
+/// The source file to build for `path`, which is the entry point of the package if `path` is
+/// a directory, like `.`.
+fn entry_point(path: &str) -> String {
+    if !Path::new(path).is_dir() {
+        return path.to_string();
+    }
+
+    let main = Path::new(path).join(package::PACKAGE_MAIN);
+
+    if !main.is_file() {
+        error(&format!(
+            "'{}' is a directory, but it has no {} to build.",
+            path,
+            package::PACKAGE_MAIN
+        ))
+    }
+
+    main.to_string_lossy().into_owned()
+}
+
+/// Removes everything from the first `--` on from `args`, returning what followed it.
+/// Those are the arguments of the program being ran, passed on exactly as they were given,
+/// including any later `--`.
+fn program_arguments(args: &mut Vec<String>) -> Vec<String> {
+    match args.iter().position(|arg| arg == "--") {
+        Some(idx) => args.split_off(idx).into_iter().skip(1).collect(),
+        None => vec![],
+    }
+}
+
+/// The number after flag `name`, like `--max-call-depth 100`, if the flag is passed.
+fn numeric_flag(args: &[String], name: &str) -> Option<u64> {
+    let idx = args.iter().position(|arg| arg == name)?;
+
+    match args.get(idx + 1).and_then(|value| value.parse().ok()) {
+        Some(value) => Some(value),
+        None => error(&format!("Expected a number after '{}'.", name)),
+    }
+}
+
+/// The flags of `run` that take a value. `build --run` accepts them too.
+const RUN_OPTIONS: [&str; 6] = [
+    "--stack-capacity",
+    "--max-call-depth",
+    "--max-instructions",
+    "--timeout",
+    "--max-output",
+    "--max-memory",
+];
+
+/// The limits a program is ran with, as given by the flags in `args`.
+fn vm_options(args: &[String]) -> hlvm::vm::VmOptions {
+    let mut options = hlvm::vm::VmOptions::default();
+
+    if let Some(capacity) = numeric_flag(args, "--stack-capacity") {
+        options = options.stack_capacity(capacity as usize);
+    }
+
+    if let Some(depth) = numeric_flag(args, "--max-call-depth") {
+        options = options.max_call_depth(depth as usize);
+    }
+
+    if let Some(instructions) = numeric_flag(args, "--max-instructions") {
+        options = options.max_instructions(instructions);
+    }
+
+    let units = [("ms", 0.001), ("s", 1.0), ("m", 60.0)];
+    if let Some(seconds) = unit_flag(args, "--timeout", &units, 1.0) {
+        options = options.timeout(Duration::from_secs_f64(seconds));
+    }
+
+    let units = [("B", 1.0), ("KB", 1024.0), ("MB", 1024.0 * 1024.0)];
+    if let Some(bytes) = unit_flag(args, "--max-output", &units, 1.0) {
+        options = options.max_output(bytes as usize);
+    }
+
+    if let Some(bytes) = unit_flag(args, "--max-memory", &units, 1.0) {
+        options = options.max_memory(bytes as usize);
+    }
+
+    options
+}
+
+/// Runs the top-level statements of a program, then calls its `main` function if it declares one.
+/// Returns the program's exit code, which is the return value of `main`.
+fn execute(
+    instructions: &[hlvm::lir::HlvmInstruction],
+    arguments: Vec<String>,
+    options: hlvm::vm::VmOptions,
+) -> i32 {
+    let mut executor = hlvm::vm::HighLevelVirtualMachine::with_options(Some(1), options);
+    executor.arguments = arguments;
+
+    if let Err(err) = executor.execute(instructions) {
+        runtime_error(&err)
+    }
+
+    let main = executor.call_stack[0].locals.get("main");
+
+    match main {
+        Some(HlvmValue::Function(..)) => match executor.call("main", vec![]) {
+            Ok(HlvmValue::Number(code)) => code as i32,
+            Ok(_) => 0,
+            Err(err) => runtime_error(&err),
+        },
+        _ => 0,
+    }
+}
+
+/// Returns the program embedded in the running executable, if there is one.
+fn embedded_program() -> Option<Vec<hlvm::lir::HlvmInstruction>> {
+    let mut file = File::open(env::current_exe().ok()?).ok()?;
+    let mut trailer = [0u8; 16];
+
+    file.seek(SeekFrom::End(-16)).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+
+    if &trailer[8..] != EMBED_MAGIC {
+        return None;
+    }
+
+    /* A damaged trailer can claim any length, so it's checked before anything is allocated */
+    let length = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+    let size = file.metadata().ok()?.len();
+
+    if length > size.saturating_sub(16) {
+        error("The program embedded in this executable is damaged.")
+    }
+
+    let mut buffer = vec![0u8; length as usize];
+
+    file.seek(SeekFrom::End(-16 - length as i64)).ok()?;
+    file.read_exact(&mut buffer).ok()?;
+
+    Some(hlvm::object::decode(&buffer).unwrap_or_else(|err| error(&err)))
+}
+
+/// Writes a copy of this executable with `program` appended to it, so that it runs the program
+/// on startup instead of acting as the CLI.
+fn emit_exe(program: &[u8], path: &Path) {
+    let current = env::current_exe().expect("Unable to locate the shoelace executable");
+    let mut contents = std::fs::read(current).expect("Unable to read the shoelace executable");
+
+    contents.extend_from_slice(program);
+    contents.extend_from_slice(&(program.len() as u64).to_le_bytes());
+    contents.extend_from_slice(EMBED_MAGIC);
+
+    std::fs::write(path, contents).expect("Unable to write file");
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+            .expect("Unable to mark executable");
+    }
+}
+
+/// Where an executable asked to be written to `output` ends up. Windows only runs files with an
+/// extension, so one without gets `.exe`; a directory with a dot in its name doesn't count.
+fn executable_path(mut output: PathBuf, windows: bool) -> PathBuf {
+    if windows && output.extension().is_none() {
+        output.set_extension("exe");
+    }
+
+    output
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    if let Some(instructions) = embedded_program() {
+        let code = execute(
+            &instructions,
+            env::args().skip(1).collect(),
+            hlvm::vm::VmOptions::default(),
+        );
+        exit(code);
+    }
+
+    let mut args: Vec<String> = env::args().collect();
+    let program_args = program_arguments(&mut args);
+
+    let mut color = compiler::error::ColorChoice::Auto;
+
+    if let Some(idx) = args.iter().position(|arg| arg.starts_with("--color=")) {
+        color = match args.remove(idx)["--color=".len()..].parse() {
+            Ok(choice) => choice,
+            Err(err) => error(&err),
+        };
+    }
+
+    compiler::error::set_color_choice(color);
+
+    if let Some(idx) = args.iter().position(|arg| arg.starts_with("--message-format=")) {
+        match args.remove(idx)["--message-format=".len()..].parse() {
+            Ok(format) => compiler::error::set_message_format(format),
+            Err(err) => error(&err),
+        }
+    }
 
     if args.len() == 1 {
         error("Expected command name.")
     }
 
+    if args[1] == "--version" || args[1] == "-V" {
+        println!(
+            "shoelace {} ({})",
+            env!("CARGO_PKG_VERSION"),
+            env!("SHOELACE_GIT_HASH")
+        );
+        println!("program format {}", hlvm::object::VERSION);
+        return;
+    }
+
     match args[1].as_str() {
         "build" => {
             if args.len() == 2 {
                 error("Expected source file.")
             }
 
-            let source = &args[2];
+            let source = &entry_point(&args[2]);
 
-            let contents =
-                std::fs::read_to_string(source).expect("Something went wrong reading the file.");
+            /* The first argument after the source that isn't an option names the output */
+            let mut positional = None;
+            let mut rest = args[3..].iter();
 
-            let ast = compiler::pipeline::lace_pipeline_init(&contents);
-            let mut typechecker = compiler::typecheck::Typechecker::new();
-            typechecker.check(ast.clone());
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--emit" | "--opt-level" | "-o" => {
+                        rest.next();
+                    }
+                    option if RUN_OPTIONS.contains(&option) => {
+                        rest.next();
+                    }
+                    "--stats" | "--no-prelude" | "--warn-shadowing" | "--verify" | "--run"
+                    | "--deterministic" => {}
+                    _ if arg.starts_with('-') && arg != "-" => {
+                        error(&format!("Unknown option '{}' for build.", arg))
+                    }
+                    _ if positional.is_none() => positional = Some(arg),
+                    _ => error(&format!(
+                        "Unexpected argument '{}'. Build takes a source file and an output path.",
+                        arg
+                    )),
+                }
+            }
+
+            let run = args.iter().any(|arg| arg == "--run");
+
+            /* The options of `run` only mean something for a program that's ran */
+            if !run {
+                let mut limits = RUN_OPTIONS.iter().chain(&["--deterministic"]);
+
+                if let Some(option) = limits.find(|option| args.iter().any(|arg| arg == *option)) {
+                    error(&format!("'{}' only applies to programs built with '--run'.", option))
+                }
+            }
+
+            let emit = match args.iter().position(|arg| arg == "--emit") {
+                Some(idx) => match args.get(idx + 1).map(String::as_str) {
+                    Some(kind @ ("obj" | "exe" | "hir" | "lir")) => kind,
+                    _ => error("Expected 'obj', 'exe', 'hir' or 'lir' after '--emit'."),
+                },
+                None => "obj",
+            };
+
+            /* `s` optimizes for size: unused prelude functions and the source map are left out */
+            let optimize_size = match args.iter().position(|arg| arg == "--opt-level") {
+                Some(idx) => match args.get(idx + 1).map(String::as_str) {
+                    Some("0") => false,
+                    Some("s") => true,
+                    _ => error("Expected '0' or 's' after '--opt-level'."),
+                },
+                None => false,
+            };
+
+            if run && (emit == "hir" || emit == "lir") {
+                error("Listings can't be run, use '--emit obj' or '--emit exe' with '--run'.")
+            }
+
+            /* `-` reads the source from stdin, which belongs to the current directory */
+            let contents = if source == "-" {
+                let mut contents = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut contents)
+                    .expect("Something went wrong reading stdin.");
+                contents
+            } else {
+                std::fs::read_to_string(source).expect("Something went wrong reading the file.")
+            };
+
+            let project = Path::new(source)
+                .parent()
+                .filter(|_| source != "-")
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+
+            let packages = match package::resolve(project) {
+                Ok(resolved) => {
+                    if !resolved.is_empty() {
+                        if let Err(err) = package::write_lockfile(project, &resolved) {
+                            error(&err)
+                        }
+                    }
+
+                    resolved
+                }
+                Err(err) => error(&err),
+            };
+
+            let show_stats = args.iter().any(|arg| arg == "--stats");
+            let mut stats = stats::Stats::default();
+
+            if show_stats {
+                stats.scan(&contents);
+            }
+
+            let mut ast = stats.time("parse", || compiler::pipeline::lace_pipeline_init(&contents));
+            let program_len = ast.len();
+            stats.count_nodes(&ast);
 
-            let hir_instructions = compiler::codegen::compile(ast);
-            let lir_instructions = hlvm::hir::from_hir(hir_instructions);
+            if !args.iter().any(|arg| arg == "--no-prelude") {
+                ast = stats.time("prelude", || compiler::prelude::link(ast));
+            }
+
+            let mut resolver = compiler::scope::Resolver::new();
+            resolver.warn_shadowing = args.iter().any(|arg| arg == "--warn-shadowing");
+            let mut ast = stats.time("resolve", || resolver.resolve(ast));
+            let symbols = resolver.symbols;
+
+            let mut typechecker = compiler::typecheck::Typechecker::new(&symbols);
+            if let Err(diagnostics) = stats.time("typecheck", || typechecker.check(&ast)) {
+                for diagnostic in &diagnostics {
+                    diagnostic.emit();
+                }
+
+                exit(1);
+            }
+
+            /* Only the program itself is mapped, the prelude's lines belong to another file */
+            let program = ast.split_off(ast.len() - program_len);
+
+            if optimize_size {
+                ast = stats.time("optimize", || {
+                    compiler::optimizer::remove_unused_functions(ast, &program)
+                });
+            }
+
+            /* Imports are relative to the source file, or to the current directory for stdin */
+            let importer = if source == "-" { Path::new("") } else { Path::new(source) };
+            let hir_instructions = stats.time("codegen", || {
+                let mut context = compiler::codegen::Context::new();
+                context.packages = packages
+                    .into_iter()
+                    .map(|(name, dir)| (name, dir.join(package::PACKAGE_MAIN)))
+                    .collect();
+
+                let mut hir_instructions = compiler::codegen::compile(ast, &symbols, &mut context);
+                hir_instructions.append(&mut compiler::import::within(importer, || {
+                    compiler::codegen::compile_mapped(program, &symbols, &mut context)
+                }));
+                hir_instructions
+            });
+
+            /* HIR is consumed by lowering, so its listing has to be made first */
+            let hir_listing = (emit == "hir").then(|| listing::hir(&hir_instructions));
+            let (lir_instructions, lines) =
+                stats.time("lower", || hlvm::hir::from_hir_mapped(hir_instructions));
+            stats.count_instructions(&lir_instructions);
+            let map_file = if source == "-" { "<stdin>" } else { source };
+            let map = hlvm::sourcemap::SourceMap::new(map_file.to_string(), lines);
+
+            if args.iter().any(|arg| arg == "--verify") {
+                if let Err(err) = stats.time("verify", || hlvm::cfg::verify_lir(&lir_instructions)) {
+                    error(&format!("Generated invalid instructions. {}", err));
+                }
+            }
+
+            let mut output = match (args.iter().position(|arg| arg == "-o"), positional) {
+                (Some(_), Some(_)) => error("The output path was given both with '-o' and after \
+                                             the source file, expected only one."),
+                (Some(idx), None) => match args.get(idx + 1) {
+                    Some(path) => PathBuf::from(path),
+                    None => error("Expected a path after '-o'."),
+                },
+                (None, Some(path)) => PathBuf::from(path),
+                (None, None) if emit == "exe" => PathBuf::from("main"),
+                (None, None) if emit == "obj" => PathBuf::from("main.o"),
+                (None, None) => PathBuf::from(format!("main.{}", emit)),
+            };
+
+            /* Listings are written instead of the program. `-o -` writes them to stdout */
+            if emit == "hir" || emit == "lir" {
+                let listing = hir_listing.unwrap_or_else(|| listing::lir(&lir_instructions));
+
+                if output == Path::new("-") {
+                    print!("{}", listing);
+                } else {
+                    std::fs::write(&output, listing).expect("Unable to write file");
+                }
+
+                if show_stats {
+                    eprint!("{}", stats);
+                }
+
+                return;
+            }
+
+            if let Err(err) = hlvm::cfg::check_limits(&lir_instructions) {
+                error(&format!("The program is too large. {}", err));
+            }
+
+            let program = stats.time("serialize", || hlvm::object::encode(&lir_instructions));
+
+            /* Stats go to stderr, so they can't end up in a program written to stdout */
+            if show_stats {
+                eprint!("{}", stats);
+            }
+
+            if emit == "exe" {
+                output = executable_path(output, cfg!(windows));
+            }
 
-            println!("{:?}", lir_instructions);
+            /* `-o -` writes the program to stdout, without a source map */
+            if output == Path::new("-") {
+                if emit == "exe" {
+                    error("Executables can't be written to stdout, use '--emit obj'.")
+                }
 
-            std::fs::write("./main.o", bincode::serialize(&lir_instructions).unwrap())
-                .expect("Unable to write file");
+                if run {
+                    error("Programs written to stdout can't be run, pipe them to 'shoelace run -'.")
+                }
+
+                std::io::stdout()
+                    .write_all(&program)
+                    .expect("Unable to write to stdout");
+                return;
+            }
+
+            if emit == "exe" {
+                emit_exe(&program, &output);
+            } else {
+                std::fs::write(&output, program).expect("Unable to write file");
+            }
+
+            if !optimize_size {
+                std::fs::write(disasm::map_path(&output), map.to_string())
+                    .expect("Unable to write source map");
+            }
+
+            /* `--run` runs what was just built, as `run` would run the file it was written to */
+            if run {
+                exit(execute(&lir_instructions, program_args, vm_options(&args)));
+            }
         }
         "run" => {
             if args.len() == 2 {
                 error("Expected source file.")
             }
 
-            let source = &args[2];
+            let mut source = args[2].clone();
+
+            /* A directory is built first, into `main.o` next to its entry point */
+            if Path::new(&source).is_dir() {
+                let entry = entry_point(&source);
+                let program = Path::new(&source).join("main.o").to_string_lossy().into_owned();
+
+                let exe = env::current_exe().expect("Unable to locate the shoelace executable");
+                let built = std::process::Command::new(exe)
+                    .args(["build", &entry, "-o", &program])
+                    .status()
+                    .map(|status| status.success())
+                    .unwrap_or(false);
+
+                if !built {
+                    exit(1);
+                }
+
+                source = program;
+            }
 
-            let f = File::open(source)
-                .expect("Could not open file");
-            let mut reader = BufReader::new(f);
             let mut buffer = Vec::new();
 
-            reader.read_to_end(&mut buffer)
-                .expect("Something went wrong while reading the file");
+            /* `-` reads the program from stdin, so `build - -o -` can be piped into it */
+            if source == "-" {
+                std::io::stdin()
+                    .read_to_end(&mut buffer)
+                    .expect("Something went wrong while reading stdin");
+            } else {
+                let f = File::open(source)
+                    .expect("Could not open file");
+                let mut reader = BufReader::new(f);
+
+                reader.read_to_end(&mut buffer)
+                    .expect("Something went wrong while reading the file");
+            }
 
-            let instructions = bincode::deserialize::<Vec<hlvm::lir::HlvmInstruction>>(&buffer)
-                .expect("Unable to deserialize instructions");
+            let instructions = hlvm::object::decode(&buffer).unwrap_or_else(|err| error(&err));
 
-            let mut executor = hlvm::vm::HighLevelVirtualMachine::new(Some(1));
+            let options = vm_options(&args);
 
             let start = Instant::now();
-            executor.execute(&instructions).expect("An error occured");
+            let code = execute(&instructions, program_args, options);
             let end = start.elapsed();
 
-            println!("{:#?}", executor.call_stack);
-            println!("Execution took {:.2?}", end);
+            /* The timing differs between runs, so it's left out when output is compared */
+            if !args.iter().any(|arg| arg == "--deterministic") {
+                println!("Execution took {:.2?}", end);
+            }
+
+            exit(code);
+        }
+        "disasm" => {
+            if args.len() == 2 {
+                error("Expected program file.")
+            }
+
+            let buffer = std::fs::read(&args[2]).expect("Could not open file");
+            let instructions = hlvm::object::decode(&buffer).unwrap_or_else(|err| error(&err));
+
+            print!("{}", disasm::disassemble(Path::new(&args[2]), &instructions));
+        }
+        "check-bytecode" => {
+            if args.len() == 2 {
+                error("Expected program file.")
+            }
+
+            let buffer = std::fs::read(&args[2]).expect("Could not open file");
+            let instructions = hlvm::object::decode(&buffer).unwrap_or_else(|err| error(&err));
+            let report = check::check(&instructions);
+
+            print!("{}", report);
+
+            if report.error.is_some() {
+                exit(1);
+            }
+        }
+        "watch" => {
+            if args.len() == 2 {
+                error("Expected source file.")
+            }
+
+            watch::watch(&entry_point(&args[2]), &args[3..], &program_args);
+        }
+        "doc" => {
+            if args.len() == 2 {
+                error("Expected source file.")
+            }
+
+            let source = &args[2];
+            let html = args.iter().any(|arg| arg == "--html");
+
+            let contents =
+                std::fs::read_to_string(source).expect("Something went wrong reading the file.");
+            let ast = compiler::pipeline::lace_pipeline_init(&contents);
+
+            let title = Path::new(source)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| source.to_string());
+
+            let (output, path) = if html {
+                (compiler::doc::html(&title, &ast), "./doc.html")
+            } else {
+                (compiler::doc::markdown(&title, &ast), "./doc.md")
+            };
+
+            std::fs::write(path, output).expect("Unable to write file");
+        }
+        "explain" => {
+            if args.len() == 2 {
+                error("Expected error code.")
+            }
+
+            match args[2].parse::<compiler::error::ErrorCode>() {
+                Ok(code) => println!("{}\n\n{}", code, code.explain()),
+                Err(err) => error(&err),
+            }
+        }
+        "add" => {
+            if args.len() < 5 || args[3] != "--path" {
+                error("Expected 'add <name> --path <directory>'.")
+            }
+
+            if let Err(err) = package::add(Path::new("."), &args[2], &args[4]) {
+                error(&err)
+            }
         }
         _ => error("Command not found."),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn program_arguments_follow_the_first_separator() {
+        let mut args = strings(&["shoelace", "run", "main.o", "--", "a b", "--", "-v", ""]);
+
+        assert_eq!(program_arguments(&mut args), strings(&["a b", "--", "-v", ""]));
+        assert_eq!(args, strings(&["shoelace", "run", "main.o"]));
+    }
+
+    #[test]
+    fn program_arguments_are_kept_as_given() {
+        let given = ["\"quoted\"", "it's", "tab\tand\nline", "ünïcødé", "日本語", "🦀"];
+        let mut args = strings(&["shoelace", "build", "main.lc", "--run", "--"]);
+        args.extend(strings(&given));
+
+        assert_eq!(program_arguments(&mut args), strings(&given));
+    }
+
+    #[test]
+    fn executables_get_an_extension_on_windows() {
+        let cases = [
+            ("main", "main.exe"),
+            ("out.d/main", "out.d/main.exe"),
+            ("main.bin", "main.bin"),
+            ("dir with space/プログラム", "dir with space/プログラム.exe"),
+        ];
+
+        for (output, expected) in cases {
+            assert_eq!(executable_path(PathBuf::from(output), true), PathBuf::from(expected));
+            assert_eq!(executable_path(PathBuf::from(output), false), PathBuf::from(output));
+        }
+    }
+
+    /* Backslashes and prefixes are only separators when Path parses them the Windows way */
+    #[cfg(windows)]
+    #[test]
+    fn windows_paths_get_an_extension() {
+        let cases = [
+            (r"C:\out.d\main", r"C:\out.d\main.exe"),
+            (r"\\server\share\main", r"\\server\share\main.exe"),
+            (r"\\?\C:\ユーザー\main", r"\\?\C:\ユーザー\main.exe"),
+            (r"C:\out\main.exe", r"C:\out\main.exe"),
+        ];
+
+        for (output, expected) in cases {
+            assert_eq!(executable_path(PathBuf::from(output), true), PathBuf::from(expected));
+        }
+    }
+
+    #[test]
+    fn no_separator_means_no_program_arguments() {
+        let mut args = strings(&["shoelace", "run", "main.o", "-"]);
+
+        assert_eq!(program_arguments(&mut args), Vec::<String>::new());
+        assert_eq!(args.len(), 4);
+    }
+}
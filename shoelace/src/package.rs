@@ -0,0 +1,271 @@
+/* --------------------------------------------------------------
+Dependency management: reading `lace.toml`, adding dependencies and writing `lace.lock`.
+Only path dependencies are supported for now.
+-------------------------------------------------------------- */
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST: &str = "lace.toml";
+pub const LOCKFILE: &str = "lace.lock";
+
+/// The entry point every package must contain.
+pub const PACKAGE_MAIN: &str = "main.lc";
+
+/// A dependency declared in the `[dependencies]` section of a manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    pub name: String,
+    pub path: String,
+}
+
+/// Writes `value` as a TOML basic string, escaping the backslashes and quotes in it.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads a TOML basic string written by `quote`, or `None` if `value` isn't one.
+fn unquote(value: &str) -> Option<String> {
+    let mut chars = value.trim().strip_prefix('"')?.strip_suffix('"')?.chars();
+    let mut unquoted = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                escaped @ ('\\' | '"') => unquoted.push(escaped),
+                _ => return None,
+            },
+            '"' => return None,
+            c => unquoted.push(c),
+        }
+    }
+
+    Some(unquoted)
+}
+
+/// Parses a `name = { path = "..." }` line.
+fn parse_dependency(line: &str) -> Result<Dependency, String> {
+    let (name, value) = line
+        .split_once('=')
+        .ok_or(format!("Invalid dependency '{}'", line))?;
+
+    let path = value
+        .trim()
+        .strip_prefix('{')
+        .and_then(|table| table.strip_suffix('}'))
+        .and_then(|table| table.split_once('='))
+        .filter(|(key, _)| key.trim() == "path")
+        .and_then(|(_, path)| unquote(path))
+        .ok_or(format!("Dependency '{}' must be written as {{ path = \"...\" }}", name.trim()))?;
+
+    Ok(Dependency {
+        name: name.trim().to_string(),
+        path,
+    })
+}
+
+/// Reads the dependencies declared in the manifest in `dir`.
+/// A missing manifest means the package has no dependencies.
+pub fn dependencies(dir: &Path) -> Result<Vec<Dependency>, String> {
+    let manifest = match std::fs::read_to_string(dir.join(MANIFEST)) {
+        Ok(manifest) => manifest,
+        Err(_) => return Ok(vec![]),
+    };
+
+    let mut dependencies = vec![];
+    let mut in_dependencies = false;
+
+    for line in manifest.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            in_dependencies = line == "[dependencies]";
+        } else if in_dependencies {
+            dependencies.push(parse_dependency(line)?);
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// Adds a path dependency to the manifest in `dir`, creating the manifest if needed.
+pub fn add(dir: &Path, name: &str, path: &str) -> Result<(), String> {
+    if dependencies(dir)?.iter().any(|dep| dep.name == name) {
+        return Err(format!("Dependency '{}' already exists", name));
+    }
+
+    let manifest_path = dir.join(MANIFEST);
+    let manifest = std::fs::read_to_string(&manifest_path).unwrap_or_default();
+    let entry = format!("{} = {{ path = {} }}", name, quote(path));
+
+    let mut lines: Vec<String> = manifest.lines().map(String::from).collect();
+
+    match lines.iter().position(|line| line.trim() == "[dependencies]") {
+        Some(idx) => lines.insert(idx + 1, entry),
+        None => {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+
+            lines.push("[dependencies]".to_string());
+            lines.push(entry);
+        }
+    }
+
+    std::fs::write(manifest_path, lines.join("\n") + "\n")
+        .map_err(|err| format!("Unable to write {}: {}", MANIFEST, err))
+}
+
+/// Resolves the dependencies of the package in `dir`, including transitive ones.
+/// Returns a map from package name to the directory containing the package.
+pub fn resolve(dir: &Path) -> Result<BTreeMap<String, PathBuf>, String> {
+    let mut resolved = BTreeMap::new();
+    resolve_into(dir, &mut resolved)?;
+    Ok(resolved)
+}
+
+fn resolve_into(dir: &Path, resolved: &mut BTreeMap<String, PathBuf>) -> Result<(), String> {
+    for dependency in dependencies(dir)? {
+        let path = dir
+            .join(&dependency.path)
+            .canonicalize()
+            .map_err(|_| format!("Dependency '{}' not found at '{}'", dependency.name, dependency.path))?;
+
+        if !path.join(PACKAGE_MAIN).is_file() {
+            return Err(format!(
+                "Dependency '{}' has no {} in '{}'",
+                dependency.name, PACKAGE_MAIN, dependency.path
+            ));
+        }
+
+        match resolved.get(&dependency.name) {
+            Some(existing) if *existing == path => continue,
+            Some(existing) => {
+                return Err(format!(
+                    "Dependency '{}' resolves to both '{}' and '{}'",
+                    dependency.name,
+                    existing.display(),
+                    path.display()
+                ))
+            }
+            None => {
+                resolved.insert(dependency.name, path.clone());
+                resolve_into(&path, resolved)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the resolved packages to the lockfile in `dir`.
+pub fn write_lockfile(dir: &Path, resolved: &BTreeMap<String, PathBuf>) -> Result<(), String> {
+    let mut lockfile = String::from("# This file is generated by shoelace. Do not edit it by hand.\n");
+
+    for (name, path) in resolved {
+        lockfile.push_str(&format!(
+            "\n[[package]]\nname = {}\npath = {}\n",
+            quote(name),
+            quote(&path.display().to_string())
+        ));
+    }
+
+    std::fs::write(dir.join(LOCKFILE), lockfile)
+        .map_err(|err| format!("Unable to write {}: {}", LOCKFILE, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    /// Writes a package in `dir` depending on `dependencies`, given as `(name, path)` pairs.
+    fn package(dir: &Path, dependencies: &[(&str, &str)]) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(PACKAGE_MAIN), "").unwrap();
+
+        for (name, path) in dependencies {
+            add(dir, name, path).unwrap();
+        }
+    }
+
+    #[test]
+    fn dependencies_are_parsed() {
+        let parsed = parse_dependency(r#"util = { path = "../util" }"#).unwrap();
+
+        assert_eq!(parsed, Dependency { name: "util".to_string(), path: "../util".to_string() });
+    }
+
+    #[test]
+    fn malformed_dependencies_are_errors() {
+        let malformed = [
+            "util",
+            r#"util = "../util""#,
+            r#"util = { git = "../util" }"#,
+            r#"util = { path = ../util }"#,
+            r#"util = { path = "../util }"#,
+            r#"util = { path = "../util"#,
+            r#"util = { path = "a"b" }"#,
+            r#"util = { path = "a\b" }"#,
+            r#"util = { path = "a\" }"#,
+        ];
+
+        for line in malformed {
+            assert!(parse_dependency(line).is_err(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn quoted_paths_are_read_back_as_written() {
+        for path in [r"C:\packages\util", r#"say "hi""#, r"trailing\", "ünïcødé/日本語"] {
+            let line = format!("util = {{ path = {} }}", quote(path));
+
+            assert_eq!(parse_dependency(&line).unwrap().path, path);
+        }
+    }
+
+    #[test]
+    fn transitive_dependencies_are_resolved() {
+        let dir = Scratch::new("package-transitive");
+        package(&dir.join("app"), &[("util", "../util")]);
+        package(&dir.join("util"), &[("text", "../text")]);
+        package(&dir.join("text"), &[]);
+
+        let resolved = resolve(&dir.join("app"));
+        let expected = ["text", "util"].map(|name| {
+            (name.to_string(), dir.join(name).canonicalize().unwrap())
+        });
+
+        assert_eq!(resolved, Ok(BTreeMap::from(expected)));
+    }
+
+    #[test]
+    fn conflicting_transitive_dependencies_are_errors() {
+        let dir = Scratch::new("package-conflict");
+        package(&dir.join("app"), &[("util", "../util"), ("text", "../text")]);
+        package(&dir.join("util"), &[("text", "../other")]);
+        package(&dir.join("text"), &[]);
+        package(&dir.join("other"), &[]);
+
+        let resolved = resolve(&dir.join("app"));
+
+        let err = resolved.unwrap_err();
+        assert!(err.starts_with("Dependency 'text' resolves to both"), "{}", err);
+    }
+
+    #[test]
+    fn dependency_cycles_are_resolved_once() {
+        let dir = Scratch::new("package-cycle");
+        package(&dir.join("a"), &[("b", "../b")]);
+        package(&dir.join("b"), &[("a", "../a")]);
+
+        let resolved = resolve(&dir.join("a"));
+        let expected = ["a", "b"].map(|name| {
+            (name.to_string(), dir.join(name).canonicalize().unwrap())
+        });
+
+        assert_eq!(resolved, Ok(BTreeMap::from(expected)));
+    }
+}
@@ -0,0 +1,36 @@
+/* --------------------------------------------------------------
+A temporary directory for the unit tests, shared by every module that touches the filesystem.
+It's removed when it's dropped, so a failing test doesn't leave it behind.
+-------------------------------------------------------------- */
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory for a single test, removed when it's dropped.
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    /// `name` keeps the directories of tests running at the same time apart.
+    pub fn new(name: &str) -> Scratch {
+        let name = format!("shoelace-{}-{}", std::process::id(), name);
+        let dir = std::env::temp_dir().join(name);
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("Unable to create a scratch directory");
+        Scratch(dir)
+    }
+}
+
+impl Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,85 @@
+/* --------------------------------------------------------------
+`shoelace build --stats`: reports how large a program is at each stage of compilation,
+and how long each pass took. Nothing is sent anywhere, the report is only printed.
+-------------------------------------------------------------- */
+
+use compiler::{
+    parser::{Node, NodeValue},
+    scanner::Token,
+    visitor::{walk_value, Visitor},
+};
+use hlvm::{lir::HlvmInstruction, stream};
+use logos::Logos;
+use std::{
+    fmt::{Display, Formatter},
+    time::{Duration, Instant},
+};
+
+#[derive(Default)]
+pub struct Stats {
+    pub tokens: usize,
+    pub nodes: usize,
+    pub instructions: usize,
+    pub constants: usize,
+
+    /// How long each pass took, in the order they ran.
+    pub passes: Vec<(&'static str, Duration)>,
+}
+
+impl Stats {
+    /// Runs `pass`, recording how long it took as `name`.
+    pub fn time<T>(&mut self, name: &'static str, pass: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = pass();
+        self.passes.push((name, start.elapsed()));
+        result
+    }
+
+    /// Counts the tokens of `source`. The parser scans as it goes, so this scans it once more
+    /// on its own to time scanning apart from parsing.
+    pub fn scan(&mut self, source: &str) {
+        self.tokens = self.time("scan", || Token::lexer(source).count());
+    }
+
+    pub fn count_nodes(&mut self, ast: &[Node]) {
+        let mut counter = NodeCounter(0);
+        counter.visit_block(ast);
+        self.nodes = counter.0;
+    }
+
+    /// Counts `instructions`, including those of the functions they push.
+    /// Every other value that's pushed is counted as a constant.
+    pub fn count_instructions(&mut self, instructions: &[HlvmInstruction]) {
+        for decoded in stream::instructions(instructions) {
+            self.instructions += 1;
+            self.constants += decoded.constant.is_some() as usize;
+        }
+    }
+}
+
+struct NodeCounter(usize);
+
+impl Visitor for NodeCounter {
+    fn visit_value(&mut self, value: &NodeValue) {
+        self.0 += 1;
+        walk_value(self, value)
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Tokens        {}", self.tokens)?;
+        writeln!(f, "AST nodes     {}", self.nodes)?;
+        writeln!(f, "Instructions  {}", self.instructions)?;
+        writeln!(f, "Constants     {}", self.constants)?;
+        writeln!(f)?;
+
+        /* Parsing scans the source again, so its time includes scanning */
+        for (name, duration) in &self.passes {
+            writeln!(f, "{:<13} {:.2?}", name, duration)?;
+        }
+
+        let total: Duration = self.passes.iter().map(|(_, duration)| *duration).sum();
+        writeln!(f, "{:<13} {:.2?}", "total", total)
+    }
+}
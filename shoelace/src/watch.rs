@@ -0,0 +1,210 @@
+/* --------------------------------------------------------------
+`shoelace watch`: rebuilds and reruns a program whenever it or one of its imports changes.
+Builds and runs happen in child processes, so errors never take the watcher down with them.
+-------------------------------------------------------------- */
+
+use crate::package;
+use colored::*;
+use compiler::scanner::{string_value, Token};
+use logos::Logos;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long files must stay unchanged before a rebuild starts, so that editors which
+/// write a file in several steps only trigger one rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// The files that `file` imports through `use`, found the way `import::load` finds them:
+/// a name in `packages` is that package, anything else is relative to `file`.
+fn imports(file: &Path, packages: &BTreeMap<String, PathBuf>) -> Vec<PathBuf> {
+    let dir = file.parent().unwrap_or(Path::new("."));
+    let mut imports = vec![];
+
+    if let Ok(contents) = std::fs::read_to_string(file) {
+        let mut tokens = Token::lexer(&contents);
+
+        while let Some(token) = tokens.next() {
+            if token == Token::KwUse {
+                if let Some(Token::String(path)) = tokens.next() {
+                    let path = string_value(path);
+
+                    imports.push(match packages.get(&path) {
+                        Some(package) => package.join(package::PACKAGE_MAIN),
+                        None => dir.join(&path),
+                    });
+                }
+            }
+        }
+    }
+
+    imports
+}
+
+/// Returns `source` along with every file it imports, directly or through other imports,
+/// and the manifest naming its packages.
+fn watched_files(source: &Path) -> Vec<PathBuf> {
+    let project = source
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    /* A broken manifest is reported by `build`, the files that can be found are still watched */
+    let packages = package::resolve(project).unwrap_or_default();
+    let canonical = |file: &Path| file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+
+    let mut files = vec![project.join(package::MANIFEST), source.to_path_buf()];
+    let mut seen: HashSet<PathBuf> = files.iter().map(|file| canonical(file)).collect();
+    let mut next = 1;
+
+    /* Files are visited once, so circular imports don't keep the walk going */
+    while next < files.len() {
+        for import in imports(&files[next], &packages) {
+            if seen.insert(canonical(&import)) {
+                files.push(import);
+            }
+        }
+
+        next += 1;
+    }
+
+    files
+}
+
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| std::fs::metadata(file).and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+fn rebuild(exe: &Path, source: &str, extra: &[String], program_args: &[String]) {
+    println!("{} {}", "Building".bright_green(), source);
+
+    let built = Command::new(exe)
+        .arg("build")
+        .arg(source)
+        .args(extra)
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+
+    if !built {
+        println!("{}", "Build failed, waiting for changes...".bright_yellow());
+        return;
+    }
+
+    println!("{} {}", "Running".bright_green(), source);
+
+    /* Run whatever `build` wrote, which is main.o unless `-o` was passed on */
+    let output = match extra.iter().position(|arg| arg == "-o") {
+        Some(idx) => extra.get(idx + 1).map_or("main.o", String::as_str),
+        None => "main.o",
+    };
+
+    let _ = Command::new(exe)
+        .args(["run", output, "--"])
+        .args(program_args)
+        .status();
+
+    println!("{}", "Waiting for changes...".bright_yellow());
+}
+
+/// Watches `source`, rebuilding and rerunning it on every change. `extra` is passed on to `build`.
+pub fn watch(source: &str, extra: &[String], program_args: &[String]) -> ! {
+    let exe = std::env::current_exe().expect("Unable to locate the shoelace executable");
+    let mut last: Option<Vec<Option<SystemTime>>> = None;
+
+    loop {
+        let files = watched_files(Path::new(source));
+        let mut stamps = modified(&files);
+
+        if last.as_ref() != Some(&stamps) {
+            /* Wait for the files to settle before rebuilding */
+            loop {
+                std::thread::sleep(DEBOUNCE);
+                let settled = modified(&files);
+
+                if settled == stamps {
+                    break;
+                }
+
+                stamps = settled;
+            }
+
+            rebuild(&exe, source, extra, program_args);
+            last = Some(stamps);
+        }
+
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scratch::Scratch;
+
+    #[test]
+    fn imports_are_watched_recursively() {
+        let dir = Scratch::new("watch-recursive");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("main.lc"), "use \"lib/a.lc\" as a\n").unwrap();
+        std::fs::write(dir.join("lib/a.lc"), "use \"b.lc\" as b\n").unwrap();
+        std::fs::write(dir.join("lib/b.lc"), "use \"a.lc\" as a\n").unwrap();
+
+        let files = watched_files(&dir.join("main.lc"));
+
+        assert_eq!(
+            files,
+            vec![
+                dir.join(package::MANIFEST),
+                dir.join("main.lc"),
+                dir.join("lib/a.lc"),
+                dir.join("lib/b.lc"),
+            ]
+        );
+    }
+
+    #[test]
+    fn import_paths_are_read_like_the_parser_reads_them() {
+        let dir = Scratch::new("watch-literals");
+        let source = [
+            "use r\"raw.lc\" as raw",
+            "use 'it\\'s.lc' as escaped",
+            "use \"\"\"long.lc\"\"\" as long",
+        ];
+        std::fs::write(dir.join("main.lc"), source.join("\n")).unwrap();
+
+        let files = imports(&dir.join("main.lc"), &BTreeMap::new());
+
+        assert_eq!(files, vec![dir.join("raw.lc"), dir.join("it's.lc"), dir.join("long.lc")]);
+    }
+
+    #[test]
+    fn packages_are_watched_through_their_entry_point() {
+        let dir = Scratch::new("watch-packages");
+        std::fs::create_dir_all(dir.join("app")).unwrap();
+        std::fs::create_dir_all(dir.join("greet")).unwrap();
+        let manifest = "[dependencies]\ngreet = { path = \"../greet\" }\n";
+        std::fs::write(dir.join("app").join(package::MANIFEST), manifest).unwrap();
+        std::fs::write(dir.join("app/main.lc"), "use \"greet\" as greet\n").unwrap();
+        std::fs::write(dir.join("greet/main.lc"), "use \"words.lc\" as words\n").unwrap();
+
+        let files = watched_files(&dir.join("app/main.lc"));
+        let greet = dir.join("greet").canonicalize().unwrap();
+
+        assert_eq!(
+            files,
+            vec![
+                dir.join("app").join(package::MANIFEST),
+                dir.join("app/main.lc"),
+                greet.join(package::PACKAGE_MAIN),
+                greet.join("words.lc"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+//! Arguments after `--` reach the program through `args!`, exactly as the shell passed them.
+
+mod common;
+
+use common::{shoelace, stdout, Scratch};
+use std::process::Command;
+
+/// Prints every argument as `repr!` formats it, one per line.
+const PRINT_ARGUMENTS: &str = "\
+let mut i: number = 0
+
+while i < args!() {
+    print!(repr!(args!(i)))
+    i = i + 1
+}
+";
+
+const ARGUMENTS: &[&str] = &[
+    "two words",
+    "\"double quoted\"",
+    "'single quoted'",
+    "back\\slash",
+    "",
+    "--",
+    "-o",
+    "ünïcødé",
+    "日本語",
+    "🦀",
+];
+
+fn expected() -> String {
+    let lines = [
+        r#""two words""#,
+        r#""\"double quoted\"""#,
+        r#""'single quoted'""#,
+        r#""back\\slash""#,
+        r#""""#,
+        r#""--""#,
+        r#""-o""#,
+        r#""ünïcødé""#,
+        r#""日本語""#,
+        r#""🦀""#,
+    ];
+
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+fn with_arguments<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    let mut args = args.to_vec();
+    args.push("--");
+    args.extend(ARGUMENTS);
+    args
+}
+
+#[test]
+fn build_run_passes_arguments() {
+    let dir = Scratch::new();
+    dir.write("main.lc", PRINT_ARGUMENTS);
+
+    let output = shoelace(&dir, &with_arguments(&["build", "main.lc", "--run"]));
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), expected());
+}
+
+#[test]
+fn run_passes_arguments() {
+    let dir = Scratch::new();
+    dir.write("main.lc", PRINT_ARGUMENTS);
+    assert!(shoelace(&dir, &["build", "main.lc", "-o", "main.o"]).status.success());
+
+    let output = shoelace(&dir, &with_arguments(&["run", "main.o", "--deterministic"]));
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), expected());
+}
+
+#[test]
+fn executables_get_every_argument() {
+    let dir = Scratch::new();
+    dir.write("main.lc", PRINT_ARGUMENTS);
+
+    let build = ["build", "main.lc", "--emit", "exe", "-o", "program"];
+    assert!(shoelace(&dir, &build).status.success());
+
+    /* Executables have no options of their own, so there's no `--` to separate them */
+    let program = dir.join(if cfg!(windows) { "program.exe" } else { "program" });
+    let output = Command::new(program).args(ARGUMENTS).output().expect("Unable to run");
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), expected());
+}
+
+#[test]
+fn missing_arguments_are_empty() {
+    let dir = Scratch::new();
+    dir.write("main.lc", "print!(args!(), repr!(args!(5)))\n");
+
+    let output = shoelace(&dir, &["build", "main.lc", "--run", "--", "only"]);
+
+    assert_eq!(stdout(&output), "1 \"\"\n");
+}
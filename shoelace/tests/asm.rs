@@ -0,0 +1,65 @@
+//! `asm` blocks are checked by the bytecode verifier when they're built, so one that breaks
+//! the stack is reported at its line instead of failing once the program runs.
+
+mod common;
+
+use common::{run, stdout};
+
+#[test]
+fn asm_blocks_that_break_the_stack_are_rejected() {
+    let source = "\
+asm { add }
+print!(\"after\")
+";
+
+    let output = run(source, &[]);
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("Error[E0109]: line 1:"), "{}", stdout(&output));
+    assert!(!stdout(&output).contains("after"), "{}", stdout(&output));
+}
+
+#[test]
+fn asm_blocks_in_functions_are_checked() {
+    let source = "\
+fn f(): number {
+    let x: number = 2
+
+    asm {
+        pop
+    }
+
+    return x
+}
+
+print!(f())
+";
+
+    let output = run(source, &[]);
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("Error[E0109]: line 4:"), "{}", stdout(&output));
+}
+
+#[test]
+fn valid_asm_blocks_run() {
+    let source = "\
+fn f(): number {
+    asm {
+        push true
+        jump_if 4
+        push 1
+        pop
+    }
+
+    return 42
+}
+
+print!(f())
+";
+
+    let output = run(source, &[]);
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), "42\n");
+}
@@ -0,0 +1,92 @@
+//! Helpers for the integration tests, which run the shoelace binary the way a user would.
+
+#![allow(dead_code)]
+
+use std::{
+    ops::Deref,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// An empty directory for a single test to build in, removed when it's dropped.
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+    pub fn new() -> Scratch {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "shoelace-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("Unable to create a scratch directory");
+        Scratch(dir)
+    }
+
+    /// Writes `contents` to `name` in the directory.
+    pub fn write(&self, name: &str, contents: &str) {
+        std::fs::write(self.0.join(name), contents).expect("Unable to write a scratch file");
+    }
+}
+
+impl Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs shoelace with `args` in `dir`, without colors so the output can be compared.
+pub fn shoelace(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_shoelace"))
+        .current_dir(dir)
+        .arg("--color=never")
+        .args(args)
+        .output()
+        .expect("Unable to run shoelace")
+}
+
+/// Builds `source` and runs it, passing `arguments` to the program.
+pub fn run(source: &str, arguments: &[&str]) -> Output {
+    let dir = Scratch::new();
+    dir.write("main.lc", source);
+
+    let mut args = vec!["build", "main.lc", "--run", "--"];
+    args.extend(arguments);
+    shoelace(&dir, &args)
+}
+
+/// What the command printed to stdout, which is also where diagnostics go.
+pub fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// The 1-based line and column a diagnostic points at, and how many characters it underlines.
+pub fn pointed_at(diagnostic: &str) -> (usize, usize, usize) {
+    let lines: Vec<&str> = diagnostic.lines().collect();
+    let pointer = lines
+        .iter()
+        .position(|line| line.contains('^'))
+        .expect("The diagnostic should point at the source");
+
+    let (gutter, _) = lines[pointer - 1].split_once(" | ").unwrap();
+    let carets = &lines[pointer][lines[pointer].find('|').unwrap() + 2..];
+    let column = carets.find('^').unwrap();
+
+    (gutter.trim().parse().unwrap(), column + 1, carets.trim().len())
+}
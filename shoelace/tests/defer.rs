@@ -0,0 +1,81 @@
+//! `defer` blocks run when their function returns, and can't be used where they'd only run
+//! once for code that runs many times.
+
+mod common;
+
+use common::{run, stdout};
+
+#[test]
+fn deferred_blocks_run_when_the_function_returns() {
+    let source = "\
+fn f(n: number): number {
+    defer {
+        print!(\"first\")
+    }
+
+    if n > 1 {
+        defer {
+            print!(\"second\")
+        }
+
+        return n
+    }
+
+    print!(\"body\")
+    return 0
+}
+
+print!(f(1))
+print!(f(2))
+";
+
+    let output = run(source, &[]);
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), "body\nfirst\n0\nsecond\nfirst\n2\n");
+}
+
+#[test]
+fn defer_inside_a_loop_is_rejected() {
+    let source = "\
+fn f() {
+    let mut i: number = 0
+
+    while i < 3 {
+        defer {
+            print!(\"loop\", i)
+        }
+
+        i = i + 1
+    }
+}
+
+f()
+";
+
+    let output = run(source, &[]);
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("Error[E0112]"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("Can't defer inside a loop"), "{}", stdout(&output));
+}
+
+#[test]
+fn functions_declared_in_loops_can_defer() {
+    let source = "\
+let mut i: number = 0
+
+while i < 2 {
+    fn f() {
+        defer {
+            print!(\"done\")
+        }
+    }
+
+    f()
+    i = i + 1
+}
+";
+
+    assert_eq!(stdout(&run(source, &[])), "done\ndone\n");
+}
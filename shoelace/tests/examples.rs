@@ -0,0 +1,62 @@
+//! Builds and runs every program in the examples directory, comparing what it prints to the
+//! `.out` file next to it. Every example also has to be a target of `cargo run --example`.
+
+mod common;
+
+use common::{shoelace, stdout, Scratch};
+use std::path::Path;
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// The names of the examples, without the `.lc` extension.
+fn examples() -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(manifest_dir().join("../examples"))
+        .expect("Unable to read the examples directory")
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_stem()?.to_str()?.to_string();
+            (path.extension()? == "lc").then_some(name)
+        })
+        .collect();
+
+    names.sort();
+    names
+}
+
+#[test]
+fn examples_print_their_expected_output() {
+    let dir = manifest_dir().join("../examples");
+    let names = examples();
+    assert!(!names.is_empty());
+
+    for name in names {
+        let source = dir.join(format!("{}.lc", name));
+        let expected = std::fs::read_to_string(dir.join(format!("{}.out", name)))
+            .unwrap_or_else(|_| panic!("{}.lc has no {}.out with its expected output", name, name));
+
+        let scratch = Scratch::new();
+        let output = shoelace(
+            &scratch,
+            &["build", source.to_str().unwrap(), "-o", "main.o", "--run"],
+        );
+
+        assert!(output.status.success(), "{} failed:\n{}", name, stdout(&output));
+        assert_eq!(stdout(&output), expected, "{} printed something else", name);
+    }
+}
+
+#[test]
+fn examples_are_cargo_examples() {
+    let manifest = std::fs::read_to_string(manifest_dir().join("Cargo.toml"))
+        .expect("Unable to read Cargo.toml");
+
+    for name in examples() {
+        let target = format!(
+            "[[example]]\nname = \"{}\"\npath = \"../examples/{}.rs\"",
+            name, name
+        );
+        assert!(manifest.contains(&target), "{} isn't an [[example]] in Cargo.toml", name);
+    }
+}
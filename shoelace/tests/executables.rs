@@ -0,0 +1,28 @@
+//! Executables made with `--emit exe` find the program appended to them, and refuse to run one
+//! that isn't all there.
+
+mod common;
+
+use common::{shoelace, stdout, Scratch};
+use std::process::Command;
+
+#[test]
+fn damaged_lengths_are_reported() {
+    let dir = Scratch::new();
+    dir.write("main.lc", "print!(\"hi\")\n");
+    let exe = ["build", "main.lc", "--emit", "exe", "-o", "program"];
+    assert!(shoelace(&dir, &exe).status.success());
+
+    let program = dir.join(if cfg!(windows) { "program.exe" } else { "program" });
+    let mut contents = std::fs::read(&program).unwrap();
+
+    /* The length sits right before the 8 byte magic at the end */
+    let length = contents.len() - 16;
+    contents[length..length + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    std::fs::write(&program, contents).unwrap();
+
+    let output = Command::new(&program).output().expect("Unable to run");
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("is damaged"), "{}", stdout(&output));
+}
@@ -0,0 +1,40 @@
+//! Errors inside the placeholders of formatted strings point at the placeholder in the source.
+
+mod common;
+
+use common::{pointed_at, run, stdout};
+
+#[test]
+fn placeholder_errors_point_into_the_source() {
+    let cases = [
+        ("let n: number = 1\n\nprint!(`value {n +}`)\n", (3, 19, 1), "Unexpected token"),
+        ("let n: number = 1\nprint!(`{n} {n 2}`)\n", (2, 16, 1), "Expected '}' after"),
+        ("print!(`x {} y`)\n", (1, 11, 2), "Expected an expression inside"),
+        ("print!(`x {1`)\n", (1, 11, 1), "Expected '}' to close"),
+        ("print!(`x } y`)\n", (1, 11, 1), "Unmatched '}'"),
+        ("print!(`ünï {1 +}`)\n", (1, 17, 1), "Unexpected token"),
+    ];
+
+    for (source, position, message) in cases {
+        let output = run(source, &[]);
+        let diagnostic = stdout(&output);
+
+        assert!(!output.status.success(), "{}", source);
+        assert!(diagnostic.contains(message), "{}", diagnostic);
+        assert_eq!(pointed_at(&diagnostic), position, "{}", diagnostic);
+    }
+}
+
+#[test]
+fn diagnostics_show_the_whole_line() {
+    let output = run("let n: number = 1\nprint!(`value {n +}` + \"!\")\n", &[]);
+
+    assert!(stdout(&output).contains("2 | print!(`value {n +}` + \"!\")"), "{}", stdout(&output));
+}
+
+#[test]
+fn braces_inside_placeholders_dont_close_them() {
+    let source = "print!(`a {\"}\"} b {[1, 2][0]} {\"{\" + \"}\"} \\{c\\}`)\n";
+
+    assert_eq!(stdout(&run(source, &[])), "a } b 1 {} {c}\n");
+}
@@ -0,0 +1,51 @@
+//! `build --run` runs programs with the same limits as `run`, given by the same flags.
+
+mod common;
+
+use common::{shoelace, stdout, Scratch};
+
+/// Never stops on its own.
+const FOREVER: &str = "\
+while true {
+    print!(\"again\")
+}
+";
+
+#[test]
+fn build_run_and_run_stop_at_the_same_limits() {
+    let dir = Scratch::new();
+    dir.write("main.lc", FOREVER);
+    assert!(shoelace(&dir, &["build", "main.lc", "-o", "main.o"]).status.success());
+
+    let limits = ["--max-instructions", "1000", "--timeout", "5s", "--deterministic"];
+    let built = shoelace(&dir, &[&["build", "main.lc", "--run"][..], &limits].concat());
+    let ran = shoelace(&dir, &[&["run", "main.o"][..], &limits].concat());
+
+    for output in [&built, &ran] {
+        assert_eq!(output.status.code(), Some(124), "{}", stdout(output));
+        assert!(stdout(output).contains("[E0309]"), "{}", stdout(output));
+    }
+
+    assert_eq!(stdout(&built), stdout(&ran));
+}
+
+#[test]
+fn build_run_limits_output() {
+    let dir = Scratch::new();
+    dir.write("main.lc", FOREVER);
+
+    let output = shoelace(&dir, &["build", "main.lc", "--run", "--max-output", "1KB"]);
+
+    assert_eq!(output.status.code(), Some(124), "{}", stdout(&output));
+}
+
+#[test]
+fn limits_need_a_program_to_run() {
+    let dir = Scratch::new();
+    dir.write("main.lc", FOREVER);
+
+    let output = shoelace(&dir, &["build", "main.lc", "--timeout", "1s"]);
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("'--timeout' only applies"), "{}", stdout(&output));
+}
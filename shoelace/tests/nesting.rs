@@ -0,0 +1,73 @@
+//! The parser limits how deeply expressions and blocks nest, see `Parser::max_depth`.
+//! Inputs past the limit must fail with a diagnostic rather than overflow the stack.
+
+mod common;
+
+use common::{run, stdout};
+
+const DEPTH: usize = 300;
+
+fn assert_too_deep(source: &str) {
+    let output = run(source, &[]);
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("Error[E0104]"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("too deeply nested"), "{}", stdout(&output));
+}
+
+fn parens(depth: usize) -> String {
+    format!("print!({}1{})\n", "(".repeat(depth), ")".repeat(depth))
+}
+
+fn calls(depth: usize) -> String {
+    format!(
+        "fn f(x: number): number {{\n    return x\n}}\nprint!({}1{})\n",
+        "f(".repeat(depth),
+        ")".repeat(depth)
+    )
+}
+
+fn blocks(depth: usize) -> String {
+    format!("{}print!(1)\n{}", "if true {\n".repeat(depth), "}\n".repeat(depth))
+}
+
+#[test]
+fn deeply_nested_parens_are_rejected() {
+    assert_too_deep(&parens(DEPTH));
+}
+
+#[test]
+fn deeply_nested_calls_are_rejected() {
+    assert_too_deep(&calls(DEPTH));
+}
+
+#[test]
+fn deeply_nested_blocks_are_rejected() {
+    assert_too_deep(&blocks(DEPTH));
+}
+
+#[test]
+fn nesting_below_the_limit_is_accepted() {
+    for source in [parens(100), calls(100), blocks(100)] {
+        let output = run(&source, &[]);
+
+        assert!(output.status.success(), "{}", stdout(&output));
+        assert_eq!(stdout(&output), "1\n");
+    }
+}
+
+#[test]
+fn long_else_if_chains_arent_nested() {
+    let mut source = String::from("let n: number = 4999\n\nif n == 0 {\n    print!(0)\n");
+
+    for i in 1..5000 {
+        source.push_str(&format!("}} else if n == {} {{\n    print!({})\n", i, i));
+    }
+
+    source.push_str("}\n");
+
+    let output = run(&source, &[]);
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), "4999\n");
+}
@@ -0,0 +1,40 @@
+//! Number literals that the scanner can't read as a whole get a diagnostic saying why.
+
+mod common;
+
+use common::{pointed_at, run, stdout};
+
+#[test]
+fn trailing_dots_point_at_the_number() {
+    let cases = [
+        ("print!(1.)\n", (1, 8, 2), "write '1.0' or '1' instead of '1.'"),
+        ("let x: number = 2.\nprint!(x)\n", (1, 17, 2), "write '2.0' or '2' instead of '2.'"),
+        ("let s: string = \"ab\"\nprint!(s[0.])\n", (2, 10, 2), "instead of '0.'"),
+        ("print!(1.5.)\n", (1, 8, 4), "write '1.5' instead of '1.5.'"),
+        ("print!(12e3. + 2)\n", (1, 8, 5), "write '12e3' instead of '12e3.'"),
+    ];
+
+    for (source, position, suggestion) in cases {
+        let output = run(source, &[]);
+        let diagnostic = stdout(&output);
+
+        assert!(!output.status.success(), "{}", source);
+        assert!(diagnostic.contains("Error[E0102]: Numbers can't end with '.'"), "{}", diagnostic);
+        assert!(diagnostic.contains(suggestion), "{}", diagnostic);
+        assert_eq!(pointed_at(&diagnostic), position, "{}", diagnostic);
+    }
+}
+
+#[test]
+fn dots_after_other_values_expect_attributes() {
+    let output = run("print!((1).)\n", &[]);
+
+    assert!(stdout(&output).contains("Expected attribute name"), "{}", stdout(&output));
+}
+
+#[test]
+fn dots_still_start_ranges_and_fractions() {
+    let output = run("print!(\"abcdef\"[1..4], .5 + 1.5, 1e3)\n", &[]);
+
+    assert_eq!(stdout(&output), "bcd 2 1000\n");
+}
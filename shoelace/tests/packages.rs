@@ -0,0 +1,40 @@
+//! Dependencies added with `shoelace add` can be imported by name.
+
+mod common;
+
+use common::{shoelace, stdout, Scratch};
+
+#[test]
+fn path_dependencies_are_imported_by_name() {
+    let dir = Scratch::new();
+    for package in ["app", "util", "util/lib"] {
+        std::fs::create_dir(dir.join(package)).unwrap();
+    }
+
+    /* The package's own imports stay relative to its files */
+    dir.write("util/main.lc", "use \"lib/text.lc\" as text\n\npub fn greet(): string {\n    \
+                               return text.hello()\n}\n");
+    dir.write("util/lib/text.lc", "pub fn hello(): string {\n    return \"hello\"\n}\n");
+    dir.write("app/main.lc", "use \"util\" as util\n\nprint!(util.greet())\n");
+
+    let app = dir.join("app");
+    let add = shoelace(&app, &["add", "util", "--path", "../util"]);
+    assert!(add.status.success(), "{}", stdout(&add));
+
+    let output = shoelace(&app, &["build", "main.lc", "--run"]);
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), "hello\n");
+    assert!(app.join("lace.lock").is_file());
+}
+
+#[test]
+fn files_named_like_packages_need_a_dependency() {
+    let dir = Scratch::new();
+    dir.write("main.lc", "use \"util\" as util\n");
+
+    let output = shoelace(&dir, &["build", "main.lc"]);
+
+    assert!(!output.status.success());
+    assert!(stdout(&output).contains("Error[E0110]"), "{}", stdout(&output));
+}
@@ -0,0 +1,120 @@
+//! Runs the same programs through every way shoelace can build and run one, which must all
+//! print the same thing: `build --run`, `build` followed by `run`, `--opt-level s` and
+//! `--emit exe`. The programs cover each kind of statement the parser supports.
+
+mod common;
+
+use common::{shoelace, stdout, Scratch};
+use std::process::Command;
+
+/// Builds and runs `source` every way there is, checking each prints `expected`.
+fn assert_parity(source: &str, expected: &str) {
+    let dir = Scratch::new();
+    dir.write("main.lc", source);
+
+    let mut outputs = vec![(
+        "build --run",
+        shoelace(&dir, &["build", "main.lc", "-o", "run.o", "--run"]),
+    )];
+
+    assert!(shoelace(&dir, &["build", "main.lc", "-o", "main.o"]).status.success());
+    outputs.push(("build, then run", shoelace(&dir, &["run", "main.o", "--deterministic"])));
+
+    let small = ["build", "main.lc", "--opt-level", "s", "-o", "small.o"];
+    assert!(shoelace(&dir, &small).status.success());
+    outputs.push(("--opt-level s", shoelace(&dir, &["run", "small.o", "--deterministic"])));
+
+    let exe = ["build", "main.lc", "--emit", "exe", "-o", "program"];
+    assert!(shoelace(&dir, &exe).status.success());
+    let program = dir.join(if cfg!(windows) { "program.exe" } else { "program" });
+    outputs.push(("--emit exe", Command::new(program).output().expect("Unable to run")));
+
+    for (how, output) in outputs {
+        assert!(output.status.success(), "{} failed:\n{}", how, stdout(&output));
+        assert_eq!(stdout(&output), expected, "{} printed something else", how);
+    }
+}
+
+#[test]
+fn while_loops() {
+    let source = "\
+let mut i: number = 0
+
+while i < 3 {
+    print!(i)
+    i = i + 1
+}
+";
+
+    assert_parity(source, "0\n1\n2\n");
+}
+
+#[test]
+fn if_else_if_else_chains() {
+    let source = "\
+fn describe(n: number): string {
+    if n == 0 {
+        return \"zero\"
+    } else if n % 2 == 0 {
+        return \"even\"
+    } else {
+        return \"odd\"
+    }
+}
+
+print!(describe(0), describe(1), describe(2))
+
+if false {
+    print!(\"unreachable\")
+}
+";
+
+    assert_parity(source, "zero odd even\n");
+}
+
+#[test]
+fn pub_declarations() {
+    let source = "\
+pub let limit: number = 2
+
+pub fn square(n: number): number {
+    return n * n
+}
+
+print!(square(limit))
+";
+
+    assert_parity(source, "4\n");
+}
+
+#[test]
+fn typeof_and_negation() {
+    let source = "\
+let n: number = 5
+
+print!(typeof n, typeof \"s\", typeof [1], typeof none, typeof true)
+print!(-n, -(-n), typeof -n)
+";
+
+    assert_parity(source, "number string array none bool\n-5 5 number\n");
+}
+
+#[test]
+fn functions_and_structs() {
+    let source = "\
+struct Counter {
+    count: number
+}
+
+fn bump(counter: Counter): Counter {
+    counter.count = counter.count + 1
+    return counter
+}
+
+let start: Counter = Counter { count: 1 }
+let bumped: Counter = bump(bump(start))
+print!(start.count, bumped.count)
+";
+
+    assert_parity(source, "1 3\n");
+}
@@ -0,0 +1,45 @@
+//! Output paths are used as given, including spaces, dots in directories and non-ASCII names.
+
+mod common;
+
+use common::{shoelace, stdout, Scratch};
+use std::process::Command;
+
+const PROGRAM: &str = "print!(\"hi\")\n";
+
+#[test]
+fn objects_and_maps_keep_unicode_paths() {
+    let dir = Scratch::new();
+    dir.write("main.lc", PROGRAM);
+    std::fs::create_dir(dir.join("out dir.d")).unwrap();
+
+    let output = "out dir.d/プログラム.o";
+    assert!(shoelace(&dir, &["build", "main.lc", "-o", output]).status.success());
+
+    assert!(dir.join(output).is_file());
+    assert!(dir.join("out dir.d/プログラム.o.map").is_file());
+
+    let run = shoelace(&dir, &["run", output, "--deterministic"]);
+    assert_eq!(stdout(&run), "hi\n");
+
+    let disasm = shoelace(&dir, &["disasm", output]);
+    assert!(disasm.status.success(), "{}", stdout(&disasm));
+    assert!(stdout(&disasm).contains("print!(\"hi\")"), "{}", stdout(&disasm));
+}
+
+#[test]
+fn executables_keep_unicode_paths() {
+    let dir = Scratch::new();
+    dir.write("main.lc", PROGRAM);
+    std::fs::create_dir(dir.join("ビルド.d")).unwrap();
+
+    let build = ["build", "main.lc", "--emit", "exe", "-o", "ビルド.d/プログラム"];
+    assert!(shoelace(&dir, &build).status.success());
+
+    let name = if cfg!(windows) { "プログラム.exe" } else { "プログラム" };
+    let program = dir.join("ビルド.d").join(name);
+    assert!(dir.join("ビルド.d").join(format!("{}.map", name)).is_file());
+
+    let output = Command::new(program).output().expect("Unable to run");
+    assert_eq!(stdout(&output), "hi\n");
+}
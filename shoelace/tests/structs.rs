@@ -0,0 +1,57 @@
+//! Structs belong to the file declaring them, like functions, and their methods are
+//! compiled and checked like any other function.
+
+mod common;
+
+use common::{shoelace, stdout, Scratch};
+
+#[test]
+fn imported_structs_keep_to_their_file() {
+    let dir = Scratch::new();
+    dir.write(
+        "lib.lc",
+        "struct Point { x: number }\n\npub fn origin(): number {\n    \
+         return Point { x: 1 }.x\n}\n",
+    );
+    dir.write(
+        "main.lc",
+        "struct Point { y: number }\nuse \"lib.lc\" as lib\n\n\
+         print!(repr!(Point { y: 2 }))\nprint!(lib.origin())\n",
+    );
+
+    let output = shoelace(&dir, &["build", "main.lc", "--run"]);
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), "{y: 2}\n1\n");
+}
+
+#[test]
+fn methods_run_with_their_own_locals() {
+    let dir = Scratch::new();
+    dir.write(
+        "main.lc",
+        "\
+type Counter {
+    let count: number = 0
+
+    fn next(step: number): number {
+        let value: number = step * 2
+        return value + 1
+    }
+
+    fn label(step: number): string {
+        let value: string = \"step \" + step as string
+        return value
+    }
+}
+
+let counter: Counter = Counter { count: 1 }
+print!(counter.next(20), counter.label(2), counter.count)
+",
+    );
+
+    let output = shoelace(&dir, &["build", "main.lc", "--run"]);
+
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(stdout(&output), "41 step 2 1\n");
+}